@@ -0,0 +1,446 @@
+use crate::chats;
+use crate::config::get_config_path;
+use crate::db::{self, schema_version};
+use crate::paths;
+use crate::export;
+use crate::persona::personas_dir;
+use crate::tags;
+use crate::template::templates_dir;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// `format` field of backup manifests
+pub const FORMAT: &str = "yo-backup";
+/// Bumped when the archive layout changes in a way older yo can't restore
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const DB_FILE: &str = "chats.db";
+const CONFIG_FILE: &str = "config.toml";
+const CURRENT_CHAT_FILE: &str = "current_chat";
+
+/// `manifest.json`, the first entry of every backup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub format: String,
+    pub format_version: u32,
+    pub yo_version: String,
+    /// Schema version of the bundled chats.db
+    pub schema_version: i64,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`
+    pub created_at: String,
+    pub chats: i64,
+    pub messages: i64,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestFile {
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// Where the state that goes into a backup lives
+#[derive(Debug, Clone)]
+pub struct StatePaths {
+    pub db: PathBuf,
+    pub config: PathBuf,
+    pub templates: PathBuf,
+    pub personas: PathBuf,
+    pub current_chat: PathBuf,
+}
+
+impl StatePaths {
+    pub fn current() -> Self {
+        StatePaths {
+            db: paths::db_path(),
+            current_chat: paths::current_chat_path(),
+            config: get_config_path(),
+            templates: templates_dir(),
+            personas: personas_dir(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreMode {
+    /// Add the backup's chats, memories and files next to the existing ones
+    Merge,
+    /// Make yo's state exactly the backup's (API keys are kept)
+    Replace,
+}
+
+/// A scratch directory removed again when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// A new directory only the user can enter. Fails rather than reuse one that
+    /// already exists, which someone else could have created.
+    pub fn new(prefix: &str) -> std::io::Result<Self> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let dir = std::env::temp_dir().join(format!("{}-{}-{}", prefix, std::process::id(), nanos));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir)?;
+        Ok(TempDir(dir))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Whether a config key holds a credential, e.g. `openai_api_key`
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    key == "key" || key.ends_with("_key") || key == "token" || key.ends_with("_token") || key.contains("secret") || key.contains("password")
+}
+
+fn strip_table(table: &mut toml::Table) {
+    table.retain(|k, _| !is_secret(k));
+    for (_, value) in table.iter_mut() {
+        if let toml::Value::Table(t) = value {
+            strip_table(t);
+        }
+    }
+}
+
+/// config.toml without API keys, tokens and passwords
+pub fn strip_secrets(config: &str) -> Result<String, String> {
+    let mut table: toml::Table = config.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    strip_table(&mut table);
+    toml::to_string_pretty(&table).map_err(|e| e.to_string())
+}
+
+fn copy_secrets(from: &toml::Table, to: &mut toml::Table) {
+    for (k, v) in from {
+        match v {
+            toml::Value::Table(inner) => {
+                if let Some(toml::Value::Table(target)) = to.get_mut(k) {
+                    copy_secrets(inner, target);
+                }
+            }
+            _ if is_secret(k) => {
+                to.insert(k.clone(), v.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A restored config.toml with the secrets of the config it replaces put back
+pub fn restore_secrets(restored: &str, existing: Option<&str>) -> Result<String, String> {
+    let mut table: toml::Table = restored.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    if let Some(existing) = existing.and_then(|e| e.parse::<toml::Table>().ok()) {
+        copy_secrets(&existing, &mut table);
+    }
+    toml::to_string_pretty(&table).map_err(|e| e.to_string())
+}
+
+/// Files under `dir` as (archive path, file) pairs, sorted
+fn collect_files(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(e) => e.flatten().map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(n) => format!("{}/{}", prefix, n),
+            None => continue,
+        };
+        if path.is_dir() {
+            collect_files(&path, &name, out);
+        } else if path.is_file() {
+            out.push((name, path));
+        }
+    }
+}
+
+/// Write a zstd-compressed tar of the database (snapshotted while yo may be in
+/// use), config.toml without secrets, templates, personas and the current-chat pointer
+pub fn create(paths: &StatePaths, output: &Path) -> Result<Manifest, String> {
+    if !paths.db.exists() {
+        return Err(format!("no database at {}", paths.db.display()));
+    }
+    let scratch = TempDir::new("yo-backup").map_err(|e| e.to_string())?;
+    let snapshot = scratch.path().join(DB_FILE);
+    let conn = Connection::open(&paths.db).map_err(|e| e.to_string())?;
+    // VACUUM INTO reads inside one transaction, so the copy is consistent even mid-write
+    conn.execute("VACUUM INTO ?1", [snapshot.to_string_lossy()]).map_err(|e| format!("snapshot failed: {}", e))?;
+    let snap = Connection::open(&snapshot).map_err(|e| e.to_string())?;
+    let count = |table: &str| snap.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0));
+    let (chats, messages) = (count("chats").unwrap_or(0), count("messages").unwrap_or(0));
+    let schema = schema_version(&snap).map_err(|e| e.to_string())?;
+    let created_at: String = snap.query_row("SELECT datetime('now')", [], |row| row.get(0)).map_err(|e| e.to_string())?;
+    drop(snap);
+
+    let mut files = vec![(DB_FILE.to_string(), snapshot)];
+    if let Ok(config) = fs::read_to_string(&paths.config) {
+        let stripped = scratch.path().join(CONFIG_FILE);
+        let clean = strip_secrets(&config).map_err(|e| format!("{}: {}", paths.config.display(), e))?;
+        fs::write(&stripped, clean).map_err(|e| e.to_string())?;
+        files.push((CONFIG_FILE.to_string(), stripped));
+    }
+    if paths.current_chat.is_file() {
+        files.push((CURRENT_CHAT_FILE.to_string(), paths.current_chat.clone()));
+    }
+    collect_files(&paths.templates, "templates", &mut files);
+    collect_files(&paths.personas, "personas", &mut files);
+
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        format_version: FORMAT_VERSION,
+        yo_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: schema,
+        created_at,
+        chats,
+        messages,
+        files: files
+            .iter()
+            .map(|(name, path)| ManifestFile { path: name.clone(), bytes: fs::metadata(path).map(|m| m.len()).unwrap_or(0) })
+            .collect(),
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+
+    // write next to the target and rename, so a failed backup never leaves a truncated archive
+    let partial = output.with_extension("partial");
+    // left over from an interrupted backup; the new one is created readable only by the user
+    let _ = fs::remove_file(&partial);
+    let write = || -> std::io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let encoder = zstd::Encoder::new(options.open(&partial)?, 0)?;
+        let mut tar = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_json.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, MANIFEST, manifest_json.as_slice())?;
+        for (name, path) in &files {
+            tar.append_path_with_name(path, name)?;
+        }
+        tar.into_inner()?.finish()?.flush()
+    };
+    if let Err(e) = write().and_then(|_| fs::rename(&partial, output)) {
+        let _ = fs::remove_file(&partial);
+        return Err(e.to_string());
+    }
+    Ok(manifest)
+}
+
+/// Unpack a backup into `dest` and check it: a known format, every listed file
+/// present with its size and an intact database, which is then migrated to this
+/// yo's schema
+pub fn unpack(archive: &Path, dest: &Path) -> Result<Manifest, String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+    // tar refuses entries that would land outside `dest`
+    tar::Archive::new(decoder).unpack(dest).map_err(|e| format!("not a readable yo backup ({})", e))?;
+    let manifest: Manifest = fs::read_to_string(dest.join(MANIFEST))
+        .map_err(|_| "not a yo backup (no manifest.json)".to_string())
+        .and_then(|m| serde_json::from_str(&m).map_err(|e| format!("invalid manifest.json: {}", e)))?;
+    if manifest.format != FORMAT {
+        return Err(format!("not a yo backup (format \"{}\")", manifest.format));
+    }
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(format!(
+            "backup format v{} was written by a newer yo ({}); this one reads up to v{}",
+            manifest.format_version, manifest.yo_version, FORMAT_VERSION
+        ));
+    }
+    for file in &manifest.files {
+        let size = fs::metadata(dest.join(&file.path)).map(|m| m.len());
+        if size.as_ref().ok() != Some(&file.bytes) {
+            return Err(format!("backup is damaged: {} is missing or has the wrong size", file.path));
+        }
+    }
+    if !manifest.files.iter().any(|f| f.path == DB_FILE) {
+        return Err("backup has no chats.db".to_string());
+    }
+    let conn = Connection::open(dest.join(DB_FILE)).map_err(|e| e.to_string())?;
+    let check: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0)).map_err(|e| e.to_string())?;
+    if check != "ok" {
+        return Err(format!("backup database is damaged: {}", check));
+    }
+    db::init_schema(&conn).map_err(|e| e.to_string())?;
+    Ok(manifest)
+}
+
+/// What merging a backup's database added
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    pub chats: usize,
+    /// Chats left out because the same chat (title and creation time) is already there
+    pub skipped: usize,
+    pub messages: usize,
+    pub memories: usize,
+}
+
+/// Copy the chats, with all branches, settings and tags, and the remembered facts of
+/// `source` into `target` under new ids. Chats `target` already has are skipped and
+/// existing facts win, so merging the same backup twice adds nothing.
+pub fn merge_chats(target: &Connection, source: &Connection) -> rusqlite::Result<MergeStats> {
+    let tx = target.unchecked_transaction()?;
+    let mut stats = MergeStats::default();
+    let mut chat_ids = HashMap::new();
+    let mut message_ids = HashMap::new();
+    for chat in export::load_all(source)? {
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM chats WHERE yo_open(title) IS ?1 AND created_at IS ?2)",
+            params![chat.title, chat.created_at],
+            |row| row.get(0),
+        )?;
+        if exists {
+            stats.skipped += 1;
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO chats (title, created_at, system_prompt, persona) VALUES (yo_seal(?1), COALESCE(?2, CURRENT_TIMESTAMP), yo_seal(?3), ?4)",
+            params![chat.title, chat.created_at, chat.system_prompt, chat.persona],
+        )?;
+        let chat_id = tx.last_insert_rowid();
+        chat_ids.insert(chat.id, chat_id);
+        if let (Some(backend), Some(model)) = (&chat.source, &chat.model) {
+            let settings = crate::config::ModelSettings {
+                source: backend.clone(),
+                model: model.clone(),
+                temperature: chat.temperature,
+                max_tokens: chat.max_tokens,
+            };
+            chats::set_settings(&tx, chat_id, &settings)?;
+        }
+        // messages come oldest first, so parents are always copied before their replies
+        for m in &chat.messages {
+            tx.execute(
+                r#"
+                INSERT INTO messages (chat_id, parent_id, role, content, created_at, hidden, pinned, model)
+                VALUES (?1, ?2, ?3, yo_seal(?4), ?5, ?6, ?7, ?8)
+                "#,
+                params![
+                    chat_id,
+                    m.parent_id.and_then(|p| message_ids.get(&p)),
+                    m.role,
+                    m.content,
+                    m.created_at,
+                    m.hidden,
+                    m.pinned,
+                    m.model
+                ],
+            )?;
+            message_ids.insert(m.id, tx.last_insert_rowid());
+        }
+        tx.execute(
+            "UPDATE chats SET head_message_id = ?1 WHERE id = ?2",
+            params![chat.head_message_id.and_then(|h| message_ids.get(&h)), chat_id],
+        )?;
+        tags::add_tags(&tx, chat_id, &chat.tags)?;
+        stats.chats += 1;
+        stats.messages += chat.messages.len();
+    }
+
+    // keep import records, so importing the same history later doesn't duplicate it
+    let mut stmt = source.prepare("SELECT source, external_id, chat_id, last_external_message, last_message_id FROM imports")?;
+    let imports = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, Option<String>>(3)?, row.get::<_, Option<i64>>(4)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (src, external_id, chat_id, last_external, last_id) in imports {
+        if let Some(new_chat) = chat_ids.get(&chat_id) {
+            tx.execute(
+                "INSERT OR IGNORE INTO imports (source, external_id, chat_id, last_external_message, last_message_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![src, external_id, new_chat, last_external, last_id.and_then(|id| message_ids.get(&id))],
+            )?;
+        }
+    }
+
+    let mut stmt = source.prepare("SELECT key, yo_open(value) FROM user_profile")?;
+    let facts = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (key, value) in facts {
+        stats.memories += tx.execute("INSERT OR IGNORE INTO user_profile (key, value) VALUES (?1, yo_seal(?2))", params![key, value])?;
+    }
+    tx.commit()?;
+    Ok(stats)
+}
+
+/// Copy the files under `from` into `to`. With `overwrite` off, files that already
+/// exist are left alone. Returns (copied, skipped).
+pub fn copy_tree(from: &Path, to: &Path, overwrite: bool) -> std::io::Result<(usize, usize)> {
+    let mut files = Vec::new();
+    collect_files(from, "", &mut files);
+    let (mut copied, mut skipped) = (0, 0);
+    for (name, path) in files {
+        let target = to.join(name.trim_start_matches('/'));
+        if target.exists() && !overwrite {
+            skipped += 1;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&path, &target)?;
+        copied += 1;
+    }
+    Ok((copied, skipped))
+}
+
+/// Put an unpacked backup in place of the current state. API keys in config.toml are
+/// carried over. Returns where the database it replaced was saved, if there was one.
+pub fn replace(paths: &StatePaths, unpacked: &Path) -> Result<Option<PathBuf>, String> {
+    let io = |e: std::io::Error| e.to_string();
+    let mut previous = None;
+    if paths.db.exists() {
+        let backup = paths.db.with_file_name("chats.db.pre-restore.bak");
+        let conn = Connection::open(&paths.db).map_err(|e| e.to_string())?;
+        let _ = fs::remove_file(&backup);
+        conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()]).map_err(|e| format!("could not save the current database: {}", e))?;
+        previous = Some(backup);
+    }
+    if let Some(dir) = paths.db.parent() {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", paths.db.display(), suffix));
+    }
+    fs::copy(unpacked.join(DB_FILE), &paths.db).map_err(io)?;
+
+    if let Ok(restored) = fs::read_to_string(unpacked.join(CONFIG_FILE)) {
+        let existing = fs::read_to_string(&paths.config).ok();
+        let config = restore_secrets(&restored, existing.as_deref())?;
+        fs::write(&paths.config, config).map_err(io)?;
+    }
+    for (dir, name) in [(&paths.templates, "templates"), (&paths.personas, "personas")] {
+        let _ = fs::remove_dir_all(dir);
+        copy_tree(&unpacked.join(name), dir, true).map_err(io)?;
+    }
+    match fs::read(unpacked.join(CURRENT_CHAT_FILE)) {
+        Ok(current) => fs::write(&paths.current_chat, current).map_err(io)?,
+        Err(_) => { let _ = fs::remove_file(&paths.current_chat); }
+    }
+    Ok(previous)
+}
+
+/// Whether there is anything a restore could overwrite
+pub fn has_state(paths: &StatePaths) -> bool {
+    let chats = Connection::open_with_flags(&paths.db, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|c| c.query_row("SELECT COUNT(*) FROM chats", [], |row| row.get::<_, i64>(0)))
+        .unwrap_or(0);
+    chats > 0 || paths.config.exists()
+}
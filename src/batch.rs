@@ -0,0 +1,178 @@
+use crate::template;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// One prompt of a batch file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchItem {
+    /// The `id` given in the file, or the line/record number
+    pub id: String,
+    /// 1-based line (JSONL) or record (CSV) number
+    pub line: usize,
+    pub prompt: String,
+    pub model: Option<String>,
+    pub system: Option<String>,
+    pub vars: HashMap<String, String>,
+}
+
+impl BatchItem {
+    /// The prompt with its variables filled in. Prompts without variables are sent as is.
+    pub fn render(&self) -> Result<String, String> {
+        if self.vars.is_empty() {
+            return Ok(self.prompt.clone());
+        }
+        template::render(&self.prompt, &self.vars, |p| std::fs::read_to_string(p))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+/// One line of the results file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub id: String,
+    pub line: usize,
+    pub model: String,
+    pub response: Option<String>,
+    pub usage: Option<Usage>,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+fn value_to_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.filter(|s| !s.trim().is_empty())
+}
+
+/// Parse a JSONL batch file: one object per line with `prompt` and optionally
+/// `id`, `model`, `system` and `vars`. Blank lines are skipped.
+pub fn parse_jsonl(text: &str) -> Result<Vec<BatchItem>, String> {
+    let mut items = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let obj: Value = serde_json::from_str(raw).map_err(|e| format!("line {}: {}", line, e))?;
+        let prompt = obj["prompt"]
+            .as_str()
+            .ok_or_else(|| format!("line {}: missing \"prompt\"", line))?
+            .to_string();
+        let vars = match &obj["vars"] {
+            Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), value_to_string(v))).collect(),
+            Value::Null => HashMap::new(),
+            _ => return Err(format!("line {}: \"vars\" must be an object", line)),
+        };
+        items.push(BatchItem {
+            id: obj.get("id").filter(|v| !v.is_null()).map(value_to_string).unwrap_or_else(|| line.to_string()),
+            line,
+            prompt,
+            model: non_empty(obj["model"].as_str().map(String::from)),
+            system: non_empty(obj.get("system").or(obj.get("system_prompt")).and_then(Value::as_str).map(String::from)),
+            vars,
+        });
+    }
+    Ok(items)
+}
+
+/// Parse a CSV batch file with a header row. `prompt` is required; `id`, `model`
+/// and `system` are optional and every other column becomes a variable.
+pub fn parse_csv(text: &str) -> Result<Vec<BatchItem>, String> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    if !headers.iter().any(|h| h == "prompt") {
+        return Err("missing \"prompt\" column".to_string());
+    }
+    let mut items = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let line = i + 1;
+        let record = record.map_err(|e| format!("record {}: {}", line, e))?;
+        let mut item = BatchItem { id: line.to_string(), line, ..Default::default() };
+        for (header, value) in headers.iter().zip(record.iter()) {
+            match header.as_str() {
+                "prompt" => item.prompt = value.to_string(),
+                "id" if !value.trim().is_empty() => item.id = value.trim().to_string(),
+                "id" => {}
+                "model" => item.model = non_empty(Some(value.to_string())),
+                "system" | "system_prompt" => item.system = non_empty(Some(value.to_string())),
+                _ => { item.vars.insert(header.clone(), value.to_string()); }
+            }
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// Parse a batch file, as CSV if it ends in `.csv` and as JSONL otherwise
+pub fn parse_file(path: &Path, text: &str) -> Result<Vec<BatchItem>, String> {
+    let is_csv = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    if is_csv { parse_csv(text) } else { parse_jsonl(text) }
+}
+
+/// Default results file: `prompts.jsonl` -> `prompts.results.jsonl`
+pub fn default_output(input: &Path) -> PathBuf {
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("batch");
+    input.with_file_name(format!("{}.results.jsonl", stem))
+}
+
+/// IDs that already have an answer in a results file. Failed and malformed lines
+/// (e.g. one cut off by an interruption) don't count, so they are retried.
+pub fn answered_ids(results: &str) -> HashSet<String> {
+    results
+        .lines()
+        .filter_map(|l| serde_json::from_str::<BatchResult>(l).ok())
+        .filter(|r| r.error.is_none() && r.response.is_some())
+        .map(|r| r.id)
+        .collect()
+}
+
+/// Spaces out request starts to at most `per_minute` per minute
+pub struct RateLimiter {
+    period: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: Option<u32>) -> Self {
+        RateLimiter {
+            period: per_minute.filter(|n| *n > 0).map(|n| Duration::from_secs(60) / n),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free slot
+    pub async fn wait(&self) {
+        let period = match self.period {
+            Some(p) => p,
+            None => return,
+        };
+        let start = {
+            let mut next = self.next.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + period;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
@@ -0,0 +1,192 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use crate::store::Message;
+use std::collections::HashMap;
+
+fn message_from_row(row: &rusqlite::Row) -> Result<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        parent_id: row.get(1)?,
+        role: row.get(2)?,
+        content: row.get(3)?,
+        created_at: row.get(4)?,
+        hidden: row.get(5)?,
+        pinned: row.get(6)?,
+    })
+}
+
+/// The message new turns are appended to: the chat's stored head,
+/// or its newest message if no head was recorded yet.
+pub fn active_head(conn: &Connection, chat_id: i64) -> Result<Option<i64>> {
+    let head: Option<i64> = conn
+        .query_row(
+            "SELECT c.head_message_id FROM chats c JOIN messages m ON m.id = c.head_message_id AND m.chat_id = c.id WHERE c.id = ?1",
+            [chat_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    if head.is_some() {
+        return Ok(head);
+    }
+    conn.query_row("SELECT MAX(id) FROM messages WHERE chat_id = ?1", [chat_id], |row| row.get(0))
+}
+
+pub fn set_head(conn: &Connection, chat_id: i64, message_id: Option<i64>) -> Result<()> {
+    conn.execute("UPDATE chats SET head_message_id = ?1 WHERE id = ?2", params![message_id, chat_id])?;
+    Ok(())
+}
+
+/// Insert a message as a child of `parent_id` and make it the chat's head
+pub fn append(conn: &Connection, chat_id: i64, parent_id: Option<i64>, role: &str, content: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO messages (chat_id, parent_id, role, content) VALUES (?1, ?2, ?3, yo_seal(?4))",
+        params![chat_id, parent_id, role, content],
+    )?;
+    let id = conn.last_insert_rowid();
+    set_head(conn, chat_id, Some(id))?;
+    Ok(id)
+}
+
+/// Messages from the root of the chat down to `head`, oldest first
+pub fn path_to(conn: &Connection, head: i64) -> Result<Vec<Message>> {
+    let mut stmt = conn.prepare(
+        r#"
+        WITH RECURSIVE path(id) AS (
+            SELECT ?1
+            UNION ALL
+            SELECT m.parent_id FROM messages m JOIN path p ON m.id = p.id WHERE m.parent_id IS NOT NULL
+        )
+        SELECT m.id, m.parent_id, m.role, yo_open(m.content), m.created_at, m.hidden, m.pinned
+        FROM messages m JOIN path p ON p.id = m.id
+        ORDER BY m.id ASC
+        "#,
+    )?;
+    let rows = stmt.query_map([head], message_from_row)?;
+    rows.collect()
+}
+
+/// Messages on the chat's active branch, oldest first
+pub fn active_path(conn: &Connection, chat_id: i64) -> Result<Vec<Message>> {
+    match active_head(conn, chat_id)? {
+        Some(head) => path_to(conn, head),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Messages without children, i.e. the tips of every branch, oldest first
+pub fn leaves(conn: &Connection, chat_id: i64) -> Result<Vec<Message>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT m.id, m.parent_id, m.role, yo_open(m.content), m.created_at, m.hidden, m.pinned FROM messages m
+        WHERE m.chat_id = ?1 AND NOT EXISTS (SELECT 1 FROM messages c WHERE c.parent_id = m.id)
+        ORDER BY m.id ASC
+        "#,
+    )?;
+    let rows = stmt.query_map([chat_id], message_from_row)?;
+    rows.collect()
+}
+
+/// Direct replies to `parent_id` (top-level messages when None), oldest first
+pub fn children(conn: &Connection, chat_id: i64, parent_id: Option<i64>) -> Result<Vec<Message>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, parent_id, role, yo_open(content), created_at, hidden, pinned FROM messages WHERE chat_id = ?1 AND parent_id IS ?2 ORDER BY id ASC",
+    )?;
+    let rows = stmt.query_map(params![chat_id, parent_id], message_from_row)?;
+    rows.collect()
+}
+
+/// The newest branch tip below (or at) `message_id`
+pub fn latest_leaf_under(conn: &Connection, message_id: i64) -> Result<i64> {
+    conn.query_row(
+        r#"
+        WITH RECURSIVE sub(id) AS (
+            SELECT ?1
+            UNION ALL
+            SELECT m.id FROM messages m JOIN sub s ON m.parent_id = s.id
+        )
+        SELECT MAX(s.id) FROM sub s
+        WHERE NOT EXISTS (SELECT 1 FROM messages c WHERE c.parent_id = s.id)
+        "#,
+        [message_id],
+        |row| row.get(0),
+    )
+}
+
+/// Number of children for every message in a chat that has more than one,
+/// i.e. the points where branches diverge
+pub fn fork_points(conn: &Connection, chat_id: i64) -> Result<HashMap<i64, i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT parent_id, COUNT(*) FROM messages WHERE chat_id = ?1 AND parent_id IS NOT NULL GROUP BY parent_id HAVING COUNT(*) > 1",
+    )?;
+    let rows = stmt.query_map([chat_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Chat a message belongs to
+pub fn chat_of(conn: &Connection, message_id: i64) -> Result<Option<i64>> {
+    conn.query_row("SELECT chat_id FROM messages WHERE id = ?1", [message_id], |row| row.get(0))
+        .optional()
+}
+
+/// Pick the messages sent to the model: the branch without hidden messages,
+/// trimmed to the newest `limit` ones. Pinned messages on the branch are kept
+/// however old they are; pins on other branches belong to another conversation.
+pub fn select_context(path: Vec<Message>, limit: Option<usize>) -> Vec<Message> {
+    let visible: Vec<Message> = path.into_iter().filter(|t| !t.hidden).collect();
+    let skip = match limit {
+        Some(n) => visible.len().saturating_sub(n.max(1)),
+        None => 0,
+    };
+    visible
+        .into_iter()
+        .enumerate()
+        .filter(|(i, t)| *i >= skip || t.pinned)
+        .map(|(_, t)| t)
+        .collect()
+}
+
+/// The context for answering user message `question_id` and the question itself,
+/// which is sent as the new input rather than as history. None if it's missing or hidden.
+pub fn question_context(conn: &Connection, question_id: i64, limit: Option<usize>) -> Result<Option<(Vec<Message>, String)>> {
+    let mut context = select_context(path_to(conn, question_id)?, limit);
+    let Some(i) = context.iter().position(|t| t.id == question_id) else {
+        return Ok(None);
+    };
+    let question = context.remove(i);
+    Ok(Some((context, question.content)))
+}
+
+/// Remove a message, attaching its replies to its parent so the branch stays connected
+pub fn delete_message(conn: &Connection, message_id: i64) -> Result<bool> {
+    let (chat_id, parent_id): (i64, Option<i64>) = match conn
+        .query_row("SELECT chat_id, parent_id FROM messages WHERE id = ?1", [message_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?
+    {
+        Some(r) => r,
+        None => return Ok(false),
+    };
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("UPDATE messages SET parent_id = ?1 WHERE parent_id = ?2", params![parent_id, message_id])?;
+    tx.execute("DELETE FROM messages WHERE id = ?1", [message_id])?;
+    tx.execute(
+        "UPDATE chats SET head_message_id = ?1 WHERE id = ?2 AND head_message_id = ?3",
+        params![parent_id, chat_id, message_id],
+    )?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Set the hidden/pinned flags of a message. Pinning unhides and hiding unpins.
+pub fn set_flags(conn: &Connection, message_id: i64, hidden: bool, pinned: bool) -> Result<bool> {
+    let n = conn.execute(
+        "UPDATE messages SET hidden = ?1, pinned = ?2 WHERE id = ?3",
+        params![hidden, pinned, message_id],
+    )?;
+    Ok(n > 0)
+}
+
+/// Show a hidden message again. A pin is left as it is.
+pub fn unhide(conn: &Connection, message_id: i64) -> Result<bool> {
+    let n = conn.execute("UPDATE messages SET hidden = 0 WHERE id = ?1", [message_id])?;
+    Ok(n > 0)
+}
@@ -0,0 +1,71 @@
+use crate::config::ModelSettings;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+/// Create a chat that remembers the given model settings. Returns its id.
+/// Without settings the chat picks up the global ones on its first ask.
+pub fn create(conn: &Connection, title: &str, settings: Option<&ModelSettings>) -> Result<i64> {
+    conn.execute("INSERT INTO chats (title) VALUES (yo_seal(?1))", [title])?;
+    let chat_id = conn.last_insert_rowid();
+    if let Some(settings) = settings {
+        set_settings(conn, chat_id, settings)?;
+    }
+    Ok(chat_id)
+}
+
+/// Delete a chat. Its messages, tags, project bindings and import records go with it
+/// (ON DELETE CASCADE). Returns how many chats were deleted, 0 or 1.
+pub fn delete(conn: &Connection, chat_id: i64) -> Result<usize> {
+    conn.execute("DELETE FROM chats WHERE id = ?1", [chat_id])
+}
+
+pub fn exists(conn: &Connection, chat_id: i64) -> Result<bool> {
+    conn.query_row("SELECT 1 FROM chats WHERE id = ?1", [chat_id], |_| Ok(()))
+        .optional()
+        .map(|r| r.is_some())
+}
+
+/// Settings stored on a chat; None for chats created before settings were remembered
+pub fn settings(conn: &Connection, chat_id: i64) -> Result<Option<ModelSettings>> {
+    conn.query_row(
+        "SELECT source, model, temperature, max_tokens FROM chats WHERE id = ?1 AND source IS NOT NULL AND model IS NOT NULL",
+        [chat_id],
+        |row| {
+            Ok(ModelSettings {
+                source: row.get(0)?,
+                model: row.get(1)?,
+                temperature: row.get(2)?,
+                max_tokens: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+pub fn set_settings(conn: &Connection, chat_id: i64, settings: &ModelSettings) -> Result<bool> {
+    let n = conn.execute(
+        "UPDATE chats SET source = ?1, model = ?2, temperature = ?3, max_tokens = ?4 WHERE id = ?5",
+        params![settings.source, settings.model, settings.temperature, settings.max_tokens, chat_id],
+    )?;
+    Ok(n > 0)
+}
+
+/// The chat's own system prompt, if it has one (e.g. from a persona)
+pub fn system_prompt(conn: &Connection, chat_id: i64) -> Result<Option<String>> {
+    conn.query_row("SELECT yo_open(system_prompt) FROM chats WHERE id = ?1", [chat_id], |row| row.get(0))
+        .optional()
+        .map(Option::flatten)
+}
+
+pub fn set_system_prompt(conn: &Connection, chat_id: i64, system_prompt: Option<&str>) -> Result<bool> {
+    let n = conn.execute("UPDATE chats SET system_prompt = yo_seal(?1) WHERE id = ?2", params![system_prompt, chat_id])?;
+    Ok(n > 0)
+}
+
+/// Record the persona a chat was started with, together with its system prompt
+pub fn set_persona(conn: &Connection, chat_id: i64, name: &str, system_prompt: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE chats SET persona = ?1, system_prompt = yo_seal(?2) WHERE id = ?3",
+        params![name, system_prompt, chat_id],
+    )?;
+    Ok(())
+}
@@ -3,6 +3,12 @@ use clap::{Parser, Subcommand};
 #[derive(Parser)]
 #[command(name = "yo", about = "ask your terminal anything", version)]
 pub struct Cli {
+    #[arg(long = "as", global = true, value_name = "PERSONA", help = "Answer with a persona's system prompt and model, e.g. `yo --as reviewer <question>`")]
+    pub persona: Option<String>,
+
+    #[arg(long, global = true, value_name = "DIR", help = "Keep config and chat data in DIR for this run (same as YO_HOME=DIR)")]
+    pub data_dir: Option<std::path::PathBuf>,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -10,9 +16,11 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     #[command(about = "Ask your AI a question", 
-        long_about = "Ask the currently configured AI model a question. The response will stream in real-time if using OpenAI.\n\nYou can use this command as either:\n  yo ask <question>\n  yo <question>\n\nBoth forms are equivalent.", 
+        long_about = "Ask the currently configured AI model a question. The response will stream in real-time if using OpenAI.\n\nYou can use this command as either:\n  yo ask <question>\n  yo <question>\n\nBoth forms are equivalent. If no chat is selected yet, a new one is started automatically.\n\nUse `yo ask --incognito <question>` for one-off or sensitive questions that should not be stored.", 
         visible_alias = "a")]
     Ask {
+        #[arg(long, visible_alias = "incognito", help = "Don't read or write chat history; nothing is saved to chats.db")]
+        no_history: bool,
         #[arg(required = true, help = "The question or prompt to send to the AI")]
         question: Vec<String>,
     },
@@ -40,8 +48,14 @@ pub enum Command {
     #[command(about = "List available AI models", long_about = "Show a list of all available models from both Ollama and OpenAI backends.")]
     List,
     
-    #[command(about = "Show current AI model in use", long_about = "Display information about the currently selected AI backend and model.")]
+    #[command(about = "Show current AI model in use", long_about = "Display information about the currently selected AI backend and model, and the current chat together with the scope it comes from: $YO_CHAT, the project, this terminal session or the global pointer.")]
     Current,
+
+    #[command(about = "Print a shell hook for per-terminal chats", long_about = "Print shell code that gives every terminal its own current chat. Add it to your shell's startup file:\n\n  eval \"$(yo shell-init bash)\"      # ~/.bashrc\n  eval \"$(yo shell-init zsh)\"       # ~/.zshrc\n  yo shell-init fish | source        # ~/.config/fish/config.fish\n\nNew terminals start from the chat used most recently anywhere. To pin a shell to one chat instead, set YO_CHAT=<id>.")]
+    ShellInit {
+        #[arg(help = "Shell to print the hook for: bash, zsh or fish")]
+        shell: String,
+    },
     
     #[command(about = "Clear the conversation history", long_about = "Clear the conversation history stored in history.txt.")]
     ClearHistory,
@@ -53,15 +67,22 @@ pub enum Command {
     NewChat {
         #[arg(help = "Optional title for the new chat")]
         title: Option<String>,
+        #[arg(long, help = "Start the chat with a persona's system prompt, model and parameters")]
+        persona: Option<String>,
     },
 
-    #[command(about = "List all chat sessions", long_about = "List all chat sessions with their IDs and titles.")]
-    ListChats,
+    #[command(about = "List all chat sessions", long_about = "List all chat sessions with their IDs, titles and tags.")]
+    ListChats {
+        #[arg(long = "tag", help = "Only list chats with this tag (repeat to require several)")]
+        tags: Vec<String>,
+    },
 
-    #[command(about = "Switch to a chat session", long_about = "Switch to a specific chat session by its ID.")]
+    #[command(about = "Switch to a chat session", long_about = "Switch to a specific chat session by its ID. The chat continues with the backend, model and parameters it was using; pass --use-global to move it to your global model instead.")]
     SwitchChat {
         #[arg(help = "ID of the chat to switch to")]
         chat_id: i64,
+        #[arg(long, help = "Continue the chat with the global model from config.toml")]
+        use_global: bool,
     },
 
     #[command(about = "Set a user profile key-value pair", long_about = "Set a key-value pair in the user profile (global memory). Format: key=value")]
@@ -76,22 +97,378 @@ pub enum Command {
         chat_id: i64,
     },
 
-    #[command(about = "Search all chats for a keyword", long_about = "Search all chat messages for a given keyword.")]
+    #[command(about = "Search all chats", long_about = "Full-text search over every message, best matches first, with highlighted snippets.\n\nQueries support \"exact phrases\", AND / OR / NOT, prefix* matches and NEAR(a b). Words are matched by stem, so 'running' also finds 'run'.")]
     Search {
-        #[arg(help = "Keyword to search for")]
+        #[arg(help = "Words, \"phrases\" or a boolean query (or a description with --semantic)")]
         query: String,
+        #[arg(long = "tag", help = "Only search chats with this tag (repeat to require several)")]
+        tags: Vec<String>,
+        #[arg(long, help = "Only messages from this role: user, assistant or system")]
+        role: Option<String>,
+        #[arg(long = "chat", value_name = "CHAT_ID", help = "Only search this chat")]
+        chat_id: Option<i64>,
+        #[arg(long, value_name = "YYYY-MM-DD", help = "Only messages from this day on")]
+        since: Option<String>,
+        #[arg(long, value_name = "YYYY-MM-DD", help = "Only messages up to and including this day")]
+        until: Option<String>,
+        #[arg(short = 'n', long, default_value_t = 20, help = "Maximum number of results")]
+        limit: usize,
+        #[arg(long, help = "Find messages by meaning using embeddings instead of keywords")]
+        semantic: bool,
+        #[arg(long, help = "Print results as JSON")]
+        json: bool,
+    },
+
+    #[command(about = "Export chats to Markdown, JSON or HTML", long_about = "Export a chat (the current one by default) or all chats with their metadata, system prompt and messages.\n\nMarkdown and HTML show the active branch; HTML is a single self-contained page for sharing. JSON contains every message on every branch and follows the versioned schema described under \"Export format\" in the README.")]
+    Export {
+        #[arg(help = "ID of the chat (defaults to the current chat)", conflicts_with = "all")]
+        chat_id: Option<i64>,
+        #[arg(long, help = "Export every chat")]
+        all: bool,
+        #[arg(short, long, default_value = "md", help = "md, json or html")]
+        format: String,
+        #[arg(short, long, value_name = "PATH", help = "File to write (default: stdout)")]
+        output: Option<std::path::PathBuf>,
+    },
+
+    #[command(about = "Import chats from other tools", long_about = "Import chat history from other tools. Importing the same data again doesn't duplicate anything: chats imported before only get their new messages.")]
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+
+    #[command(about = "Back up all yo state to one archive", long_about = "Write a zstd-compressed tar archive with the chat database (a consistent snapshot, safe while yo is running), config.toml without API keys, templates, personas and the current chat, plus a manifest with version info.\n\nRestore it with `yo restore`.")]
+    Backup {
+        #[arg(short, long, value_name = "PATH", default_value = "yo-backup.tar.zst", help = "Archive to write")]
+        output: std::path::PathBuf,
+    },
+
+    #[command(about = "Restore a backup made with `yo backup`", long_about = "Check a backup archive, migrate its database to this yo's schema and restore it.\n\nWith --merge the backup's chats, memories, templates and personas are added to the existing ones; chats already present are skipped. With --replace yo's state becomes the backup's, keeping your API keys and the previous database as chats.db.pre-restore.bak. Without either, yo asks when there is existing state.")]
+    Restore {
+        #[arg(help = "Archive written by `yo backup`")]
+        archive: std::path::PathBuf,
+        #[arg(long, conflicts_with = "replace", help = "Add the backup to the existing chats")]
+        merge: bool,
+        #[arg(long, help = "Replace the existing chats and settings")]
+        replace: bool,
+    },
+
+    #[command(about = "Encrypt or decrypt the chat database", long_about = "Encrypt messages, chat titles, system prompts and remembered facts in chats.db, or decrypt them again. Tags, fact names, model settings and search embeddings stay readable, and embeddings say something about what a message is about.\n\nyo asks for the passphrase when it needs it. To avoid the prompt, set YO_PASSPHRASE or `passphrase_command` in config.toml, e.g. passphrase_command = \"pass show yo\". Search and export keep working while the passphrase is available.")]
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+
+    #[command(about = "Apply the retention policy and compact the database", long_about = "Delete what the [retention] table in config.toml says to, remove rows left behind by deleted chats and messages, then VACUUM chats.db and report the space reclaimed.\n\n  [retention]\n  max_age_days = 90     # delete messages older than this\n  max_chats = 200       # keep only the most recently active chats\n  keep_pinned = true    # never delete from chats holding a pinned message (default)\n  keep_tagged = true    # never delete from tagged chats (default)\n  auto_gc_every = 50    # run `yo gc` every 50 invocations\n\nReplies to deleted messages stay attached to the nearest message that is kept. Chats emptied by max_age_days are deleted.")]
+    Gc {
+        #[arg(long, help = "Report what would be deleted without changing anything")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Rebuild the semantic search index", long_about = "Drop all stored message embeddings and embed every message again. Run this after changing `embedding_model` in config.toml; `yo search --semantic` otherwise only embeds new messages.")]
+    Reindex {
+        #[arg(short, long, help = "Embedding model to use instead of the configured one")]
+        model: Option<String>,
     },
 
     #[command(about = "View the current chat's history", long_about = "Display all messages in the current chat session in a readable format.")]
     ViewChat,
 
-    #[command(about = "Delete a chat session", long_about = "Delete a specific chat session and all its messages by its ID.")]
+    #[command(about = "Delete a chat session", long_about = "Delete a specific chat session and all its messages by its ID, or every chat carrying the given tags.")]
     DeleteChat {
-        #[arg(help = "ID of the chat to delete")]
-        chat_id: i64,
+        #[arg(help = "ID of the chat to delete", required_unless_present = "tags", conflicts_with = "tags")]
+        chat_id: Option<i64>,
+        #[arg(long = "tag", help = "Delete all chats with this tag (repeat to require several)")]
+        tags: Vec<String>,
     },
 
     #[command(about = "Delete all chats and messages", long_about = "Delete all chat sessions and all messages. This cannot be undone.")]
     ClearAllChats,
+
+    #[command(about = "Extract user facts from a chat into the profile", long_about = "Ask the model to pull durable facts about you (languages, OS, projects, conventions) out of a chat. Proposed entries are compared with the existing profile and shown for approval before being saved.\n\nSet `extract_memories = true` in config.toml to run this after every ask, and `auto_accept_memories = true` to skip the approval step.")]
+    Remember {
+        #[arg(help = "ID of the chat to learn from (defaults to the current chat)")]
+        chat_id: Option<i64>,
+        #[arg(short, long, help = "Save all proposed entries without asking")]
+        yes: bool,
+    },
+
+    #[command(about = "Add tags to a chat", long_about = "Add one or more tags to a chat. Tags are lowercased and spaces become dashes, e.g. 'Work Stuff' is stored as 'work-stuff'.")]
+    Tag {
+        #[arg(help = "ID of the chat to tag")]
+        chat_id: i64,
+        #[arg(required = true, help = "Tags to add")]
+        tags: Vec<String>,
+    },
+
+    #[command(about = "Remove tags from a chat", long_about = "Remove one or more tags from a chat.")]
+    Untag {
+        #[arg(help = "ID of the chat to untag")]
+        chat_id: i64,
+        #[arg(required = true, help = "Tags to remove")]
+        tags: Vec<String>,
+    },
+
+    #[command(about = "List all tags", long_about = "List all tags with the number of chats using each one.")]
+    Tags,
+
+    #[command(about = "Start a new branch from a message", long_about = "Fork the conversation at a message. The message's chat becomes the current chat and your next question is asked as a reply to that message, leaving the original thread untouched. Message IDs are shown by `yo view-chat`.")]
+    Fork {
+        #[arg(help = "ID of the message to branch from")]
+        message_id: i64,
+    },
+
+    #[command(about = "List branches of the current chat", long_about = "List the tips of every branch in the current chat. The active branch is marked with '*'.")]
+    Branches,
+
+    #[command(about = "Switch to another branch", long_about = "Make the branch containing the given message active. If the message has replies, the newest branch below it is chosen.\n\nAlso available as `yo pick <message-id>` to choose which alternate answer is current.", visible_alias = "pick")]
+    SwitchBranch {
+        #[arg(help = "ID of a message on the branch to switch to")]
+        message_id: i64,
+    },
+
+    #[command(about = "Set the model of a chat", long_about = "Change the backend, model or generation parameters of a single chat (the current one by default) without touching config.toml.\n\nSwitching the backend without naming a model uses the model from config.toml when it is for that backend.")]
+    ChatModel {
+        #[arg(help = "Model to use for the chat, e.g. 'gpt-4o' or 'llama3:latest'")]
+        model: Option<String>,
+        #[arg(short, long, help = "Backend to use: 'openai' or 'ollama'")]
+        backend: Option<String>,
+        #[arg(short, long, help = "Sampling temperature (OpenAI)")]
+        temperature: Option<f32>,
+        #[arg(long, help = "Maximum number of tokens to generate (OpenAI)")]
+        max_tokens: Option<u32>,
+        #[arg(long, conflicts_with = "temperature", help = "Go back to the backend's default temperature")]
+        no_temperature: bool,
+        #[arg(long, conflicts_with = "max_tokens", help = "Remove the token limit")]
+        no_max_tokens: bool,
+        #[arg(long, help = "Chat to change (defaults to the current chat)")]
+        chat_id: Option<i64>,
+    },
+
+    #[command(about = "Manage personas", long_about = "Personas bundle a system prompt with an optional backend, model and generation parameters. They live in the personas/ directory next to config.toml (one TOML file per persona) or as [personas.<name>] tables in config.toml.\n\nUse them with `yo --as <persona> <question>` or `yo new-chat --persona <persona>`.")]
+    Personas {
+        #[command(subcommand)]
+        command: PersonaCommand,
+    },
+
+    #[command(about = "Bind a directory to a chat", long_about = "Make a chat the current one whenever yo runs inside a directory. Defaults to the current git repository root (or working directory) and the current chat.\n\nSet `project_chats = true` in config.toml to give every repository its own chat automatically.")]
+    Bind {
+        #[arg(help = "ID of the chat (defaults to the current chat)")]
+        chat_id: Option<i64>,
+        #[arg(long, value_name = "PATH", help = "Directory to bind instead of the current project")]
+        dir: Option<std::path::PathBuf>,
+    },
+
+    #[command(about = "Unbind a directory from its chat", long_about = "Remove a directory's chat binding. Defaults to the binding that applies in the working directory.")]
+    Unbind {
+        #[arg(long, value_name = "PATH", help = "Directory to unbind")]
+        dir: Option<std::path::PathBuf>,
+    },
+
+    #[command(about = "List directories bound to chats")]
+    Projects,
+
+    #[command(about = "Chat interactively", long_about = "Start an interactive session in the current chat. Answers are streamed and every turn is saved like `yo ask`.\n\nEnd a line with \\ or wrap text in \"\"\" to send several lines. Slash commands: /model, /new, /switch <id>, /system, /history, /clear, /help, /exit.")]
+    Chat,
+
+    #[command(about = "Answer many prompts from a JSONL or CSV file", long_about = "Run every prompt of a file and append the results to a JSONL file, one line per prompt with the response, token usage and any error.\n\nJSONL lines look like {\"id\": \"q1\", \"prompt\": \"Translate {{word}}\", \"model\": \"gpt-4o\", \"system\": \"...\", \"vars\": {\"word\": \"cat\"}}; only prompt is required. CSV files need a header row with a prompt column; id, model and system are optional and other columns become variables.\n\nPrompts already answered in the results file are skipped, so an interrupted batch can be resumed by running it again. The current chat is left alone unless --save is given.")]
+    Batch {
+        #[arg(help = "JSONL or CSV file with one prompt per line")]
+        file: std::path::PathBuf,
+        #[arg(short, long, value_name = "PATH", help = "Results file (default: <file>.results.jsonl)")]
+        output: Option<std::path::PathBuf>,
+        #[arg(short = 'j', long, default_value_t = 4, help = "Number of prompts to run at once")]
+        concurrency: usize,
+        #[arg(long, value_name = "PER_MINUTE", help = "Start at most this many requests per minute")]
+        rate: Option<u32>,
+        #[arg(long, help = "Also store every prompt and answer in the current chat")]
+        save: bool,
+    },
+
+    #[command(about = "Run a prompt template", long_about = "Render a template from the templates/ directory next to config.toml and ask it like a normal question.\n\nTemplates use {{name}} placeholders, {{name|default}} for optional ones and {{file:path}} to include a file. Piped stdin is bound to {{input}}, e.g.\n  git diff | yo run review --var lang=Rust\n\nThe template is refused if a required variable is missing.")]
+    Run {
+        #[arg(help = "Name of the template")]
+        template: String,
+        #[arg(long = "var", value_name = "KEY=VALUE", help = "Set a template variable (repeatable)")]
+        vars: Vec<String>,
+        #[arg(long, visible_alias = "incognito", help = "Don't read or write chat history")]
+        no_history: bool,
+        #[arg(long, help = "Print the rendered prompt instead of sending it")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Manage prompt templates", long_about = "List, show and create prompt templates in the templates/ directory next to config.toml.")]
+    Templates {
+        #[command(subcommand)]
+        command: TemplateCommand,
+    },
+
+    #[command(about = "Regenerate the last answer", long_about = "Re-run the last question of the current chat. The previous answer is kept as an alternate; list them with `yo alternates`.")]
+    Regen {
+        #[arg(short, long, help = "Model to use for this answer only")]
+        model: Option<String>,
+        #[arg(short, long, help = "Sampling temperature for this answer only (OpenAI)")]
+        temperature: Option<f32>,
+    },
+
+    #[command(about = "Edit the last question and ask again", long_about = "Open the last question of the current chat in $EDITOR and ask the edited version. The original question and its answers are kept as an alternate branch.")]
+    EditLast,
+
+    #[command(about = "List alternate answers to the last question", long_about = "Show every answer generated for the last question (and every edited version of it). The current one is marked with '*'.")]
+    Alternates,
+
+    #[command(about = "Delete a single message", long_about = "Delete one message by its ID (see `yo view-chat`). Replies to it are attached to its parent so the rest of the chat is kept.")]
+    DeleteMessage {
+        #[arg(help = "ID of the message to delete")]
+        message_id: i64,
+    },
+
+    #[command(about = "Hide a message from the context", long_about = "Keep a message in the chat but stop sending it to the model.")]
+    Hide {
+        #[arg(help = "ID of the message to hide")]
+        message_id: i64,
+    },
+
+    #[command(about = "Unhide a message", long_about = "Send a previously hidden message to the model again.")]
+    Unhide {
+        #[arg(help = "ID of the message to unhide")]
+        message_id: i64,
+    },
+
+    #[command(about = "Pin a message to the context", long_about = "Always send a message to the model, even when it is older than `max_context_messages`. A pin applies to the branches the message is on.")]
+    Pin {
+        #[arg(help = "ID of the message to pin")]
+        message_id: i64,
+    },
+
+    #[command(about = "Unpin a message", long_about = "Stop forcing a message into the context.")]
+    Unpin {
+        #[arg(help = "ID of the message to unpin")]
+        message_id: i64,
+    },
+}
+
+impl Command {
+    /// Whether the command opens chats.db. Auto gc only runs after these, so setup,
+    /// incognito asks, dry runs and the like never read or write the history.
+    pub fn uses_history(&self) -> bool {
+        !matches!(
+            self,
+            Command::Setup
+                | Command::Config
+                | Command::Switch { .. }
+                | Command::Gpt { .. }
+                | Command::List
+                | Command::Current
+                | Command::ShellInit { .. }
+                | Command::Personas { .. }
+                | Command::Templates { .. }
+                | Command::Ask { no_history: true, .. }
+                | Command::Run { no_history: true, .. }
+                | Command::Run { dry_run: true, .. }
+                | Command::Batch { save: false, .. }
+                | Command::Import {
+                    source: ImportSource::Chatgpt { dry_run: true, .. }
+                        | ImportSource::Llm { dry_run: true, .. }
+                        | ImportSource::Aichat { dry_run: true, .. }
+                }
+                // gc just ran, and a restored database is left as it was backed up
+                | Command::Gc { .. }
+                | Command::Restore { .. }
+        )
+    }
+}
+
+
+#[derive(Subcommand)]
+pub enum PersonaCommand {
+    #[command(about = "List all personas")]
+    List,
+
+    #[command(about = "Show a persona")]
+    Show {
+        #[arg(help = "Name of the persona")]
+        name: String,
+    },
+
+    #[command(about = "Create a persona", long_about = "Create a persona file in the personas/ directory. Without --system, $EDITOR opens to write the system prompt.")]
+    New {
+        #[arg(help = "Name of the persona, e.g. 'reviewer'")]
+        name: String,
+        #[arg(short, long, help = "System prompt")]
+        system: Option<String>,
+        #[arg(short, long, help = "Backend to use: 'openai' or 'ollama'")]
+        backend: Option<String>,
+        #[arg(short, long, help = "Model to use")]
+        model: Option<String>,
+        #[arg(short, long, help = "Sampling temperature (OpenAI)")]
+        temperature: Option<f32>,
+        #[arg(long, help = "Maximum number of tokens to generate (OpenAI)")]
+        max_tokens: Option<u32>,
+    },
+
+    #[command(about = "Edit a persona in $EDITOR")]
+    Edit {
+        #[arg(help = "Name of the persona")]
+        name: String,
+    },
+
+    #[command(about = "Delete a persona")]
+    Delete {
+        #[arg(help = "Name of the persona")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImportSource {
+    #[command(about = "Import a ChatGPT data export", long_about = "Import conversations.json from a ChatGPT data export (Settings → Data controls → Export data), or the unzipped export directory.\n\nEach conversation becomes a chat with its original title and timestamps. Only the branch a conversation was left on is imported.")]
+    Chatgpt {
+        #[arg(help = "conversations.json or the export directory")]
+        file: std::path::PathBuf,
+        #[arg(long, help = "Report what would be imported without saving anything")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Import the logs of Simon Willison's llm", long_about = "Import the prompts and responses llm logged in its SQLite database (see `llm logs path`).\n\nEach llm conversation becomes a chat, keeping its name, system prompt, timestamps and the model of every response.")]
+    Llm {
+        #[arg(help = "Path to logs.db (default: llm's own)")]
+        file: Option<std::path::PathBuf>,
+        #[arg(long, help = "Report what would be imported without saving anything")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Import aichat sessions and its messages.md log", long_about = "Import aichat's saved sessions (YAML) and its messages.md log. Pass a session file, messages.md, a sessions directory or aichat's config directory (the default).\n\nSessions keep their system prompt and model; ones using an OpenAI or Ollama model continue with it in yo. Sessions don't record when messages were sent, so they get the file's modification time.")]
+    Aichat {
+        #[arg(help = "Session file, messages.md or directory (default: aichat's config directory)")]
+        path: Option<std::path::PathBuf>,
+        #[arg(long, help = "Report what would be imported without saving anything")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    #[command(about = "Encrypt existing and future chat history with a passphrase")]
+    Encrypt,
+    #[command(about = "Decrypt chat history and stop encrypting it")]
+    Decrypt,
 }
 
+#[derive(Subcommand)]
+pub enum TemplateCommand {
+    #[command(about = "List all templates and their required variables")]
+    List,
+
+    #[command(about = "Show a template and its placeholders")]
+    Show {
+        #[arg(help = "Name of the template")]
+        name: String,
+    },
+
+    #[command(about = "Create a template and open it in $EDITOR")]
+    New {
+        #[arg(help = "Name of the template, e.g. 'review'")]
+        name: String,
+    },
+}
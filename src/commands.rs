@@ -1,14 +1,33 @@
-use crate::config::{get_config_path, load_or_create_config, save_config, Config};
+use crate::config::{get_config_path, load_or_create_config, save_config, try_load_config, Config};
 use prettytable::{Table, Row, Cell};
 use reqwest::Client;
 use serde_json::Value;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read, Write};
 use std::process::Command as ShellCommand;
 use std::fs;
 use async_trait::async_trait;
 use anyhow::Result;
 use futures_util::StreamExt;
-use crate::db::init_db;
+use crate::db::{self, SCHEMA_VERSION};
+use crate::crypto::{self, CryptoError};
+use crate::memory::{self, Proposal};
+use crate::tags;
+use crate::search::{self, SearchFilter};
+use crate::embeddings::{self, EmbeddingModel};
+use crate::backup::{self, RestoreMode, StatePaths};
+use crate::export;
+use crate::import;
+use crate::chats;
+use crate::paths;
+use crate::project;
+use crate::retention;
+use crate::store::{ChatStore, Message};
+use crate::session::{self, Scope};
+use crate::persona::{self, Persona};
+use crate::template;
+use crate::batch::{self, BatchItem, BatchResult, RateLimiter, Usage};
+use crate::branches;
+use crate::conversation::{Conversation, DEFAULT_SYSTEM_PROMPT};
 
 #[async_trait]
 pub trait AIProvider {
@@ -23,17 +42,25 @@ pub trait AIProvider {
 pub struct OpenAIProvider {
     pub model: String,
     pub api_key: String,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
 }
 
 #[async_trait]
 impl AIProvider for OpenAIProvider {
     async fn ask_openai(&self, messages: Vec<serde_json::Value>) -> Result<String> {
         let client = reqwest::Client::new();
-        let body = serde_json::json!({
+        let mut body = serde_json::json!({
             "model": self.model,
             "messages": messages,
             "stream": true
         });
+        if let Some(t) = self.temperature {
+            body["temperature"] = serde_json::json!(t);
+        }
+        if let Some(n) = self.max_tokens {
+            body["max_tokens"] = serde_json::json!(n);
+        }
         let res = client
             .post("https://api.openai.com/v1/chat/completions")
             .bearer_auth(&self.api_key)
@@ -115,6 +142,211 @@ impl AIProvider for Provider {
     }
 }
 
+/// Run a non-streaming completion without printing anything.
+/// Used for background calls such as memory extraction.
+async fn complete_quietly(cfg: &Config, conversation: &Conversation) -> Result<String> {
+    complete_with_usage(cfg, conversation).await.map(|(text, _)| text)
+}
+
+/// Like `complete_quietly`, also returning token usage when the backend reports it
+async fn complete_with_usage(cfg: &Config, conversation: &Conversation) -> Result<(String, Option<Usage>)> {
+    match cfg.source.as_str() {
+        "openai" => {
+            let api_key = cfg.openai_api_key.clone().ok_or_else(|| anyhow::anyhow!("OpenAI API key not set"))?;
+            let mut body = serde_json::json!({
+                "model": cfg.model,
+                "messages": conversation.openai_messages(),
+            });
+            if let Some(t) = cfg.temperature {
+                body["temperature"] = serde_json::json!(t);
+            }
+            if let Some(n) = cfg.max_tokens {
+                body["max_tokens"] = serde_json::json!(n);
+            }
+            let res = Client::new()
+                .post("https://api.openai.com/v1/chat/completions")
+                .bearer_auth(api_key)
+                .json(&body)
+                .send()
+                .await?;
+            let status = res.status();
+            if !status.is_success() {
+                let err_text = res.text().await.unwrap_or_default();
+                return Err(anyhow::anyhow!("OpenAI API error: {}\n{}", status, err_text));
+            }
+            let j: Value = res.json().await?;
+            let text = j["choices"][0]["message"]["content"].as_str().unwrap_or_default().to_string();
+            Ok((text, serde_json::from_value(j["usage"].clone()).ok()))
+        }
+        // `ollama run` doesn't report token counts
+        "ollama" => OllamaProvider { model: cfg.model.clone() }.ask_ollama(&conversation.ollama_prompt())
+            .await
+            .map(|t| (t, None))
+            .map_err(|e| anyhow::anyhow!("failed to run ollama: {}", e)),
+        other => Err(anyhow::anyhow!("Unknown backend: {}", other)),
+    }
+}
+
+/// Embed texts with the OpenAI embeddings endpoint or Ollama's embed API, one vector per text
+async fn embed_texts(cfg: &Config, model: &EmbeddingModel, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let (res, key) = match model.source.as_str() {
+        "openai" => {
+            let api_key = cfg.openai_api_key.clone().ok_or_else(|| anyhow::anyhow!("OpenAI API key not set"))?;
+            let res = Client::new()
+                .post("https://api.openai.com/v1/embeddings")
+                .bearer_auth(api_key)
+                .json(&serde_json::json!({ "model": model.model, "input": texts }))
+                .send()
+                .await?;
+            (res, "data")
+        }
+        "ollama" => {
+            let host = std::env::var("OLLAMA_HOST").unwrap_or_else(|_| "127.0.0.1:11434".to_string());
+            let base = if host.starts_with("http") { host } else { format!("http://{}", host) };
+            let res = Client::new()
+                .post(format!("{}/api/embed", base.trim_end_matches('/')))
+                .json(&serde_json::json!({ "model": model.model, "input": texts }))
+                .send()
+                .await?;
+            (res, "embeddings")
+        }
+        other => return Err(anyhow::anyhow!("Unknown backend: {}", other)),
+    };
+    let status = res.status();
+    if !status.is_success() {
+        let err_text = res.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("{} embedding error: {}\n{}", model.source, status, err_text));
+    }
+    let j: Value = res.json().await?;
+    let items = j[key].as_array().cloned().unwrap_or_default();
+    let vectors: Vec<Vec<f32>> = items
+        .iter()
+        .map(|item| {
+            let v = if key == "data" { &item["embedding"] } else { item };
+            v.as_array().map(|xs| xs.iter().filter_map(Value::as_f64).map(|x| x as f32).collect()).unwrap_or_default()
+        })
+        .collect();
+    if vectors.len() != texts.len() {
+        return Err(anyhow::anyhow!("expected {} embeddings, got {}", texts.len(), vectors.len()));
+    }
+    Ok(vectors)
+}
+
+/// Embed every message that has no vector from `model` yet. Returns how many were added.
+async fn index_embeddings(conn: &rusqlite::Connection, cfg: &Config, model: &EmbeddingModel) -> Result<usize> {
+    const BATCH: usize = 64;
+    let label = model.label();
+    let total = embeddings::count_pending(conn, &label)?;
+    if total == 0 {
+        return Ok(0);
+    }
+    eprintln!("🧮 Embedding {} message(s) with {}…", total, label);
+    let mut done = 0;
+    loop {
+        let batch = embeddings::pending(conn, &label, BATCH)?;
+        if batch.is_empty() {
+            break;
+        }
+        let texts: Vec<String> = batch.iter().map(|(_, content)| embeddings::embed_text(content)).collect();
+        let vectors = embed_texts(cfg, model, &texts).await?;
+        let tx = conn.unchecked_transaction()?;
+        for ((id, _), vector) in batch.iter().zip(&vectors) {
+            embeddings::store(&tx, *id, &label, vector)?;
+        }
+        tx.commit()?;
+        done += batch.len();
+        if total > BATCH {
+            eprintln!("   {}/{}", done, total);
+        }
+    }
+    Ok(done)
+}
+
+/// `bytes` for people, e.g. "1.5 MB"
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} bytes", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+/// Apply the retention policy, drop orphaned rows and VACUUM. Doesn't need the
+/// passphrase of an encrypted database, as no content is read.
+fn collect_garbage(dry_run: bool) -> Result<(retention::GcStats, u64), String> {
+    let policy = try_load_config().and_then(|c| c.retention).unwrap_or_default();
+    let conn = db::open(&paths::db_path()).map_err(|e| e.to_string())?;
+    let stats = retention::collect(&conn, &policy, dry_run).map_err(|e| e.to_string())?;
+    if dry_run {
+        return Ok((stats, 0));
+    }
+    let _ = fs::write(paths::gc_counter_path(), "0");
+    let reclaimed = retention::vacuum(&conn).map_err(|e| e.to_string())?;
+    Ok((stats, reclaimed))
+}
+
+/// Apply the retention policy and compact chats.db
+pub fn gc(dry_run: bool) {
+    match collect_garbage(dry_run) {
+        Ok((stats, _)) if dry_run => println!(
+            "🔍 Dry run: would delete {} chat(s), {} old message(s) and {} orphaned row(s)",
+            stats.chats, stats.messages, stats.orphans
+        ),
+        Ok((stats, reclaimed)) => println!(
+            "🧹 Deleted {} chat(s), {} old message(s) and {} orphaned row(s); reclaimed {}",
+            stats.chats, stats.messages, stats.orphans, format_size(reclaimed)
+        ),
+        Err(e) => eprintln!("gc failed: {}", e),
+    }
+}
+
+/// Count this invocation and run `yo gc` when `auto_gc_every` says it's time
+pub fn auto_gc() {
+    let every = match try_load_config().and_then(|c| c.retention).and_then(|r| r.auto_gc_every) {
+        Some(n) if n > 0 => n,
+        _ => return,
+    };
+    let path = paths::gc_counter_path();
+    let count = fs::read_to_string(&path).ok().and_then(|s| s.trim().parse::<u32>().ok()).unwrap_or(0) + 1;
+    if count < every {
+        let _ = fs::write(&path, count.to_string());
+        return;
+    }
+    match collect_garbage(false) {
+        Ok((stats, reclaimed)) if stats != retention::GcStats::default() || reclaimed > 0 => eprintln!(
+            "🧹 Auto gc: deleted {} chat(s), {} old message(s) and {} orphaned row(s); reclaimed {}",
+            stats.chats, stats.messages, stats.orphans, format_size(reclaimed)
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("Auto gc failed: {}", e),
+    }
+}
+
+/// Drop all stored embeddings and embed every message again, e.g. after changing `embedding_model`
+pub async fn reindex_embeddings(model: Option<String>) {
+    let mut cfg = load_or_create_config();
+    if model.is_some() {
+        cfg.embedding_model = model;
+    }
+    let model = EmbeddingModel::from_config(&cfg);
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match embeddings::clear(&store) {
+        Ok(n) if n > 0 => println!("🗑️ Dropped {} stored embedding(s)", n),
+        Ok(_) => {}
+        Err(e) => { eprintln!("Failed to clear embeddings: {}", e); return; }
+    }
+    match index_embeddings(&store, &cfg, &model).await {
+        Ok(n) => println!("✅ Indexed {} message(s) with {}", n, model.label()),
+        Err(e) => eprintln!("Failed to index messages: {}", e),
+    }
+}
+
 async fn fetch_openai_models(api_key: &str) -> Vec<String> {
     let client = Client::new();
     let res = client
@@ -164,14 +396,13 @@ pub fn clear_history() {
         Some(id) => id,
         None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
     };
-    let conn = match init_db() {
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    if let Err(e) = conn.execute("DELETE FROM messages WHERE chat_id = ?1", [chat_id]) {
-        eprintln!("Failed to clear history: {}", e);
-    } else {
-        println!("✅ Cleared history for current chat");
+    match store.clear_messages(chat_id) {
+        Ok(_) => println!("✅ Cleared history for current chat"),
+        Err(e) => eprintln!("Failed to clear history: {}", e),
     }
 }
 
@@ -198,13 +429,13 @@ pub fn setup() {
     // pick default model
     let default_model = if src == "openai" { "gpt-4".to_string() } else { 
         let loc = fetch_ollama_local();
-        loc.get(0).cloned().unwrap_or_else(|| {
+        loc.first().cloned().unwrap_or_else(|| {
             eprintln!("no local ollama model installed");
             std::process::exit(1);
         })
     };
 
-    let cfg = Config { source: src.clone(), model: default_model, openai_api_key: key };
+    let cfg = Config { source: src.clone(), model: default_model, openai_api_key: key, ..Default::default() };
     save_config(&cfg);
     println!("✅ setup complete");
     println!("⚙️ config saved at {}", get_config_path().display());
@@ -327,84 +558,396 @@ pub async fn list_models() {
 }
 
 /// Ask current model; for Ollama, use ollama run and exit with /bye
-pub async fn ask(question: &[String]) {
-    let chat_id = match get_current_chat_id() {
-        Some(id) => id,
-        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+pub async fn ask(question: &[String], no_history: bool, persona_name: Option<&str>) {
+    let prompt = question.join(" ");
+    let persona = match persona_name {
+        Some(name) => match find_persona(name) {
+            Some(p) => Some(p),
+            None => return,
+        },
+        None => None,
     };
-    let conn = match init_db() {
+    if no_history {
+        ask_incognito(&prompt, persona.as_ref()).await;
+        return;
+    }
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    let prompt = question.join(" ");
-    // Store user message
-    let _ = conn.execute(
-        "INSERT INTO messages (chat_id, role, content) VALUES (?1, 'user', ?2)",
-        (&chat_id, &prompt),
-    );
-    let cfg = load_or_create_config();
-    // Fetch full chat history for context
-    let mut stmt = conn.prepare("SELECT role, content FROM messages WHERE chat_id = ?1 ORDER BY created_at ASC").unwrap();
-    let history: Vec<(String, String)> = stmt
-        .query_map([chat_id], |row| Ok((row.get(0)?, row.get(1)?)))
-        .unwrap()
+    let chat_id = match ensure_current_chat(&store, &prompt) {
+        Some(id) => id,
+        None => return,
+    };
+    // Store user message on the active branch
+    let parent = branches::active_head(&store, chat_id).unwrap_or(None);
+    let user_id = match branches::append(&store, chat_id, parent, "user", &prompt) {
+        Ok(id) => id,
+        Err(e) => { eprintln!("Failed to store message: {}", e); return; }
+    };
+    let mut cfg = chat_config(&store, chat_id);
+    let system_prompt = match &persona {
+        Some(p) => {
+            cfg.apply(&p.settings(&cfg.settings()));
+            p.system_prompt.clone()
+        }
+        None => chat_system_prompt(&store, chat_id),
+    };
+    answer(&store, &cfg, chat_id, user_id, &system_prompt).await;
+    if cfg.extract_memories == Some(true) {
+        extract_memories(Some(chat_id), false).await;
+    }
+}
+
+/// The current chat, creating and selecting a new one (titled after the first question)
+/// if none is selected or the selected chat was deleted
+fn ensure_current_chat(conn: &rusqlite::Connection, prompt: &str) -> Option<i64> {
+    if let Some(id) = get_current_chat_id() {
+        if chats::exists(conn, id).unwrap_or(false) {
+            return Some(id);
+        }
+    }
+    let mut title: String = prompt.chars().take(40).collect();
+    if title.len() < prompt.len() {
+        title.push('…');
+    }
+    match chats::create(conn, &title, None) {
+        Ok(chat_id) => {
+            set_current_chat_id(chat_id);
+            println!("💬 Started new chat '{}' (id: {})", title, chat_id);
+            Some(chat_id)
+        }
+        Err(e) => { eprintln!("Failed to create chat: {}", e); None }
+    }
+}
+
+/// The config to answer a chat with: the global one overlaid with the chat's own
+/// backend, model and parameters. Chats that don't have any yet adopt the global ones.
+fn chat_config(conn: &rusqlite::Connection, chat_id: i64) -> Config {
+    let mut cfg = load_or_create_config();
+    match chats::settings(conn, chat_id) {
+        Ok(Some(settings)) => cfg.apply(&settings),
+        Ok(None) => {
+            if let Err(e) = chats::set_settings(conn, chat_id, &cfg.settings()) {
+                eprintln!("Failed to save the chat's model settings: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to read the chat's model settings, using the global ones: {}", e),
+    }
+    cfg
+}
+
+fn chat_system_prompt(conn: &rusqlite::Connection, chat_id: i64) -> String {
+    chats::system_prompt(conn, chat_id)
+        .ok()
         .flatten()
-        .collect();
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string())
+}
+
+/// Look up a persona by name, reporting unknown or broken ones
+fn find_persona(name: &str) -> Option<Persona> {
+    match persona::load(name) {
+        Ok(Some((p, _))) => Some(p),
+        Ok(None) => {
+            eprintln!("Persona '{}' not found.", name);
+            let names: Vec<String> = persona::list().into_iter().map(|(n, _, _)| n).collect();
+            if names.is_empty() {
+                eprintln!("Create one with `yo personas new {} --system \"...\"`", name);
+            } else {
+                eprintln!("Available personas: {}", names.join(", "));
+            }
+            None
+        }
+        Err(e) => { eprintln!("Failed to load persona '{}': {}", name, e); None }
+    }
+}
+
+/// Ask without reading or writing chats.db: no history, no profile, nothing persisted
+async fn ask_incognito(prompt: &str, persona: Option<&Persona>) {
+    let mut cfg = load_or_create_config();
+    let system_prompt = match persona {
+        Some(p) => {
+            cfg.apply(&p.settings(&cfg.settings()));
+            p.system_prompt.as_str()
+        }
+        None => DEFAULT_SYSTEM_PROMPT,
+    };
+    let conversation = Conversation::build(system_prompt, &[], Vec::new(), Some(prompt));
+    let _ = send(&cfg, &conversation).await;
+}
+
+/// Send the branch ending at user message `user_id` to the model and store the reply as its child
+async fn answer(store: &ChatStore, cfg: &Config, chat_id: i64, user_id: i64, system_prompt: &str) {
+    // The branch up to the question, skipping hidden messages and keeping pinned ones
+    let (history, question) = match branches::question_context(store, user_id, cfg.max_context_messages) {
+        Ok(Some(found)) => found,
+        Ok(None) => { eprintln!("Message #{} not found in chat {}.", user_id, chat_id); return; }
+        Err(e) => { eprintln!("Failed to read chat: {}", e); return; }
+    };
+    let conversation = Conversation::build(system_prompt, &load_profile(store), history, Some(&question));
+    if let Some(response) = send(cfg, &conversation).await {
+        if let Err(e) = branches::append(store, chat_id, Some(user_id), "assistant", &response) {
+            eprintln!("Failed to save the answer: {}", e);
+        }
+    }
+}
+
+/// Send a conversation to the configured backend, printing the answer as it arrives
+async fn send(cfg: &Config, conversation: &Conversation) -> Option<String> {
     match cfg.source.as_str() {
         "openai" => {
-            let mut messages = vec![serde_json::json!({
-                "role": "system",
-                "content": "You are a helpful AI assistant."
-            })];
-            for (role, content) in &history {
-                messages.push(serde_json::json!({"role": role, "content": content}));
-            }
-            messages.push(serde_json::json!({"role": "user", "content": &prompt}));
+            let api_key = match cfg.openai_api_key.clone() {
+                Some(k) => k,
+                None => {
+                    eprintln!("No OpenAI API key set. Run `yo setup` first.");
+                    return None;
+                }
+            };
             let provider = Provider::OpenAI(OpenAIProvider {
                 model: cfg.model.clone(),
-                api_key: cfg.openai_api_key.clone().unwrap(),
+                api_key,
+                temperature: cfg.temperature,
+                max_tokens: cfg.max_tokens,
             });
-            match provider.ask_openai(messages).await {
-                Ok(response) => {
-                    let _ = conn.execute(
-                        "INSERT INTO messages (chat_id, role, content) VALUES (?1, 'assistant', ?2)",
-                        (&chat_id, &response),
-                    );
-                }
+            match provider.ask_openai(conversation.openai_messages()).await {
+                Ok(response) => Some(response),
                 Err(e) => {
                     eprintln!("Error during AI call: {}", e);
+                    None
                 }
             }
         }
         "ollama" => {
-            let mut full_prompt = String::new();
-            for (role, content) in &history {
-                let who = match role.as_str() {
-                    "user" => "User",
-                    "assistant" => "AI",
-                    _ => role.as_str(),
-                };
-                full_prompt.push_str(&format!("{}: {}\n", who, content));
+            if cfg.temperature.is_some() || cfg.max_tokens.is_some() {
+                eprintln!("⚠️ temperature and max_tokens are not supported by the ollama backend, ignoring them");
             }
-            full_prompt.push_str(&format!("User: {}\n", &prompt));
             let provider = Provider::Ollama(OllamaProvider {
                 model: cfg.model.clone(),
             });
-            match provider.ask_ollama(&full_prompt).await {
+            match provider.ask_ollama(&conversation.ollama_prompt()).await {
                 Ok(response) => {
                     println!("{}", response);
-                    let _ = conn.execute(
-                        "INSERT INTO messages (chat_id, role, content) VALUES (?1, 'assistant', ?2)",
-                        (&chat_id, &response),
-                    );
+                    Some(response)
                 }
                 Err(e) => {
                     eprintln!("Error during AI call: {}", e);
+                    None
                 }
             }
         }
-        _ => eprintln!("Unknown backend: {}", cfg.source),
+        _ => {
+            eprintln!("Unknown backend: {}", cfg.source);
+            None
+        }
+    }
+}
+
+/// The newest user message on the active branch of the current chat
+fn last_user_turn(conn: &rusqlite::Connection, chat_id: i64) -> Option<Message> {
+    branches::active_path(conn, chat_id)
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .find(|t| t.role == "user")
+}
+
+/// Re-run the last user turn; the previous answer is kept as an alternate
+pub async fn regen(model: Option<String>, temperature: Option<f32>) {
+    let chat_id = match get_current_chat_id() {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let last = match last_user_turn(&store, chat_id) {
+        Some(t) => t,
+        None => { eprintln!("Nothing to regenerate: the current chat has no questions yet."); return; }
+    };
+    let mut cfg = chat_config(&store, chat_id);
+    if let Some(m) = model {
+        cfg.model = m;
+    }
+    if temperature.is_some() {
+        cfg.temperature = temperature;
+    }
+    println!("🔁 Regenerating answer to #{} with {}", last.id, cfg.settings().label());
+    answer(&store, &cfg, chat_id, last.id, &chat_system_prompt(&store, chat_id)).await;
+}
+
+/// Open the last question in $EDITOR and ask the edited version on a new branch
+pub async fn edit_last() {
+    let chat_id = match get_current_chat_id() {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let last = match last_user_turn(&store, chat_id) {
+        Some(t) => t,
+        None => { eprintln!("Nothing to edit: the current chat has no questions yet."); return; }
+    };
+    let edited = match edit_in_editor(&last.content) {
+        Ok(text) => text,
+        Err(e) => { eprintln!("Failed to open editor: {}", e); return; }
+    };
+    let edited = edited.trim();
+    if edited.is_empty() || edited == last.content.trim() {
+        println!("Question unchanged, nothing to ask.");
+        return;
+    }
+    let user_id = match branches::append(&store, chat_id, last.parent_id, "user", edited) {
+        Ok(id) => id,
+        Err(e) => { eprintln!("Failed to store message: {}", e); return; }
+    };
+    let cfg = chat_config(&store, chat_id);
+    answer(&store, &cfg, chat_id, user_id, &chat_system_prompt(&store, chat_id)).await;
+}
+
+/// Let the user edit `initial` in $VISUAL/$EDITOR and return the result
+fn edit_in_editor(initial: &str) -> io::Result<String> {
+    // a new file only the user can read, in yo's own directory rather than a shared /tmp
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+    let dir = paths::data_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("edit-{}-{}.md", std::process::id(), nanos));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    if let Err(e) = options.open(&path)?.write_all(initial.as_bytes()) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    let status = open_in_editor(&path);
+    let text = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    status.and(text)
+}
+
+/// Open a file in $VISUAL/$EDITOR and wait for the editor to exit
+fn open_in_editor(path: &std::path::Path) -> io::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // $EDITOR may carry arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = ShellCommand::new(program).args(parts).arg(path).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} exited with {}", editor, status)))
+    }
+}
+
+/// List alternate answers (and edited versions) of the last question
+pub fn list_alternates() {
+    let chat_id = match get_current_chat_id() {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let path = branches::active_path(&store, chat_id).unwrap_or_default();
+    let last = match path.iter().rev().find(|t| t.role == "user") {
+        Some(t) => t,
+        None => { println!("The current chat has no questions yet."); return; }
+    };
+    let on_path = |id: i64| path.iter().any(|t| t.id == id);
+    let preview = |t: &Message| -> String { t.content.lines().next().unwrap_or("").chars().take(70).collect() };
+
+    let questions = branches::children(&store, chat_id, last.parent_id).unwrap_or_default();
+    let questions: Vec<_> = questions.into_iter().filter(|t| t.role == "user").collect();
+    if questions.len() > 1 {
+        println!("\nVersions of the last question:");
+        for q in &questions {
+            println!("{} #{} [{}] {}", if on_path(q.id) { "*" } else { " " }, q.id, q.created_at, preview(q));
+        }
+    }
+    let answers = branches::children(&store, chat_id, Some(last.id)).unwrap_or_default();
+    println!("\nAnswers to #{}:", last.id);
+    if answers.is_empty() {
+        println!("  (none yet, run `yo regen`)");
+    }
+    for a in &answers {
+        println!("{} #{} [{}] {}", if on_path(a.id) { "*" } else { " " }, a.id, a.created_at, preview(a));
+    }
+    println!("\nMake one current with `yo pick <message-id>`");
+}
+
+fn load_profile(store: &ChatStore) -> Vec<(String, String)> {
+    store.profile().unwrap_or_default().into_iter().map(|p| (p.key, p.value)).collect()
+}
+
+/// Ask the model to pull durable user facts out of a chat and store approved ones in the profile
+pub async fn extract_memories(chat_id: Option<i64>, yes: bool) {
+    let chat_id = match chat_id.or_else(get_current_chat_id) {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let transcript: String = match branches::active_path(&store, chat_id) {
+        Ok(turns) => turns
+            .iter()
+            .map(|t| {
+                let who = match t.role.as_str() { "user" => "User", "assistant" => "AI", _ => &t.role };
+                format!("{}: {}\n", who, t.content)
+            })
+            .collect(),
+        Err(e) => { eprintln!("Failed to read chat: {}", e); return; }
+    };
+    if transcript.is_empty() {
+        println!("Chat {} has no messages to learn from.", chat_id);
+        return;
+    }
+
+    let cfg = load_or_create_config();
+    let conversation = Conversation::build(memory::EXTRACTION_PROMPT, &[], Vec::new(), Some(&transcript));
+    let reply = match complete_quietly(&cfg, &conversation).await {
+        Ok(r) => r,
+        Err(e) => { eprintln!("Failed to extract memories: {}", e); return; }
+    };
+    let proposals = memory::propose(memory::parse_facts(&reply), &load_profile(&store));
+    if proposals.is_empty() {
+        println!("🧠 Nothing new to remember.");
+        return;
+    }
+
+    let auto_accept = yes || cfg.auto_accept_memories == Some(true);
+    println!("🧠 Proposed profile entries:");
+    for p in &proposals {
+        match p {
+            Proposal::Add { key, value } => println!("  + {} = {}", key, value),
+            Proposal::Update { key, old, value } => println!("  ~ {} = {} (was: {})", key, value, old),
+        }
+    }
+    let mut saved = 0;
+    for p in &proposals {
+        if !auto_accept {
+            print!("Save {} = {}? (y/N): ", p.key(), p.value());
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).unwrap();
+            if input.trim().to_lowercase() != "y" {
+                continue;
+            }
+        }
+        match store.set_profile(p.key(), p.value()) {
+            Ok(_) => saved += 1,
+            Err(e) => eprintln!("Failed to save {}: {}", p.key(), e),
+        }
     }
+    println!("✅ Saved {} of {} profile entries", saved, proposals.len());
 }
 
 /// Show information about the current model in use
@@ -447,135 +990,733 @@ pub fn show_current() {
         }
     }
     
-    println!("\n💡 Use 'yo list' to see all available models");
-}
-
-const CURRENT_CHAT_FILE: &str = "current_chat";
-
-fn set_current_chat_id(chat_id: i64) {
-    let config_dir = dirs::home_dir().unwrap().join(".config").join("yo");
-    let file_path = config_dir.join(CURRENT_CHAT_FILE);
-    let _ = fs::write(file_path, chat_id.to_string());
-}
-
-fn get_current_chat_id() -> Option<i64> {
-    let config_dir = dirs::home_dir().unwrap().join(".config").join("yo");
-    let file_path = config_dir.join(CURRENT_CHAT_FILE);
-    if let Ok(s) = fs::read_to_string(file_path) {
-        s.trim().parse().ok()
-    } else {
-        None
+    match current_chat() {
+        (Some(chat_id), scope) => {
+            println!("\n💬 Current chat: {} (from {})", chat_id, scope);
+            if let Ok(store) = ChatStore::open() {
+                if let Ok(Some(settings)) = chats::settings(&store, chat_id) {
+                    println!("Chat model:   {}", settings.label());
+                }
+            }
+        }
+        (None, Scope::Project(dir)) => println!("\n📁 Project {} has no chat yet; the next ask starts one", dir.display()),
+        (None, _) => println!("\n💬 No current chat; the next ask starts one"),
     }
+
+    println!("\n💡 Use 'yo list' to see all available models");
 }
 
-pub fn new_chat(title: Option<String>) {
-    let conn = match init_db() {
-        Ok(c) => c,
-        Err(e) => { eprintln!("DB error: {}", e); return; }
+/// Change the model and parameters of one chat without touching config.toml.
+/// For `temperature` and `max_tokens`, None keeps the current value and Some(None) clears it.
+pub fn set_chat_model(chat_id: Option<i64>, model: Option<String>, backend: Option<String>, temperature: Option<Option<f32>>, max_tokens: Option<Option<u32>>) {
+    let chat_id = match chat_id.or_else(get_current_chat_id) {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
     };
-    let title = title.unwrap_or_else(|| "New Chat".to_string());
-    let res = conn.execute(
-        "INSERT INTO chats (title) VALUES (?1)",
-        [&title],
-    );
-    match res {
-        Ok(_) => {
-            let chat_id = conn.last_insert_rowid();
-            set_current_chat_id(chat_id);
-            println!("✅ Started new chat '{}' (id: {})", title, chat_id);
-        },
-        Err(e) => eprintln!("Failed to create chat: {}", e),
+    if let Some(b) = backend.as_deref() {
+        if b != "openai" && b != "ollama" {
+            eprintln!("usage: --backend <ollama|openai>");
+            return;
+        }
     }
-}
-
-pub fn list_chats() {
-    let conn = match init_db() {
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    let mut stmt = match conn.prepare("SELECT id, title, created_at FROM chats ORDER BY created_at DESC") {
-        Ok(s) => s,
-        Err(e) => { eprintln!("Query error: {}", e); return; }
+    if !chats::exists(&store, chat_id).unwrap_or(false) {
+        eprintln!("Chat ID {} not found.", chat_id);
+        return;
+    }
+    let mut settings = match chats::settings(&store, chat_id) {
+        Ok(Some(s)) => s,
+        _ => load_or_create_config().settings(),
     };
-    let rows = stmt.query_map([], |row| {
-        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
-    });
-    match rows {
-        Ok(rows) => {
-            println!("\nChats:");
-            for row in rows.flatten() {
-                println!("  [{}] {} (created: {})", row.0, row.1, row.2);
+    match (backend, model) {
+        (Some(b), None) if b != settings.source => {
+            // the old model belongs to the other backend
+            let global = load_or_create_config().settings();
+            if global.source != b {
+                eprintln!("Which {} model? e.g. `yo chat-model <model> --backend {}`", b, b);
+                return;
             }
-        },
-        Err(e) => eprintln!("Failed to list chats: {}", e),
+            settings.source = b;
+            settings.model = global.model;
+        }
+        (backend, model) => {
+            if let Some(b) = backend {
+                settings.source = b;
+            }
+            if let Some(m) = model {
+                settings.model = m;
+            }
+        }
+    }
+    if let Some(t) = temperature {
+        settings.temperature = t;
+    }
+    if let Some(m) = max_tokens {
+        settings.max_tokens = m;
+    }
+    match chats::set_settings(&store, chat_id, &settings) {
+        Ok(_) => println!("✅ Chat {} now uses {}", chat_id, settings.label()),
+        Err(e) => eprintln!("Failed to update chat model: {}", e),
     }
 }
 
-pub fn switch_chat(chat_id: i64) {
-    let conn = match init_db() {
-        Ok(c) => c,
-        Err(e) => { eprintln!("DB error: {}", e); return; }
-    };
-    let mut stmt = match conn.prepare("SELECT id, title FROM chats WHERE id = ?1") {
-        Ok(s) => s,
-        Err(e) => { eprintln!("Query error: {}", e); return; }
-    };
-    let result = stmt.query_row([chat_id], |row| {
-        Ok(row.get::<_, String>(1)?)
-    });
-    match result {
-        Ok(title) => {
-            set_current_chat_id(chat_id);
-            println!("✅ Switched to chat [{}] {}", chat_id, title);
-        }
-        Err(rusqlite::Error::QueryReturnedNoRows) => {
-            eprintln!("Chat ID {} not found.", chat_id);
-        }
-        Err(e) => eprintln!("Failed to switch chat: {}", e),
+/// The project the working directory belongs to and the chat bound to it.
+/// None unless a directory here is bound or `project_chats` is on.
+fn project_binding(conn: &rusqlite::Connection) -> Option<(std::path::PathBuf, Option<i64>)> {
+    let cwd = project::normalize_dir(&std::env::current_dir().ok()?);
+    match project::binding_for(conn, &cwd) {
+        Ok(Some((dir, chat_id))) => return Some((dir, Some(chat_id))),
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to look up the project chat: {}", e),
+    }
+    if try_load_config().and_then(|c| c.project_chats) == Some(true) {
+        return Some((project::project_root(&cwd), None));
     }
+    None
 }
 
-pub fn set_profile(pair: &str) {
-    let parts: Vec<&str> = pair.splitn(2, '=').collect();
-    if parts.len() != 2 {
-        eprintln!("Invalid format. Use key=value");
-        return;
+/// chats.db for the project bindings alone. They aren't encrypted, so unlike `ChatStore::open`
+/// this never asks for the passphrase just to find the current chat.
+fn bindings_db() -> Option<rusqlite::Connection> {
+    match db::open(&paths::db_path()) {
+        Ok(conn) => Some(conn),
+        Err(e) => { eprintln!("DB error, ignoring project chats: {}", e); None }
     }
-    let key = parts[0].trim();
+}
+
+/// Make `chat_id` current: for the project when per-project chats apply, otherwise for this
+/// terminal session (if any) and globally, so new terminals start from the latest chat
+fn set_current_chat_id(chat_id: i64) {
+    if let Some(pinned) = session::pinned_chat().filter(|&p| p != chat_id) {
+        eprintln!("⚠️  {}={} still pins this shell to chat {}; run `export {}={}` or unset it", session::CHAT_VAR, pinned, pinned, session::CHAT_VAR, chat_id);
+    }
+    if let Some(conn) = bindings_db() {
+        if let Some((dir, _)) = project_binding(&conn) {
+            if let Err(e) = project::bind(&conn, &dir, chat_id) {
+                eprintln!("Failed to bind {} to chat {}: {}", dir.display(), chat_id, e);
+            }
+            return;
+        }
+    }
+    if let Some(id) = session::session_id() {
+        let dir = paths::sessions_dir();
+        let _ = session::prune(&dir, session::SESSION_TTL);
+        if let Err(e) = session::write_chat(&dir.join(&id), chat_id) {
+            eprintln!("Failed to save the current chat for session {}: {}", id, e);
+        }
+    }
+    let _ = session::write_chat(&paths::current_chat_path(), chat_id);
+}
+
+/// The current chat and the scope it comes from: $YO_CHAT, the project, this terminal
+/// session, then the global pointer. A project without a chat yet wins with no chat.
+fn current_chat() -> (Option<i64>, Scope) {
+    if let Some(chat_id) = session::pinned_chat() {
+        return (Some(chat_id), Scope::Env);
+    }
+    if let Some(conn) = bindings_db() {
+        if let Some((dir, chat_id)) = project_binding(&conn) {
+            return (chat_id, Scope::Project(dir));
+        }
+    }
+    if let Some(id) = session::session_id() {
+        if let Some(chat_id) = session::read_chat(&paths::sessions_dir().join(&id)) {
+            return (Some(chat_id), Scope::Session(id));
+        }
+    }
+    (session::read_chat(&paths::current_chat_path()), Scope::Global)
+}
+
+fn get_current_chat_id() -> Option<i64> {
+    current_chat().0
+}
+
+/// Print the shell hook that gives each terminal its own current chat
+pub fn shell_init(shell: &str) {
+    match session::hook(shell) {
+        Some(code) => print!("{}", code),
+        None => eprintln!("usage: yo shell-init <bash|zsh|fish>"),
+    }
+}
+
+/// Bind a directory (default: the current git root or directory) to a chat (default: the current one)
+pub fn bind_project(chat_id: Option<i64>, dir: Option<std::path::PathBuf>) {
+    let dir = match dir.or_else(|| std::env::current_dir().ok().map(|d| project::project_root(&project::normalize_dir(&d)))) {
+        Some(d) => project::normalize_dir(&d),
+        None => { eprintln!("Could not determine the current directory."); return; }
+    };
+    if !dir.is_dir() {
+        eprintln!("{} is not a directory.", dir.display());
+        return;
+    }
+    let chat_id = match chat_id.or_else(get_current_chat_id) {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Pass a chat id or start a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    if !chats::exists(&store, chat_id).unwrap_or(false) {
+        eprintln!("Chat ID {} not found.", chat_id);
+        return;
+    }
+    match project::bind(&store, &dir, chat_id) {
+        Ok(_) => println!("📁 Bound {} to chat {}", dir.display(), chat_id),
+        Err(e) => eprintln!("Failed to bind directory: {}", e),
+    }
+}
+
+/// Remove the binding of a directory (default: the one that applies here)
+pub fn unbind_project(dir: Option<std::path::PathBuf>) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let dir = match dir {
+        Some(d) => project::normalize_dir(&d),
+        None => {
+            let cwd = match std::env::current_dir() {
+                Ok(d) => project::normalize_dir(&d),
+                Err(e) => { eprintln!("Could not determine the current directory: {}", e); return; }
+            };
+            match project::binding_for(&store, &cwd) {
+                Ok(Some((d, _))) => d,
+                Ok(None) => { println!("No directory here is bound to a chat."); return; }
+                Err(e) => { eprintln!("Failed to look up binding: {}", e); return; }
+            }
+        }
+    };
+    match project::unbind(&store, &dir) {
+        Ok(true) => {
+            println!("✅ Unbound {}", dir.display());
+            if try_load_config().and_then(|c| c.project_chats) == Some(true) {
+                println!("💡 project_chats is on, so the next ask here starts a new project chat");
+            }
+        }
+        Ok(false) => println!("{} is not bound to a chat.", dir.display()),
+        Err(e) => eprintln!("Failed to unbind directory: {}", e),
+    }
+}
+
+pub fn list_projects() {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match project::bindings(&store) {
+        Ok(rows) if rows.is_empty() => println!("No directories are bound to chats. Use `yo bind` in a project."),
+        Ok(rows) => {
+            println!("\nProject chats:");
+            for (path, chat_id, title) in rows {
+                let title = title.unwrap_or_else(|| "(deleted chat)".to_string());
+                println!("  {} → [{}] {}", path, chat_id, title);
+            }
+        }
+        Err(e) => eprintln!("Failed to list project chats: {}", e),
+    }
+}
+
+pub fn new_chat(title: Option<String>, persona_name: Option<&str>) {
+    let persona = match persona_name {
+        Some(name) => match find_persona(name) {
+            Some(p) => Some(p),
+            None => return,
+        },
+        None => None,
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let title = title.unwrap_or_else(|| "New Chat".to_string());
+    let mut settings = try_load_config().map(|c| c.settings());
+    if let (Some(p), Some(s)) = (&persona, settings.as_mut()) {
+        *s = p.settings(s);
+    }
+    match chats::create(&store, &title, settings.as_ref()) {
+        Ok(chat_id) => {
+            if let (Some(name), Some(p)) = (persona_name, &persona) {
+                if let Err(e) = chats::set_persona(&store, chat_id, name, &p.system_prompt) {
+                    eprintln!("Failed to apply persona: {}", e);
+                }
+            }
+            set_current_chat_id(chat_id);
+            println!("✅ Started new chat '{}' (id: {})", title, chat_id);
+            if let (Some(name), Some(s)) = (persona_name, &settings) {
+                println!("🎭 Persona '{}' with {}", name, s.label());
+            }
+        },
+        Err(e) => eprintln!("Failed to create chat: {}", e),
+    }
+}
+
+/// Show the current chat's system prompt, or replace it ("default" goes back to the built-in one)
+pub fn chat_system(text: Option<String>) {
+    let chat_id = match get_current_chat_id() {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match text.as_deref() {
+        None => println!("⚙️ System prompt: {}", chat_system_prompt(&store, chat_id)),
+        Some(t) => {
+            let prompt = if t == "default" { None } else { Some(t) };
+            match chats::set_system_prompt(&store, chat_id, prompt) {
+                Ok(true) => println!("✅ Updated system prompt for chat {}", chat_id),
+                Ok(false) => eprintln!("Chat ID {} not found.", chat_id),
+                Err(e) => eprintln!("Failed to update system prompt: {}", e),
+            }
+        }
+    }
+}
+
+/// Render a prompt template and send it through the normal ask path
+pub async fn run_template(name: &str, vars: &[String], no_history: bool, dry_run: bool, persona: Option<&str>) {
+    let path = match template::find(name) {
+        Some(p) => p,
+        None => {
+            eprintln!("Template '{}' not found in {}", name, template::templates_dir().display());
+            eprintln!("Create one with `yo templates new {}`", name);
+            return;
+        }
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) => { eprintln!("Failed to read {}: {}", path.display(), e); return; }
+    };
+    let mut vars = match template::parse_vars(vars) {
+        Ok(v) => v,
+        Err(e) => { eprintln!("{}", e); return; }
+    };
+    // Piped stdin is bound to {{input}}
+    if !vars.contains_key("input") && !io::stdin().is_terminal() {
+        let mut input = String::new();
+        if io::stdin().read_to_string(&mut input).is_ok() && !input.is_empty() {
+            vars.insert("input".to_string(), input);
+        }
+    }
+    let prompt = match template::render(&text, &vars, |p| fs::read_to_string(p)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("❌ Can't run template '{}': {}", name, e);
+            eprintln!("Pass variables with --var key=value (stdin is bound to 'input')");
+            return;
+        }
+    };
+    if dry_run {
+        println!("{}", prompt);
+        return;
+    }
+    ask(&[prompt], no_history, persona).await;
+}
+
+/// Answer every prompt of a JSONL/CSV file, appending one result per line to a JSONL file.
+/// Prompts already answered in the results file are skipped, so an interrupted run can be resumed.
+pub async fn run_batch(file: &std::path::Path, output: Option<std::path::PathBuf>, concurrency: usize, rate: Option<u32>, save: bool, persona: Option<&str>) {
+    let text = match fs::read_to_string(file) {
+        Ok(t) => t,
+        Err(e) => { eprintln!("Failed to read {}: {}", file.display(), e); return; }
+    };
+    let items = match batch::parse_file(file, &text) {
+        Ok(items) => items,
+        Err(e) => { eprintln!("Invalid batch file {}: {}", file.display(), e); return; }
+    };
+    let persona = match persona {
+        Some(name) => match find_persona(name) {
+            Some(p) => Some(p),
+            None => return,
+        },
+        None => None,
+    };
+    let mut base = load_or_create_config();
+    let default_system = match &persona {
+        Some(p) => {
+            base.apply(&p.settings(&base.settings()));
+            p.system_prompt.clone()
+        }
+        None => DEFAULT_SYSTEM_PROMPT.to_string(),
+    };
+
+    let output = output.unwrap_or_else(|| batch::default_output(file));
+    let previous = fs::read_to_string(&output).unwrap_or_default();
+    let answered = batch::answered_ids(&previous);
+    let total = items.len();
+    let pending: Vec<BatchItem> = items.into_iter().filter(|i| !answered.contains(&i.id)).collect();
+    let skipped = total - pending.len();
+    if skipped > 0 {
+        println!("⏭️ Skipping {} prompt(s) already answered in {}", skipped, output.display());
+    }
+    if pending.is_empty() {
+        println!("✅ Nothing to do");
+        return;
+    }
+
+    let mut out = match fs::OpenOptions::new().create(true).append(true).open(&output) {
+        Ok(f) => f,
+        Err(e) => { eprintln!("Failed to open {}: {}", output.display(), e); return; }
+    };
+    // A line cut off by an interruption must not swallow the next result
+    if !previous.is_empty() && !previous.ends_with('\n') {
+        let _ = writeln!(out);
+    }
+    let chat = if save {
+        let store = match ChatStore::open() {
+            Ok(c) => c,
+            Err(e) => { eprintln!("DB error: {}", e); return; }
+        };
+        let title = format!("batch {}", file.display());
+        match ensure_current_chat(&store, &title) {
+            Some(id) => Some((store, id)),
+            None => return,
+        }
+    } else {
+        None
+    };
+
+    println!("🚀 Running {} prompt(s), {} at a time", pending.len(), concurrency.max(1));
+    let limiter = RateLimiter::per_minute(rate);
+    let (base, default_system, limiter) = (&base, &default_system, &limiter);
+    let mut results = futures_util::stream::iter(pending)
+        .map(|item| async move {
+            let mut cfg = base.clone();
+            if let Some(m) = &item.model {
+                cfg.model = m.clone();
+            }
+            let mut started = std::time::Instant::now();
+            let outcome = match item.render() {
+                Ok(prompt) => {
+                    limiter.wait().await;
+                    started = std::time::Instant::now();
+                    let system = item.system.as_deref().unwrap_or(default_system);
+                    let conversation = Conversation::build(system, &[], Vec::new(), Some(&prompt));
+                    complete_with_usage(&cfg, &conversation).await.map(|r| (prompt, r)).map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            let (prompt, result) = match outcome {
+                Ok((prompt, (response, usage))) => (Some(prompt), BatchResult {
+                    id: item.id, line: item.line, model: cfg.model, response: Some(response), usage, error: None, elapsed_ms,
+                }),
+                Err(error) => (None, BatchResult {
+                    id: item.id, line: item.line, model: cfg.model, response: None, usage: None, error: Some(error), elapsed_ms,
+                }),
+            };
+            (prompt, result)
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let (mut ok, mut failed) = (0, 0);
+    while let Some((prompt, mut result)) = results.next().await {
+        // an answer that didn't make it into the chat is retried on the next run
+        if let (Some((store, chat_id)), Some(prompt), Some(response)) = (&chat, prompt, &result.response) {
+            if let Err(e) = save_batch_answer(store, *chat_id, &prompt, response) {
+                result.error = Some(format!("answered, but saving to the chat failed: {}", e));
+            }
+        }
+        match &result.error {
+            None => { ok += 1; println!("✅ [{}] {} ms", result.id, result.elapsed_ms); }
+            Some(e) => { failed += 1; eprintln!("❌ [{}] {}", result.id, e); }
+        }
+        if let Err(e) = serde_json::to_string(&result).map_err(io::Error::other).and_then(|l| writeln!(out, "{}", l)) {
+            eprintln!("Failed to write result for [{}]: {}", result.id, e);
+        }
+    }
+    println!("\nDone: {} answered, {} failed, {} skipped. Results in {}", ok, failed, skipped, output.display());
+    if failed > 0 {
+        println!("💡 Run the same command again to retry the failed prompts");
+    }
+}
+
+/// Add a batch prompt and its answer to the end of a chat's active branch
+fn save_batch_answer(conn: &rusqlite::Connection, chat_id: i64, prompt: &str, response: &str) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let parent = branches::active_head(&tx, chat_id)?;
+    let user_id = branches::append(&tx, chat_id, parent, "user", prompt)?;
+    branches::append(&tx, chat_id, Some(user_id), "assistant", response)?;
+    tx.commit()
+}
+
+pub fn list_templates() {
+    let all = template::list();
+    if all.is_empty() {
+        println!("No templates yet. Create one with `yo templates new <name>`");
+        println!("Templates live in {}", template::templates_dir().display());
+        return;
+    }
+    println!("\nTemplates:");
+    for (name, path) in all {
+        let vars = fs::read_to_string(&path).map(|t| template::required_vars(&t)).unwrap_or_default();
+        if vars.is_empty() {
+            println!("  {}", name);
+        } else {
+            println!("  {} (needs: {})", name, vars.join(", "));
+        }
+    }
+}
+
+pub fn show_template(name: &str) {
+    let path = match template::find(name) {
+        Some(p) => p,
+        None => { eprintln!("Template '{}' not found.", name); return; }
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            println!("# {}\n", path.display());
+            println!("{}", text);
+            let placeholders = template::placeholders(&text);
+            if !placeholders.is_empty() {
+                println!("\nPlaceholders:");
+                for p in placeholders {
+                    match p {
+                        template::Placeholder::Var { name, default: None } => println!("  {} (required)", name),
+                        template::Placeholder::Var { name, default: Some(d) } => println!("  {} (default: {})", name, d),
+                        template::Placeholder::File(f) => println!("  file:{}", f),
+                    }
+                }
+            }
+        }
+        Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+    }
+}
+
+pub fn new_template(name: &str) {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        eprintln!("invalid template name '{}'", name);
+        return;
+    }
+    if let Some(existing) = template::find(name) {
+        eprintln!("Template '{}' already exists at {}", name, existing.display());
+        return;
+    }
+    let dir = template::templates_dir();
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Failed to create {}: {}", dir.display(), e);
+        return;
+    }
+    let path = if std::path::Path::new(name).extension().is_some() { dir.join(name) } else { dir.join(format!("{}.md", name)) };
+    let starter = "Explain the following {{lang|Rust}} code:\n\n{{input}}\n";
+    if let Err(e) = fs::write(&path, starter) {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+        return;
+    }
+    if let Err(e) = open_in_editor(&path) {
+        eprintln!("Failed to open editor: {}", e);
+    }
+    println!("✅ Created template '{}' at {}", name, path.display());
+}
+
+pub fn list_personas() {
+    let all = persona::list();
+    if all.is_empty() {
+        println!("No personas yet. Create one with `yo personas new <name> --system \"...\"`");
+        return;
+    }
+    println!("\nPersonas:");
+    for (name, p, origin) in all {
+        let model = match (&p.source, &p.model) {
+            (Some(s), Some(m)) => format!(" [{}:{}]", s, m),
+            (None, Some(m)) => format!(" [{}]", m),
+            (Some(s), None) => format!(" [{}]", s),
+            (None, None) => String::new(),
+        };
+        let from = if origin == persona::Origin::Config { " (config.toml)" } else { "" };
+        let preview: String = p.system_prompt.lines().next().unwrap_or("").chars().take(60).collect();
+        println!("  {}{}{}: {}", name, model, from, preview);
+    }
+}
+
+pub fn show_persona(name: &str) {
+    let p = match find_persona(name) {
+        Some(p) => p,
+        None => return,
+    };
+    println!("Persona: {}", name);
+    println!("Backend: {}", p.source.as_deref().unwrap_or("(chat default)"));
+    println!("Model:   {}", p.model.as_deref().unwrap_or("(chat default)"));
+    if let Some(t) = p.temperature {
+        println!("Temperature: {}", t);
+    }
+    if let Some(n) = p.max_tokens {
+        println!("Max tokens:  {}", n);
+    }
+    println!("\nSystem prompt:\n{}", p.system_prompt);
+}
+
+pub fn new_persona(name: &str, system: Option<String>, persona: Persona) {
+    if let Err(e) = persona::validate_name(name) {
+        eprintln!("{}", e);
+        return;
+    }
+    if persona::persona_path(name).exists() {
+        eprintln!("Persona '{}' already exists. Use `yo personas edit {}`.", name, name);
+        return;
+    }
+    if let Some(b) = persona.source.as_deref() {
+        if b != "openai" && b != "ollama" {
+            eprintln!("usage: --backend <ollama|openai>");
+            return;
+        }
+    }
+    let system_prompt = match system {
+        Some(s) => s,
+        None => match edit_in_editor("") {
+            Ok(s) => s.trim().to_string(),
+            Err(e) => { eprintln!("Failed to open editor: {}", e); return; }
+        },
+    };
+    if system_prompt.is_empty() {
+        eprintln!("A persona needs a system prompt.");
+        return;
+    }
+    match persona::save(name, &Persona { system_prompt, ..persona }) {
+        Ok(path) => println!("✅ Created persona '{}' at {}", name, path.display()),
+        Err(e) => eprintln!("Failed to save persona: {}", e),
+    }
+}
+
+/// Open a persona file in $EDITOR; personas defined in config.toml are copied to personas/ first
+pub fn edit_persona(name: &str) {
+    let path = match persona::load(name) {
+        Ok(Some((_, persona::Origin::File))) => persona::persona_path(name),
+        Ok(Some((p, persona::Origin::Config))) => match persona::save(name, &p) {
+            Ok(path) => path,
+            Err(e) => { eprintln!("Failed to copy persona: {}", e); return; }
+        },
+        Ok(None) => { eprintln!("Persona '{}' not found.", name); return; }
+        Err(e) => { eprintln!("{}", e); return; }
+    };
+    if let Err(e) = open_in_editor(&path) {
+        eprintln!("Failed to open editor: {}", e);
+        return;
+    }
+    match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|s| persona::parse(&s)) {
+        Ok(_) => println!("✅ Saved persona '{}'", name),
+        Err(e) => eprintln!("⚠️ {} is not a valid persona anymore: {}", path.display(), e),
+    }
+}
+
+pub fn delete_persona(name: &str) {
+    let path = persona::persona_path(name);
+    match persona::load(name) {
+        Ok(Some((_, persona::Origin::File))) => match fs::remove_file(&path) {
+            Ok(_) => println!("✅ Deleted persona '{}'", name),
+            Err(e) => eprintln!("Failed to delete persona: {}", e),
+        },
+        Ok(Some((_, persona::Origin::Config))) => {
+            eprintln!("Persona '{}' is defined in {}; remove it there.", name, get_config_path().display());
+        }
+        Ok(None) => eprintln!("Persona '{}' not found.", name),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// Resolve `--tag` filters to the set of matching chat IDs; None when no filter was given
+fn tag_filter(conn: &rusqlite::Connection, tags: &[String]) -> rusqlite::Result<Option<Vec<i64>>> {
+    let tags = tags::normalize_tags(tags);
+    if tags.is_empty() {
+        return Ok(None);
+    }
+    tags::chats_with_tags(conn, &tags).map(Some)
+}
+
+fn format_tags(tags: &[String]) -> String {
+    tags.iter().map(|t| format!(" #{}", t)).collect()
+}
+
+pub fn list_chats(tags: &[String]) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let filter = match tag_filter(&store, tags) {
+        Ok(f) => f,
+        Err(e) => { eprintln!("Query error: {}", e); return; }
+    };
+    match store.chats() {
+        Ok(all) => {
+            println!("\nChats:");
+            for chat in all {
+                if filter.as_ref().is_some_and(|ids| !ids.contains(&chat.id)) {
+                    continue;
+                }
+                let chat_tags = tags::tags_for_chat(&store, chat.id).unwrap_or_default();
+                let model = match &chat.settings {
+                    Some(settings) => format!("{}, ", settings.label()),
+                    None => String::new(),
+                };
+                println!("  [{}] {}{} ({}created: {})", chat.id, chat.title, format_tags(&chat_tags), model, chat.created_at);
+            }
+        },
+        Err(e) => eprintln!("Failed to list chats: {}", e),
+    }
+}
+
+pub fn switch_chat(chat_id: i64, use_global: bool) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match store.chat(chat_id) {
+        Ok(Some(chat)) => {
+            set_current_chat_id(chat_id);
+            println!("✅ Switched to chat [{}] {}", chat_id, chat.title);
+            let global = try_load_config().map(|c| c.settings());
+            if use_global {
+                if let Some(global) = global {
+                    match chats::set_settings(&store, chat_id, &global) {
+                        Ok(_) => println!("⚙️ Chat now uses {}", global.label()),
+                        Err(e) => eprintln!("Failed to update chat model: {}", e),
+                    }
+                }
+            } else if let Some(settings) = chat.settings {
+                println!("⚙️ Restored {}", settings.label());
+                if global.is_some_and(|g| g != settings) {
+                    println!("💡 Run `yo switch-chat {} --use-global` to continue with your global model instead", chat_id);
+                }
+            }
+        }
+        Ok(None) => eprintln!("Chat ID {} not found.", chat_id),
+        Err(e) => eprintln!("Failed to switch chat: {}", e),
+    }
+}
+
+pub fn set_profile(pair: &str) {
+    let parts: Vec<&str> = pair.splitn(2, '=').collect();
+    if parts.len() != 2 {
+        eprintln!("Invalid format. Use key=value");
+        return;
+    }
+    let key = parts[0].trim();
     let value = parts[1].trim();
-    let conn = match init_db() {
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    let res = conn.execute(
-        "INSERT INTO user_profile (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value=excluded.value",
-        [key, value],
-    );
-    match res {
+    match store.set_profile(key, value) {
         Ok(_) => println!("✅ Set profile: {} = {}", key, value),
         Err(e) => eprintln!("Failed to set profile: {}", e),
     }
 }
 
 pub fn summarize_chat(chat_id: i64) {
-    let conn = match init_db() {
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    let mut stmt = match conn.prepare("SELECT role, content FROM messages WHERE chat_id = ?1 ORDER BY created_at ASC") {
-        Ok(s) => s,
-        Err(e) => { eprintln!("Query error: {}", e); return; }
-    };
-    let rows = stmt.query_map([chat_id], |row| {
-        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
-    });
-    match rows {
-        Ok(rows) => {
+    match branches::active_path(&store, chat_id) {
+        Ok(turns) => {
             let mut full_chat = String::new();
-            for row in rows.flatten() {
-                let (role, content) = row;
-                let who = match role.as_str() { "user" => "You", "assistant" => "AI", _ => &role };
-                full_chat.push_str(&format!("{}: {}\n", who, content));
+            for t in turns {
+                let who = match t.role.as_str() { "user" => "You", "assistant" => "AI", _ => &t.role };
+                full_chat.push_str(&format!("{}: {}\n", who, t.content));
             }
             println!("\n--- Chat #{} Summary (stub) ---\n{}\n-------------------------------\n", chat_id, full_chat);
             // TODO: Send full_chat to AI with a 'summarize' prompt and print the result
@@ -584,31 +1725,409 @@ pub fn summarize_chat(chat_id: i64) {
     }
 }
 
-pub fn search_chats(query: &str) {
-    let conn = match init_db() {
+/// Full-text search over all messages, best matches first. With `semantic`, messages
+/// are ranked by embedding similarity instead, embedding new messages first.
+pub async fn search_chats(query: &str, mut filter: SearchFilter, semantic: bool, json: bool) {
+    if let Some(role) = filter.role.take() {
+        filter.role = match role.to_lowercase().as_str() {
+            "user" | "you" => Some("user".to_string()),
+            "assistant" | "ai" => Some("assistant".to_string()),
+            "system" => Some("system".to_string()),
+            _ => { eprintln!("usage: --role <user|assistant|system>"); return; }
+        };
+    }
+    for date in [&filter.since, &filter.until].into_iter().flatten() {
+        if !search::valid_date(date) {
+            eprintln!("Invalid date '{}': use YYYY-MM-DD", date);
+            return;
+        }
+    }
+    filter.tags = tags::normalize_tags(&filter.tags);
+    filter.highlight = if json {
+        ("**".to_string(), "**".to_string())
+    } else if io::stdout().is_terminal() {
+        ("\x1b[1;33m".to_string(), "\x1b[0m".to_string())
+    } else {
+        ("[".to_string(), "]".to_string())
+    };
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    let sql = "SELECT chat_id, created_at, role, content FROM messages WHERE content LIKE ?1 ORDER BY chat_id, created_at";
-    let pattern = format!("%{}%", query);
-    let mut stmt = match conn.prepare(sql) {
-        Ok(s) => s,
-        Err(e) => { eprintln!("Query error: {}", e); return; }
+    let hits = if semantic {
+        let cfg = load_or_create_config();
+        let model = EmbeddingModel::from_config(&cfg);
+        if let Err(e) = index_embeddings(&store, &cfg, &model).await {
+            eprintln!("Failed to index messages: {}", e);
+            return;
+        }
+        let query_vector = match embed_texts(&cfg, &model, &[query.to_string()]).await {
+            Ok(mut v) => v.remove(0),
+            Err(e) => { eprintln!("Failed to embed query: {}", e); return; }
+        };
+        search::semantic(&store, &model.label(), &query_vector, &filter)
+    } else {
+        search::search(&store, query, &filter)
     };
-    let rows = stmt.query_map([pattern], |row| {
-        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
-    });
-    match rows {
-        Ok(rows) => {
-            println!("\n--- Search Results for '{}' ---", query);
-            for row in rows.flatten() {
-                let (chat_id, ts, role, content) = row;
-                let who = match role.as_str() { "user" => "You", "assistant" => "AI", _ => &role };
-                println!("[chat {}] [{}] {}: {}", chat_id, ts, who, content);
+    let hits = match hits {
+        Ok(h) => h,
+        Err(e) => { eprintln!("Failed to search chats: {}", e); return; }
+    };
+    if json {
+        match serde_json::to_string_pretty(&hits) {
+            Ok(s) => println!("{}", s),
+            Err(e) => eprintln!("Failed to encode results: {}", e),
+        }
+        return;
+    }
+    if hits.is_empty() {
+        println!("No messages match '{}'.", query);
+        return;
+    }
+    println!("\n🔎 {} result(s) for '{}'\n", hits.len(), query);
+    for hit in hits {
+        let who = match hit.role.as_str() { "user" => "You", "assistant" => "AI", other => other };
+        let title = hit.chat_title.as_deref().unwrap_or("(untitled)");
+        println!("[chat {}] {} · #{} {} · {}", hit.chat_id, title, hit.message_id, who, hit.created_at);
+        if let Some(context) = &hit.context {
+            println!("   ↳ in reply to: {}", context);
+        }
+        println!("   {}\n", hit.snippet.replace('\n', " "));
+    }
+}
+
+/// Write a backup archive of all yo state
+pub fn backup_state(output: &std::path::Path) {
+    match backup::create(&StatePaths::current(), output) {
+        Ok(manifest) => println!(
+            "✅ Backed up {} chat(s), {} message(s) and {} file(s) to {} (API keys left out)",
+            manifest.chats,
+            manifest.messages,
+            manifest.files.len(),
+            output.display()
+        ),
+        Err(e) => eprintln!("Backup failed: {}", e),
+    }
+}
+
+/// Restore a backup archive, asking whether to merge or replace only when there is existing state
+pub fn restore_state(archive: &std::path::Path, mode: Option<RestoreMode>) {
+    let scratch = match backup::TempDir::new("yo-restore") {
+        Ok(d) => d,
+        Err(e) => { eprintln!("Failed to create a temporary directory: {}", e); return; }
+    };
+    let manifest = match backup::unpack(archive, scratch.path()) {
+        Ok(m) => m,
+        Err(e) => { eprintln!("❌ Can't restore {}: {}", archive.display(), e); return; }
+    };
+    println!(
+        "📦 Backup from {} UTC (yo {}): {} chat(s), {} message(s)",
+        manifest.created_at, manifest.yo_version, manifest.chats, manifest.messages
+    );
+    if manifest.schema_version < SCHEMA_VERSION {
+        println!("   migrated from schema v{} to v{}", manifest.schema_version, SCHEMA_VERSION);
+    }
+    let paths = StatePaths::current();
+    let mode = match mode {
+        Some(m) => m,
+        None if !backup::has_state(&paths) => RestoreMode::Replace,
+        None if !io::stdin().is_terminal() => {
+            eprintln!("yo already has chats or a config here. Pass --merge or --replace.");
+            return;
+        }
+        None => {
+            println!("yo already has chats or a config here. [m]erge the backup in, [r]eplace everything, or [a]bort? (m/r/A): ");
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).unwrap();
+            match input.trim().to_lowercase().as_str() {
+                "m" | "merge" => RestoreMode::Merge,
+                "r" | "replace" => RestoreMode::Replace,
+                _ => { println!("Aborted."); return; }
             }
-            println!("-------------------------------\n");
+        }
+    };
+    match mode {
+        RestoreMode::Replace => match backup::replace(&paths, scratch.path()) {
+            Ok(Some(previous)) => println!("✅ Restored {} (previous database kept as {})", archive.display(), previous.display()),
+            Ok(None) => println!("✅ Restored {}", archive.display()),
+            Err(e) => eprintln!("Restore failed: {}", e),
+        },
+        RestoreMode::Merge => {
+            let store = match ChatStore::open() {
+                Ok(c) => c,
+                Err(e) => { eprintln!("DB error: {}", e); return; }
+            };
+            let restored = match db::open(&scratch.path().join("chats.db")) {
+                Ok(c) => c,
+                Err(e) => { eprintln!("Failed to open the backup database: {}", e); return; }
+            };
+            if let Err(e) = crypto::unlock(&restored, "the backup") {
+                eprintln!("Failed to open the backup database: {}", e);
+                return;
+            }
+            let stats = match backup::merge_chats(&store, &restored) {
+                Ok(s) => s,
+                Err(e) => { eprintln!("Merge failed, nothing was changed: {}", e); return; }
+            };
+            let mut files = (0, 0);
+            for (dir, name) in [(&paths.templates, "templates"), (&paths.personas, "personas")] {
+                match backup::copy_tree(&scratch.path().join(name), dir, false) {
+                    Ok((copied, skipped)) => files = (files.0 + copied, files.1 + skipped),
+                    Err(e) => eprintln!("Failed to restore {}: {}", name, e),
+                }
+            }
+            if !paths.config.exists() {
+                if let Err(e) = fs::copy(scratch.path().join("config.toml"), &paths.config) {
+                    eprintln!("Failed to restore config.toml: {}", e);
+                }
+            }
+            println!(
+                "✅ Merged {} chat(s) ({} message(s)) and {} memory fact(s); {} chat(s) were already here",
+                stats.chats, stats.messages, stats.memories, stats.skipped
+            );
+            if files.0 + files.1 > 0 {
+                println!("   {} template/persona file(s) added, {} kept as they were", files.0, files.1);
+            }
+        }
+    }
+}
+
+/// A passphrase to encrypt with: from YO_PASSPHRASE or passphrase_command, or typed twice
+fn new_passphrase() -> Option<String> {
+    let configured = std::env::var_os(crypto::PASSPHRASE_VAR).is_some_and(|v| !v.is_empty())
+        || try_load_config().and_then(|c| c.passphrase_command).is_some();
+    if !configured && !io::stdin().is_terminal() {
+        eprintln!("No passphrase: set {} or passphrase_command in config.toml, or run this in a terminal.", crypto::PASSPHRASE_VAR);
+        return None;
+    }
+    let passphrase = match crypto::passphrase("🔑 New passphrase: ") {
+        Ok(p) => p,
+        Err(e) => { eprintln!("{}", e); return None; }
+    };
+    if passphrase.is_empty() {
+        eprintln!("The passphrase can't be empty.");
+        return None;
+    }
+    if !configured {
+        match rpassword::prompt_password("🔑 Repeat it: ") {
+            Ok(again) if again == passphrase => {}
+            Ok(_) => { eprintln!("The passphrases don't match, nothing was changed."); return None; }
+            Err(e) => { eprintln!("Failed to read the passphrase: {}", e); return None; }
+        }
+    }
+    Some(passphrase)
+}
+
+/// Encrypt message content and system prompts in chats.db
+pub fn encrypt_db() {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match crypto::is_encrypted(&store) {
+        Ok(true) => { println!("🔒 chats.db is already encrypted"); return; }
+        Ok(false) => {}
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    }
+    let passphrase = match new_passphrase() {
+        Some(p) => p,
+        None => return,
+    };
+    match crypto::encrypt_all(&store, &passphrase) {
+        Ok(n) => {
+            println!("🔒 Encrypted chats.db ({} value(s))", n);
+            println!("   Keep the passphrase safe: without it these chats can't be read.");
+            let copies = paths::db_backups();
+            if !copies.is_empty() {
+                println!("⚠️  These older copies are not encrypted; delete them once you don't need them:");
+                for copy in copies {
+                    println!("   {}", copy.display());
+                }
+            }
+        }
+        Err(e) => eprintln!("Encryption failed: {}", e),
+    }
+}
+
+/// Decrypt chats.db and stop encrypting new messages
+pub fn decrypt_db() {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match crypto::decrypt_all(&store) {
+        Ok(n) => println!("🔓 Decrypted chats.db ({} value(s))", n),
+        Err(CryptoError::NotEncrypted) => println!("chats.db isn't encrypted"),
+        Err(e) => eprintln!("Decryption failed, nothing was changed: {}", e),
+    }
+}
+
+/// Store imported chats and report what changed, or would change on a dry run
+fn store_imported(source: &str, chats: &[import::ImportedChat], dry_run: bool) {
+    // a dry run only reads the import records, so it neither migrates nor unlocks chats.db
+    let conn = if dry_run {
+        let path = paths::db_path();
+        let opened = if path.exists() {
+            rusqlite::Connection::open_with_flags(&path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        } else {
+            rusqlite::Connection::open_in_memory()
+        };
+        opened.map_err(db::DbError::from)
+    } else {
+        db::init_db()
+    };
+    let conn = match conn {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let stats = match import::import(&conn, source, chats, dry_run) {
+        Ok(s) => s,
+        Err(e) => { eprintln!("Import failed, nothing was saved: {}", e); return; }
+    };
+    if dry_run {
+        for change in &stats.changes {
+            let what = if change.new_chat { "new chat" } else { "update" };
+            println!("  {} ({}, {} message(s))", change.title, what, change.messages);
+        }
+        println!(
+            "🔍 Dry run: would import {} new chat(s), update {}, {} already up to date ({} message(s))",
+            stats.created, stats.updated, stats.unchanged, stats.messages
+        );
+    } else {
+        println!(
+            "✅ Imported {} new chat(s), updated {}, {} already up to date ({} message(s) added)",
+            stats.created, stats.updated, stats.unchanged, stats.messages
+        );
+    }
+}
+
+/// Import the conversations of a ChatGPT data export. `path` is its
+/// conversations.json or the unzipped export directory.
+pub fn import_chatgpt(path: &std::path::Path, dry_run: bool) {
+    let file = if path.is_dir() { path.join("conversations.json") } else { path.to_path_buf() };
+    let text = match fs::read_to_string(&file) {
+        Ok(t) => t,
+        Err(e) => { eprintln!("Failed to read {}: {}", file.display(), e); return; }
+    };
+    let chats = match import::parse_chatgpt(&text) {
+        Ok(c) => c,
+        Err(e) => { eprintln!("{} is not a ChatGPT export: {}", file.display(), e); return; }
+    };
+    store_imported("chatgpt", &chats, dry_run);
+}
+
+/// Import the logs.db of Simon Willison's `llm`, by default from where `llm logs path` puts it
+pub fn import_llm(path: Option<std::path::PathBuf>, dry_run: bool) {
+    let file = match path.or_else(|| {
+        std::env::var_os("LLM_USER_PATH")
+            .map(std::path::PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|d| d.join("io.datasette.llm")))
+            .map(|d| d.join("logs.db"))
+    }) {
+        Some(f) => f,
+        None => { eprintln!("Could not find llm's logs.db, pass its path"); return; }
+    };
+    if !file.is_file() {
+        eprintln!("No llm log database at {}", file.display());
+        return;
+    }
+    let chats = rusqlite::Connection::open_with_flags(&file, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|conn| import::read_llm(&conn));
+    match chats {
+        Ok(chats) => store_imported("llm", &chats, dry_run),
+        Err(e) => eprintln!("Failed to read {}: {}", file.display(), e),
+    }
+}
+
+/// Import aichat sessions (*.yaml) and its messages.md log. `path` is one of those
+/// files, a sessions directory or aichat's config directory (the default).
+pub fn import_aichat(path: Option<std::path::PathBuf>, dry_run: bool) {
+    let path = match path.or_else(|| {
+        std::env::var_os("AICHAT_CONFIG_DIR")
+            .map(std::path::PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|d| d.join("aichat")))
+    }) {
+        Some(p) => p,
+        None => { eprintln!("Could not find aichat's config directory, pass its path"); return; }
+    };
+    let mut files = Vec::new();
+    if path.is_dir() {
+        let sessions = if path.join("sessions").is_dir() { path.join("sessions") } else { path.clone() };
+        if let Ok(entries) = fs::read_dir(&sessions) {
+            files.extend(entries.flatten().map(|e| e.path()));
+        }
+        files.push(path.join("messages.md"));
+    } else {
+        files.push(path.clone());
+    }
+    files.sort();
+    let mut chats = Vec::new();
+    for file in files.iter().filter(|f| f.is_file()) {
+        let ext = file.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        if !matches!(ext.as_str(), "yaml" | "yml" | "md") {
+            continue;
+        }
+        let text = match fs::read_to_string(file) {
+            Ok(t) => t,
+            Err(e) => { eprintln!("Failed to read {}: {}", file.display(), e); continue; }
+        };
+        if ext == "md" {
+            chats.extend(import::parse_aichat_log(&text));
+            continue;
+        }
+        let name = file.file_stem().and_then(|s| s.to_str()).unwrap_or("session");
+        let modified = fs::metadata(file)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .and_then(|d| import::format_unix(d.as_secs_f64()));
+        match import::parse_aichat_session(name, &text, modified) {
+            Ok(chat) => chats.push(chat),
+            Err(e) => eprintln!("Skipping {}: {}", file.display(), e),
+        }
+    }
+    if chats.is_empty() {
+        eprintln!("No aichat sessions or messages.md found in {}", path.display());
+        return;
+    }
+    store_imported("aichat", &chats, dry_run);
+}
+
+/// Write one chat (or all of them) as Markdown, JSON or HTML, to a file or stdout
+pub fn export_chats(chat_id: Option<i64>, all: bool, format: &str, output: Option<std::path::PathBuf>) {
+    let format = match export::Format::parse(format) {
+        Some(f) => f,
+        None => { eprintln!("usage: --format <md|json|html>"); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let chats = if all {
+        export::load_all(&store)
+    } else {
+        let chat_id = match chat_id.or_else(get_current_chat_id) {
+            Some(id) => id,
+            None => { eprintln!("No current chat selected. Pass a chat id or --all."); return; }
+        };
+        match export::load_chat(&store, chat_id) {
+            Ok(Some(chat)) => Ok(vec![chat]),
+            Ok(None) => { eprintln!("Chat ID {} not found.", chat_id); return; }
+            Err(e) => Err(e),
+        }
+    };
+    let doc = match chats.and_then(|chats| export::document(&store, chats)) {
+        Ok(d) => d,
+        Err(e) => { eprintln!("Failed to read chats: {}", e); return; }
+    };
+    let rendered = export::render(&doc, format);
+    match output {
+        Some(path) => match fs::write(&path, rendered) {
+            Ok(_) => println!("✅ Exported {} chat(s) to {}", doc.chats.len(), path.display()),
+            Err(e) => eprintln!("Failed to write {}: {}", path.display(), e),
         },
-        Err(e) => eprintln!("Failed to search chats: {}", e),
+        None => print!("{}", rendered),
     }
 }
 
@@ -617,24 +2136,21 @@ pub fn view_chat() {
         Some(id) => id,
         None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
     };
-    let conn = match init_db() {
+    let store = match ChatStore::open() {
         Ok(c) => c,
         Err(e) => { eprintln!("DB error: {}", e); return; }
     };
-    let mut stmt = match conn.prepare("SELECT created_at, role, content FROM messages WHERE chat_id = ?1 ORDER BY created_at ASC") {
-        Ok(s) => s,
-        Err(e) => { eprintln!("Query error: {}", e); return; }
-    };
-    let rows = stmt.query_map([chat_id], |row| {
-        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
-    });
-    match rows {
-        Ok(rows) => {
+    let forks = branches::fork_points(&store, chat_id).unwrap_or_default();
+    match branches::active_path(&store, chat_id) {
+        Ok(turns) => {
             println!("\n--- Chat History (chat id: {}) ---", chat_id);
-            for row in rows.flatten() {
-                let (ts, role, content) = row;
-                let who = match role.as_str() { "user" => "You", "assistant" => "AI", _ => &role };
-                println!("[{}] {}: {}", ts, who, content);
+            for t in turns {
+                let who = match t.role.as_str() { "user" => "You", "assistant" => "AI", _ => &t.role };
+                let flag = if t.pinned { " 📌" } else if t.hidden { " (hidden)" } else { "" };
+                println!("#{} [{}]{} {}: {}", t.id, t.created_at, flag, who, t.content);
+                if let Some(n) = forks.get(&t.id) {
+                    println!("   ⑂ {} branches diverge here (see `yo branches`)", n);
+                }
             }
             println!("-------------------------------\n");
         },
@@ -642,45 +2158,245 @@ pub fn view_chat() {
     }
 }
 
+pub fn delete_message(message_id: i64) {
+    println!("Are you sure you want to delete message {}? This cannot be undone! (y/N): ", message_id);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    if input.trim().to_lowercase() != "y" {
+        println!("Aborted.");
+        return;
+    }
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match branches::delete_message(&store, message_id) {
+        Ok(true) => println!("✅ Deleted message {}", message_id),
+        Ok(false) => eprintln!("Message ID {} not found.", message_id),
+        Err(e) => eprintln!("Failed to delete message: {}", e),
+    }
+}
+
+/// Hide, pin or unpin a message. Hidden messages stay in the chat but are never sent
+/// to the model; pinned ones are always sent.
+pub fn set_message_flags(message_id: i64, hidden: bool, pinned: bool) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match branches::set_flags(&store, message_id, hidden, pinned) {
+        Ok(true) if pinned => println!("📌 Pinned message {}", message_id),
+        Ok(true) if hidden => println!("✅ Hid message {}", message_id),
+        Ok(true) => println!("✅ Message {} is back to normal", message_id),
+        Ok(false) => eprintln!("Message ID {} not found.", message_id),
+        Err(e) => eprintln!("Failed to update message: {}", e),
+    }
+}
+
+/// Send a hidden message to the model again. Unhiding a pinned message keeps the pin.
+pub fn unhide_message(message_id: i64) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match branches::unhide(&store, message_id) {
+        Ok(true) => println!("✅ Message {} is back in the context", message_id),
+        Ok(false) => eprintln!("Message ID {} not found.", message_id),
+        Err(e) => eprintln!("Failed to update message: {}", e),
+    }
+}
+
+/// Start a new branch from a message: the next question is asked as its reply
+pub fn fork(message_id: i64) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let chat_id = match branches::chat_of(&store, message_id) {
+        Ok(Some(id)) => id,
+        Ok(None) => { eprintln!("Message ID {} not found.", message_id); return; }
+        Err(e) => { eprintln!("Query error: {}", e); return; }
+    };
+    if let Err(e) = branches::set_head(&store, chat_id, Some(message_id)) {
+        eprintln!("Failed to fork: {}", e);
+        return;
+    }
+    set_current_chat_id(chat_id);
+    println!("⑂ Forked chat {} at message #{}. Your next question starts a new branch.", chat_id, message_id);
+}
+
+/// List the branches of the current chat
+pub fn list_branches() {
+    let chat_id = match get_current_chat_id() {
+        Some(id) => id,
+        None => { eprintln!("No current chat selected. Start or switch to a chat first."); return; }
+    };
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let head = branches::active_head(&store, chat_id).unwrap_or(None);
+    let leaves = match branches::leaves(&store, chat_id) {
+        Ok(l) => l,
+        Err(e) => { eprintln!("Failed to list branches: {}", e); return; }
+    };
+    if leaves.is_empty() {
+        println!("Chat {} has no messages yet.", chat_id);
+        return;
+    }
+    println!("\nBranches of chat {}:", chat_id);
+    if let Some(h) = head {
+        if !leaves.iter().any(|l| l.id == h) {
+            println!("* (new branch from #{})", h);
+        }
+    }
+    for leaf in &leaves {
+        let marker = if Some(leaf.id) == head { "*" } else { " " };
+        let depth = branches::path_to(&store, leaf.id).map(|p| p.len()).unwrap_or(0);
+        let preview: String = leaf.content.lines().next().unwrap_or("").chars().take(60).collect();
+        println!("{} #{} [{}] {} messages: {}", marker, leaf.id, leaf.created_at, depth, preview);
+    }
+    println!("\nSwitch with `yo switch-branch <message-id>`");
+}
+
+/// Make the branch containing a message the active one
+pub fn switch_branch(message_id: i64) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let chat_id = match branches::chat_of(&store, message_id) {
+        Ok(Some(id)) => id,
+        Ok(None) => { eprintln!("Message ID {} not found.", message_id); return; }
+        Err(e) => { eprintln!("Query error: {}", e); return; }
+    };
+    let leaf = match branches::latest_leaf_under(&store, message_id) {
+        Ok(id) => id,
+        Err(e) => { eprintln!("Query error: {}", e); return; }
+    };
+    if let Err(e) = branches::set_head(&store, chat_id, Some(leaf)) {
+        eprintln!("Failed to switch branch: {}", e);
+        return;
+    }
+    set_current_chat_id(chat_id);
+    println!("✅ Switched chat {} to the branch ending at message #{}", chat_id, leaf);
+}
+
 pub fn delete_chat(chat_id: i64) {
     println!("Are you sure you want to delete chat {}? This cannot be undone! (y/N): ", chat_id);
     io::stdout().flush().unwrap();
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
     if input.trim().to_lowercase() == "y" {
-        let conn = match init_db() {
+        let store = match ChatStore::open() {
             Ok(c) => c,
             Err(e) => { eprintln!("DB error: {}", e); return; }
         };
-        if let Err(e) = conn.execute("DELETE FROM messages WHERE chat_id = ?1", [chat_id]) {
-            eprintln!("Failed to delete chat messages: {}", e);
+        match store.delete_chats(&[chat_id]) {
+            Ok(0) => eprintln!("Chat ID {} not found.", chat_id),
+            Ok(_) => println!("✅ Deleted chat {}", chat_id),
+            Err(e) => eprintln!("Failed to delete chat {}: {}", chat_id, e),
         }
-        if let Err(e) = conn.execute("DELETE FROM chats WHERE id = ?1", [chat_id]) {
-            eprintln!("Failed to delete chat: {}", e);
+    } else {
+        println!("Aborted.");
+    }
+}
+
+/// Delete every chat carrying all of the given tags
+pub fn delete_chats_by_tag(tags: &[String]) {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    let ids = match tag_filter(&store, tags) {
+        Ok(Some(ids)) => ids,
+        Ok(None) => { eprintln!("No valid tags given."); return; }
+        Err(e) => { eprintln!("Query error: {}", e); return; }
+    };
+    if ids.is_empty() {
+        println!("No chats match the given tags.");
+        return;
+    }
+    println!("Are you sure you want to delete {} chat(s) tagged{}? This cannot be undone! (y/N): ", ids.len(), format_tags(&tags::normalize_tags(tags)));
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    if input.trim().to_lowercase() == "y" {
+        match store.delete_chats(&ids) {
+            Ok(n) => println!("✅ Deleted {} chat(s)", n),
+            Err(e) => eprintln!("Failed to delete chats: {}", e),
         }
-        println!("✅ Deleted chat {}", chat_id);
     } else {
         println!("Aborted.");
     }
 }
 
+pub fn tag_chat(chat_id: i64, tags: &[String]) {
+    let tags = tags::normalize_tags(tags);
+    if tags.is_empty() {
+        eprintln!("No valid tags given.");
+        return;
+    }
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match store.chat(chat_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => { eprintln!("Chat ID {} not found.", chat_id); return; }
+        Err(e) => { eprintln!("Query error: {}", e); return; }
+    }
+    match tags::add_tags(&store, chat_id, &tags) {
+        Ok(_) => println!("✅ Tagged chat {} with{}", chat_id, format_tags(&tags)),
+        Err(e) => eprintln!("Failed to tag chat: {}", e),
+    }
+}
+
+pub fn untag_chat(chat_id: i64, tags: &[String]) {
+    let tags = tags::normalize_tags(tags);
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match tags::remove_tags(&store, chat_id, &tags) {
+        Ok(0) => println!("Chat {} had none of those tags.", chat_id),
+        Ok(n) => println!("✅ Removed {} tag(s) from chat {}", n, chat_id),
+        Err(e) => eprintln!("Failed to untag chat: {}", e),
+    }
+}
+
+pub fn list_tags() {
+    let store = match ChatStore::open() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("DB error: {}", e); return; }
+    };
+    match tags::all_tags(&store) {
+        Ok(all) if all.is_empty() => println!("No tags yet. Add some with `yo tag <chat-id> <tag>...`"),
+        Ok(all) => {
+            println!("\nTags:");
+            for (name, count) in all {
+                println!("  #{} ({} chat{})", name, count, if count == 1 { "" } else { "s" });
+            }
+        }
+        Err(e) => eprintln!("Failed to list tags: {}", e),
+    }
+}
+
 pub fn clear_all_chats() {
     println!("Are you sure you want to delete ALL chats and messages? This cannot be undone! (y/N): ");
     io::stdout().flush().unwrap();
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
     if input.trim().to_lowercase() == "y" {
-        let conn = match init_db() {
+        let store = match ChatStore::open() {
             Ok(c) => c,
             Err(e) => { eprintln!("DB error: {}", e); return; }
         };
-        if let Err(e) = conn.execute("DELETE FROM messages", []) {
-            eprintln!("Failed to clear messages: {}", e);
-        }
-        if let Err(e) = conn.execute("DELETE FROM chats", []) {
-            eprintln!("Failed to clear chats: {}", e);
+        match store.clear_all() {
+            Ok(()) => println!("✅ All chats and messages deleted"),
+            Err(e) => eprintln!("Failed to delete chats: {}", e),
         }
-        println!("✅ All chats and messages deleted");
     } else {
         println!("Aborted.");
     }
@@ -1,31 +1,93 @@
+use crate::paths;
+use crate::persona::Persona;
+use crate::retention::Retention;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
-/// Resolve $XDG_CONFIG_HOME or fallback to ~/.config
-fn base_dir() -> PathBuf {
-    if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
-        PathBuf::from(xdg)
-    } else if let Some(home) = dirs::home_dir() {
-        home.join(".config")
-    } else {
-        panic!("could not determine config directory");
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Config {
     /// "openai" or "ollama"
     pub source: String,
     /// model ID, e.g. "gpt-4" or "llama3:latest"
     pub model: String,
     pub openai_api_key: Option<String>,
+    /// ask the model for durable user facts after every `yo ask`
+    pub extract_memories: Option<bool>,
+    /// save extracted facts without asking for approval
+    pub auto_accept_memories: Option<bool>,
+    /// send at most this many recent messages as context (pinned messages are always sent)
+    pub max_context_messages: Option<usize>,
+    /// default sampling temperature for new chats
+    pub temperature: Option<f32>,
+    /// default cap on generated tokens for new chats
+    pub max_tokens: Option<u32>,
+    /// give every git repository (or directory outside one) its own current chat
+    pub project_chats: Option<bool>,
+    /// model for `yo search --semantic`, e.g. "text-embedding-3-small" or "nomic-embed-text"
+    pub embedding_model: Option<String>,
+    /// command printing the passphrase for an encrypted chats.db, e.g. "pass show yo"
+    pub passphrase_command: Option<String>,
+    /// what `yo gc` deletes, as a `[retention]` table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<Retention>,
+    /// personas defined inline as `[personas.<name>]` tables
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub personas: BTreeMap<String, Persona>,
 }
 
-pub fn get_config_path() -> PathBuf {
-    let dir = base_dir().join("yo");
+/// Backend, model and generation parameters, remembered per chat
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelSettings {
+    pub source: String,
+    pub model: String,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+impl ModelSettings {
+    pub fn label(&self) -> String {
+        let mut s = format!("{}:{}", self.source, self.model);
+        if let Some(t) = self.temperature {
+            s.push_str(&format!(" t={}", t));
+        }
+        if let Some(n) = self.max_tokens {
+            s.push_str(&format!(" max={}", n));
+        }
+        s
+    }
+}
+
+impl Config {
+    /// The global settings new chats start with
+    pub fn settings(&self) -> ModelSettings {
+        ModelSettings {
+            source: self.source.clone(),
+            model: self.model.clone(),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+        }
+    }
+
+    /// Use a chat's settings instead of the global ones
+    pub fn apply(&mut self, settings: &ModelSettings) {
+        self.source = settings.source.clone();
+        self.model = settings.model.clone();
+        self.temperature = settings.temperature;
+        self.max_tokens = settings.max_tokens;
+    }
+}
+
+/// Directory holding config.toml and the personas/ and templates/ directories
+pub fn get_config_dir() -> PathBuf {
+    let dir = paths::config_dir();
     fs::create_dir_all(&dir).unwrap();
-    dir.join("config.toml")
+    dir
+}
+
+pub fn get_config_path() -> PathBuf {
+    get_config_dir().join("config.toml")
 }
 
 pub fn load_or_create_config() -> Config {
@@ -38,6 +100,12 @@ pub fn load_or_create_config() -> Config {
     toml::from_str(&s).expect("invalid config format")
 }
 
+/// Load the config if `yo setup` has been run, without exiting otherwise
+pub fn try_load_config() -> Option<Config> {
+    let s = fs::read_to_string(get_config_path()).ok()?;
+    toml::from_str(&s).ok()
+}
+
 pub fn save_config(cfg: &Config) {
     let path = get_config_path();
     let toml = toml::to_string_pretty(cfg).unwrap();
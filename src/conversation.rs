@@ -0,0 +1,102 @@
+use crate::store::Message;
+use crate::memory;
+use serde_json::Value;
+
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful AI assistant.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// Parse a role as stored in the `messages` table
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "system" => Some(Role::System),
+            "user" => Some(Role::User),
+            "assistant" => Some(Role::Assistant),
+            _ => None,
+        }
+    }
+
+    /// Role name in the OpenAI chat completions API
+    pub fn openai(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+
+    /// Speaker label in the plain-text transcript sent to `ollama run`
+    pub fn ollama(self) -> &'static str {
+        match self {
+            Role::System => "System",
+            Role::User => "User",
+            Role::Assistant => "AI",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+/// The exact list of messages sent to a model for one turn.
+///
+/// History is ordered by message id (not by the second-resolution `created_at`),
+/// and the new input is appended exactly once.
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    messages: Vec<ChatMessage>,
+}
+
+impl Conversation {
+    /// Assemble a conversation from a system prompt, the user's profile facts, prior
+    /// messages and the new input. `history` must not already contain `input`.
+    /// Messages with roles other than system/user/assistant are dropped.
+    pub fn build(system_prompt: &str, profile: &[(String, String)], mut history: Vec<Message>, input: Option<&str>) -> Self {
+        let mut system = system_prompt.trim().to_string();
+        if let Some(facts) = memory::profile_prompt(profile) {
+            if !system.is_empty() {
+                system.push_str("\n\n");
+            }
+            system.push_str(&facts);
+        }
+        let mut messages = Vec::new();
+        if !system.is_empty() {
+            messages.push(ChatMessage { role: Role::System, content: system });
+        }
+        history.sort_by_key(|t| t.id);
+        for t in history {
+            if let Some(role) = Role::parse(&t.role) {
+                messages.push(ChatMessage { role, content: t.content });
+            }
+        }
+        if let Some(input) = input {
+            messages.push(ChatMessage { role: Role::User, content: input.to_string() });
+        }
+        Conversation { messages }
+    }
+
+    /// Messages in the OpenAI chat completions format
+    pub fn openai_messages(&self) -> Vec<Value> {
+        self.messages
+            .iter()
+            .map(|m| serde_json::json!({"role": m.role.openai(), "content": m.content}))
+            .collect()
+    }
+
+    /// Conversation flattened into a transcript prompt for `ollama run`
+    pub fn ollama_prompt(&self) -> String {
+        self.messages
+            .iter()
+            .map(|m| format!("{}: {}\n", m.role.ollama(), m.content))
+            .collect()
+    }
+}
@@ -0,0 +1,248 @@
+use crate::config::try_load_config;
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fmt;
+use std::io::IsTerminal;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Supplies the passphrase without a prompt
+pub const PASSPHRASE_VAR: &str = "YO_PASSPHRASE";
+
+/// Start of every sealed value
+const PREFIX: &str = "yoenc:v1:";
+const NONCE_LEN: usize = 24;
+/// Poly1305 tag after the ciphertext
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+/// Sealed into the database so a wrong passphrase is caught before anything is read or written
+const CHECK: &str = "yo";
+
+#[derive(Debug)]
+pub enum CryptoError {
+    /// The database is encrypted and no passphrase was available
+    Locked,
+    WrongPassphrase,
+    AlreadyEncrypted,
+    NotEncrypted,
+    /// A sealed value that isn't in a format this yo can read
+    Corrupt,
+    Passphrase(String),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::Locked => write!(
+                f,
+                "chats.db is encrypted; set {} or passphrase_command in config.toml, or run yo in a terminal",
+                PASSPHRASE_VAR
+            ),
+            CryptoError::WrongPassphrase => write!(f, "wrong passphrase"),
+            CryptoError::AlreadyEncrypted => write!(f, "chats.db is already encrypted"),
+            CryptoError::NotEncrypted => write!(f, "chats.db isn't encrypted"),
+            CryptoError::Corrupt => write!(f, "an encrypted value is damaged or from a newer yo"),
+            CryptoError::Passphrase(e) => write!(f, "could not read the passphrase: {}", e),
+            CryptoError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+impl From<rusqlite::Error> for CryptoError {
+    fn from(e: rusqlite::Error) -> Self {
+        CryptoError::Sqlite(e)
+    }
+}
+
+/// XChaCha20-Poly1305 with a key derived from the passphrase by Argon2id
+#[derive(Clone)]
+pub struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Result<Cipher, CryptoError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| CryptoError::Passphrase(e.to_string()))?;
+        Ok(Cipher(XChaCha20Poly1305::new(Key::from_slice(&key))))
+    }
+
+    /// `yoenc:v1:` followed by the base64 of a random nonce and the ciphertext
+    pub fn seal(&self, text: &str) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut bytes = nonce.to_vec();
+        bytes.extend(self.0.encrypt(&nonce, text.as_bytes()).expect("encrypting in memory can't fail"));
+        format!("{}{}", PREFIX, STANDARD.encode(bytes))
+    }
+
+    pub fn open(&self, sealed: &str) -> Result<String, CryptoError> {
+        let bytes = sealed_bytes(sealed).ok_or(CryptoError::Corrupt)?;
+        let (nonce, data) = bytes.split_at(NONCE_LEN);
+        let plain = self.0.decrypt(XNonce::from_slice(nonce), data).map_err(|_| CryptoError::WrongPassphrase)?;
+        String::from_utf8(plain).map_err(|_| CryptoError::Corrupt)
+    }
+}
+
+/// Nonce, ciphertext and tag of a value in the format `Cipher::seal` writes
+fn sealed_bytes(text: &str) -> Option<Vec<u8>> {
+    let bytes = STANDARD.decode(text.strip_prefix(PREFIX)?).ok()?;
+    (bytes.len() >= NONCE_LEN + TAG_LEN).then_some(bytes)
+}
+
+/// Whether `text` has the exact shape of a sealed value. Text that merely starts
+/// with `yoenc:` doesn't.
+pub fn is_sealed(text: &str) -> bool {
+    sealed_bytes(text).is_some()
+}
+
+/// Salt and check value of an encrypted database
+fn stored_key(conn: &Connection) -> rusqlite::Result<Option<(String, String)>> {
+    conn.query_row("SELECT salt, check_value FROM encryption WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()
+}
+
+pub fn is_encrypted(conn: &Connection) -> rusqlite::Result<bool> {
+    Ok(stored_key(conn)?.is_some())
+}
+
+/// The key of an encrypted database, if `passphrase` is the right one
+pub fn key_for(conn: &Connection, passphrase: &str) -> Result<Cipher, CryptoError> {
+    let (salt, check) = stored_key(conn)?.ok_or(CryptoError::NotEncrypted)?;
+    let salt = STANDARD.decode(salt).map_err(|_| CryptoError::Corrupt)?;
+    let cipher = Cipher::derive(passphrase, &salt)?;
+    match cipher.open(&check) {
+        Ok(text) if text == CHECK => Ok(cipher),
+        Ok(_) | Err(CryptoError::WrongPassphrase) => Err(CryptoError::WrongPassphrase),
+        Err(e) => Err(e),
+    }
+}
+
+/// Define the SQL functions every query on message content goes through:
+/// `yo_seal(text)` encrypts with `cipher` (and leaves text alone without one),
+/// `yo_open(text)` decrypts sealed text. Whether a value is sealed follows from the
+/// database being encrypted, never from what the text looks like: in a plain
+/// database everything is returned as written, and in an encrypted one without
+/// `cipher` reading a sealed value fails.
+pub fn register(conn: &Connection, cipher: Option<Cipher>) -> rusqlite::Result<()> {
+    let locked = cipher.is_none() && is_encrypted(conn)?;
+    let sealer = cipher.clone();
+    conn.create_scalar_function("yo_seal", 1, FunctionFlags::SQLITE_UTF8, move |ctx| {
+        let text: Option<String> = ctx.get(0)?;
+        Ok(match (text, &sealer) {
+            (Some(t), Some(c)) => Some(c.seal(&t)),
+            (t, _) => t,
+        })
+    })?;
+    conn.create_scalar_function(
+        "yo_open",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let text: Option<String> = ctx.get(0)?;
+            let opened = match (text, &cipher) {
+                (Some(t), Some(c)) if is_sealed(&t) => c.open(&t).map(Some),
+                (Some(t), None) if locked && is_sealed(&t) => Err(CryptoError::Locked),
+                (t, _) => Ok(t),
+            };
+            opened.map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))
+        },
+    )
+}
+
+/// The passphrase from $YO_PASSPHRASE, from `passphrase_command` in config.toml,
+/// or typed at a prompt
+pub fn passphrase(prompt: &str) -> Result<String, CryptoError> {
+    if let Some(p) = std::env::var(PASSPHRASE_VAR).ok().filter(|p| !p.is_empty()) {
+        return Ok(p);
+    }
+    if let Some(cmd) = try_load_config().and_then(|c| c.passphrase_command) {
+        let out = Command::new("sh")
+            .args(["-c", &cmd])
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| CryptoError::Passphrase(e.to_string()))?;
+        if !out.status.success() {
+            return Err(CryptoError::Passphrase(format!("`{}` exited with {}", cmd, out.status)));
+        }
+        return Ok(String::from_utf8_lossy(&out.stdout).trim_end_matches(['\r', '\n']).to_string());
+    }
+    if std::io::stdin().is_terminal() {
+        return rpassword::prompt_password(prompt).map_err(|e| CryptoError::Passphrase(e.to_string()));
+    }
+    Err(CryptoError::Locked)
+}
+
+/// The key unlocked last, by salt, so one run asks for the passphrase only once
+static UNLOCKED: Mutex<Option<(String, Cipher)>> = Mutex::new(None);
+
+/// Make an open database readable: register the SQL functions, with the key if it's
+/// encrypted. `name` says which database the passphrase prompt is for.
+pub fn unlock(conn: &Connection, name: &str) -> Result<(), CryptoError> {
+    let Some((salt, _)) = stored_key(conn)? else {
+        return Ok(register(conn, None)?);
+    };
+    let cached = UNLOCKED.lock().unwrap().as_ref().filter(|(s, _)| *s == salt).map(|(_, c)| c.clone());
+    let cipher = match cached {
+        Some(c) => c,
+        None => {
+            let cipher = key_for(conn, &passphrase(&format!("🔑 Passphrase for {}: ", name))?)?;
+            *UNLOCKED.lock().unwrap() = Some((salt, cipher.clone()));
+            cipher
+        }
+    };
+    Ok(register(conn, Some(cipher))?)
+}
+
+/// Encrypt every message, chat title, system prompt and profile value with a key derived from `passphrase`,
+/// then drop the plaintext left in the search index and in free pages.
+/// Returns how many values were encrypted.
+pub fn encrypt_all(conn: &Connection, passphrase: &str) -> Result<usize, CryptoError> {
+    if is_encrypted(conn)? {
+        return Err(CryptoError::AlreadyEncrypted);
+    }
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = Cipher::derive(passphrase, &salt)?;
+    register(conn, Some(cipher.clone()))?;
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO encryption (id, salt, check_value) VALUES (1, ?1, ?2)",
+        params![STANDARD.encode(salt), cipher.seal(CHECK)],
+    )?;
+    // the search index triggers stop once the key is stored, so the index is emptied below
+    let mut sealed = tx.execute("UPDATE messages SET content = yo_seal(content) WHERE content IS NOT NULL", [])?;
+    sealed += tx.execute("UPDATE chats SET title = yo_seal(title) WHERE title IS NOT NULL", [])?;
+    sealed += tx.execute("UPDATE chats SET system_prompt = yo_seal(system_prompt) WHERE system_prompt IS NOT NULL", [])?;
+    sealed += tx.execute("UPDATE user_profile SET value = yo_seal(value) WHERE value IS NOT NULL", [])?;
+    tx.execute("INSERT INTO messages_fts (messages_fts) VALUES ('delete-all')", [])?;
+    tx.commit()?;
+    conn.execute_batch("VACUUM")?;
+    Ok(sealed)
+}
+
+/// Decrypt everything again on a connection unlocked with the key, forget the key and
+/// rebuild the search index. Returns how many values were decrypted.
+pub fn decrypt_all(conn: &Connection) -> Result<usize, CryptoError> {
+    if !is_encrypted(conn)? {
+        return Err(CryptoError::NotEncrypted);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let mut opened = tx.execute("UPDATE messages SET content = yo_open(content) WHERE content IS NOT NULL", [])?;
+    opened += tx.execute("UPDATE chats SET title = yo_open(title) WHERE title IS NOT NULL", [])?;
+    opened += tx.execute("UPDATE chats SET system_prompt = yo_open(system_prompt) WHERE system_prompt IS NOT NULL", [])?;
+    opened += tx.execute("UPDATE user_profile SET value = yo_open(value) WHERE value IS NOT NULL", [])?;
+    tx.execute("DELETE FROM encryption", [])?;
+    tx.execute("INSERT INTO messages_fts (messages_fts) VALUES ('rebuild')", [])?;
+    tx.commit()?;
+    register(conn, None)?;
+    Ok(opened)
+}
@@ -1,19 +1,137 @@
-use rusqlite::{Connection, Result};
-use std::path::PathBuf;
+use crate::crypto::{self, CryptoError};
+use crate::paths;
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-pub fn get_db_path() -> PathBuf {
-    let config_dir = dirs::home_dir().unwrap().join(".config").join("yo");
-    if !config_dir.exists() {
-        let _ = fs::create_dir_all(&config_dir);
+/// Why chats.db couldn't be opened
+#[derive(Debug)]
+pub enum DbError {
+    Sqlite(rusqlite::Error),
+    Io(std::io::Error),
+    /// A migration failed; the database was left as it was
+    Migration { version: i64, name: &'static str, source: rusqlite::Error },
+    /// The database was written by a newer yo with migrations this build doesn't know
+    TooNew { found: i64, supported: i64 },
+    /// The database is encrypted and couldn't be unlocked
+    Locked(CryptoError),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Sqlite(e) => write!(f, "{}", e),
+            DbError::Io(e) => write!(f, "{}", e),
+            DbError::Migration { version, name, source } => write!(f, "migration {} ({}) failed: {}", version, name, source),
+            DbError::TooNew { found, supported } => write!(
+                f,
+                "chats.db has schema version {} but this yo only supports up to {}; please upgrade yo",
+                found, supported
+            ),
+            DbError::Locked(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        DbError::Sqlite(e)
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(e: std::io::Error) -> Self {
+        DbError::Io(e)
     }
-    config_dir.join("chats.db")
 }
 
-pub fn init_db() -> Result<Connection> {
-    let db_path = get_db_path();
+/// Open chats.db, asking for the passphrase if it's encrypted
+pub fn init_db() -> std::result::Result<Connection, DbError> {
+    let conn = open(&paths::db_path())?;
+    crypto::unlock(&conn, "chats.db").map_err(DbError::Locked)?;
+    Ok(conn)
+}
+
+/// Open a database file, backing it up and migrating it if it's from an older yo
+pub fn open(db_path: &Path) -> std::result::Result<Connection, DbError> {
     let conn = Connection::open(db_path)?;
-    // Create tables if they don't exist
+    let version = schema_version(&conn)?;
+    if version < SCHEMA_VERSION {
+        let has_tables: bool =
+            conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table')", [], |row| row.get(0))?;
+        if has_tables {
+            let backup = backup_path(db_path, version);
+            fs::copy(db_path, &backup)?;
+            eprintln!("📦 Upgrading chats.db from schema v{} to v{} (backup: {})", version, SCHEMA_VERSION, backup.display());
+        }
+    }
+    init_schema(&conn)?;
+    Ok(conn)
+}
+
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Schema migrations in order. `PRAGMA user_version` holds how many have been applied.
+/// Never change or reorder a released migration, only append new ones.
+///
+/// Databases from before versioning are at version 0 but may already have some of
+/// the early changes, so the migrations up to `project_chats` are idempotent.
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("base tables", base_tables),
+    ("tags", tag_tables),
+    ("message branches", message_branches),
+    ("hidden and pinned messages", message_flags),
+    ("per-chat model settings and personas", chat_settings),
+    ("project chats", project_chats),
+    ("full-text search", message_search),
+    ("message embeddings", message_embeddings),
+    ("imported chats", imported_chats),
+    ("message models", message_models),
+    ("encryption", encryption),
+    ("foreign keys", foreign_keys),
+    ("search index follows the encryption state", index_by_encryption),
+];
+
+/// Schema version this build creates and understands
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+pub fn schema_version(conn: &Connection) -> Result<i64> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Apply pending migrations in one transaction, so a failed upgrade leaves the
+/// database untouched. Refuses databases from a newer yo. Then turns on foreign
+/// keys, so deleting a chat takes its messages, tags and bindings with it. Also
+/// defines the SQL functions for message content, without a key until `crypto::unlock`.
+pub fn init_schema(conn: &Connection) -> std::result::Result<(), DbError> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(DbError::TooNew { found: version, supported: SCHEMA_VERSION });
+    }
+    if version < SCHEMA_VERSION {
+        let tx = conn.unchecked_transaction()?;
+        for (i, (name, migration)) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            migration(&tx).map_err(|source| DbError::Migration { version: i as i64 + 1, name, source })?;
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+    }
+    // outside the transaction, where SQLite ignores it
+    conn.pragma_update(None, "foreign_keys", true)?;
+    crypto::register(conn, None)?;
+    Ok(())
+}
+
+/// Where the copy of a database is kept before upgrading it from `version`
+pub fn backup_path(db_path: &Path, version: i64) -> PathBuf {
+    let name = db_path.file_name().and_then(|n| n.to_str()).unwrap_or("chats.db");
+    db_path.with_file_name(format!("{}.v{}.bak", name, version))
+}
+
+fn base_tables(conn: &Connection) -> Result<()> {
     conn.execute_batch(
         r#"
         CREATE TABLE IF NOT EXISTS chats (
@@ -37,6 +155,346 @@ pub fn init_db() -> Result<Connection> {
             value TEXT
         );
         "#
+    )
+}
+
+fn tag_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS chat_tags (
+            chat_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY(chat_id, tag_id),
+            FOREIGN KEY(chat_id) REFERENCES chats(id),
+            FOREIGN KEY(tag_id) REFERENCES tags(id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_chat_tags_tag ON chat_tags(tag_id);
+        "#
     )?;
-    Ok(conn)
+    crate::tags::migrate_legacy_tags(conn)
+}
+
+fn message_branches(conn: &Connection) -> Result<()> {
+    if add_column_if_missing(conn, "messages", "parent_id", "INTEGER REFERENCES messages(id)")? {
+        // Existing chats were linear: link every message to the one before it
+        conn.execute_batch(
+            r#"
+            UPDATE messages SET parent_id = (
+                SELECT MAX(p.id) FROM messages p WHERE p.chat_id = messages.chat_id AND p.id < messages.id
+            );
+            "#
+        )?;
+    }
+    add_column_if_missing(conn, "chats", "head_message_id", "INTEGER")?;
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_messages_parent ON messages(parent_id);")
+}
+
+fn message_flags(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "hidden", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "messages", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn chat_settings(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "chats", "source", "TEXT")?;
+    add_column_if_missing(conn, "chats", "model", "TEXT")?;
+    add_column_if_missing(conn, "chats", "temperature", "REAL")?;
+    add_column_if_missing(conn, "chats", "max_tokens", "INTEGER")?;
+    add_column_if_missing(conn, "chats", "persona", "TEXT")?;
+    Ok(())
+}
+
+fn project_chats(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_chats (
+            path TEXT PRIMARY KEY,
+            chat_id INTEGER NOT NULL,
+            FOREIGN KEY(chat_id) REFERENCES chats(id)
+        );
+        "#
+    )
+}
+
+/// FTS5 index over message content, kept in sync with `messages` by triggers
+fn message_search(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE VIRTUAL TABLE messages_fts USING fts5(
+            content,
+            content = 'messages',
+            content_rowid = 'id',
+            tokenize = 'porter unicode61'
+        );
+        CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');
+        "#
+    )
+}
+
+/// Vectors for semantic search, dropped when their message is deleted or edited
+fn message_embeddings(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE embeddings (
+            message_id INTEGER PRIMARY KEY REFERENCES messages(id),
+            model TEXT NOT NULL,
+            dims INTEGER NOT NULL,
+            vector BLOB NOT NULL
+        );
+        CREATE INDEX idx_embeddings_model ON embeddings(model);
+        CREATE TRIGGER embeddings_delete AFTER DELETE ON messages BEGIN
+            DELETE FROM embeddings WHERE message_id = old.id;
+        END;
+        CREATE TRIGGER embeddings_update AFTER UPDATE OF content ON messages BEGIN
+            DELETE FROM embeddings WHERE message_id = old.id;
+        END;
+        "#
+    )
+}
+
+/// Where imported chats came from, so importing the same data again adds nothing
+fn imported_chats(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE imports (
+            source TEXT NOT NULL,
+            external_id TEXT NOT NULL,
+            chat_id INTEGER NOT NULL REFERENCES chats(id),
+            last_external_message TEXT,
+            last_message_id INTEGER,
+            imported_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (source, external_id)
+        );
+        "#
+    )
+}
+
+/// Model that wrote a message, kept for imported history
+fn message_models(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "model", "TEXT")?;
+    Ok(())
+}
+
+/// Key check for databases encrypted with `yo db encrypt`. Sealed text (`yoenc:*`)
+/// stays out of the search index, and sealing a message keeps its embedding.
+fn encryption(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE encryption (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            salt TEXT NOT NULL,
+            check_value TEXT NOT NULL
+        );
+        DROP TRIGGER messages_fts_insert;
+        DROP TRIGGER messages_fts_delete;
+        DROP TRIGGER messages_fts_update;
+        DROP TRIGGER embeddings_update;
+        CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages WHEN new.content NOT GLOB 'yoenc:*' BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages WHEN old.content NOT GLOB 'yoenc:*' BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content)
+                SELECT 'delete', old.id, old.content WHERE old.content NOT GLOB 'yoenc:*';
+            INSERT INTO messages_fts (rowid, content)
+                SELECT new.id, new.content WHERE new.content NOT GLOB 'yoenc:*';
+        END;
+        CREATE TRIGGER embeddings_update AFTER UPDATE OF content ON messages
+        WHEN (old.content GLOB 'yoenc:*') = (new.content GLOB 'yoenc:*') BEGIN
+            DELETE FROM embeddings WHERE message_id = old.id;
+        END;
+        "#
+    )
+}
+
+/// Enforce the foreign keys with ON DELETE CASCADE. SQLite can't add constraints
+/// to a table, so the tables are rebuilt, minus rows left behind by chats and
+/// messages deleted before. Runs with foreign keys off, as they are during migrations.
+fn foreign_keys(conn: &Connection) -> Result<()> {
+    // deleted through the triggers, so the search index and embeddings follow
+    conn.execute_batch(
+        r#"
+        DELETE FROM messages WHERE chat_id IS NULL OR chat_id NOT IN (SELECT id FROM chats);
+        UPDATE messages SET parent_id = NULL WHERE parent_id NOT IN (SELECT id FROM messages);
+        DELETE FROM chat_tags WHERE chat_id NOT IN (SELECT id FROM chats) OR tag_id NOT IN (SELECT id FROM tags);
+        DELETE FROM project_chats WHERE chat_id NOT IN (SELECT id FROM chats);
+        DELETE FROM imports WHERE chat_id NOT IN (SELECT id FROM chats);
+        UPDATE imports SET last_message_id = NULL WHERE last_message_id NOT IN (SELECT id FROM messages);
+        DELETE FROM embeddings WHERE message_id NOT IN (SELECT id FROM messages);
+        "#,
+    )?;
+    rebuild(
+        conn,
+        "messages",
+        r#"
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        parent_id INTEGER REFERENCES messages(id) ON DELETE SET NULL,
+        role TEXT,
+        content TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        hidden INTEGER NOT NULL DEFAULT 0,
+        pinned INTEGER NOT NULL DEFAULT 0,
+        model TEXT
+        "#,
+        "id, chat_id, parent_id, role, content, created_at, hidden, pinned, model",
+    )?;
+    rebuild(
+        conn,
+        "chat_tags",
+        r#"
+        chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY(chat_id, tag_id)
+        "#,
+        "chat_id, tag_id",
+    )?;
+    rebuild(
+        conn,
+        "project_chats",
+        "path TEXT PRIMARY KEY, chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE",
+        "path, chat_id",
+    )?;
+    rebuild(
+        conn,
+        "embeddings",
+        r#"
+        message_id INTEGER PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+        model TEXT NOT NULL,
+        dims INTEGER NOT NULL,
+        vector BLOB NOT NULL
+        "#,
+        "message_id, model, dims, vector",
+    )?;
+    rebuild(
+        conn,
+        "imports",
+        r#"
+        source TEXT NOT NULL,
+        external_id TEXT NOT NULL,
+        chat_id INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        last_external_message TEXT,
+        last_message_id INTEGER REFERENCES messages(id) ON DELETE SET NULL,
+        imported_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (source, external_id)
+        "#,
+        "source, external_id, chat_id, last_external_message, last_message_id, imported_at",
+    )?;
+    // the triggers and indexes went with the old tables; embeddings now cascade instead of a trigger
+    conn.execute_batch(
+        r#"
+        CREATE INDEX idx_messages_chat ON messages(chat_id);
+        CREATE INDEX idx_messages_parent ON messages(parent_id);
+        CREATE INDEX idx_chat_tags_tag ON chat_tags(tag_id);
+        CREATE INDEX idx_imports_chat ON imports(chat_id);
+        CREATE INDEX idx_embeddings_model ON embeddings(model);
+        CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages WHEN new.content NOT GLOB 'yoenc:*' BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages WHEN old.content NOT GLOB 'yoenc:*' BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content)
+                SELECT 'delete', old.id, old.content WHERE old.content NOT GLOB 'yoenc:*';
+            INSERT INTO messages_fts (rowid, content)
+                SELECT new.id, new.content WHERE new.content NOT GLOB 'yoenc:*';
+        END;
+        CREATE TRIGGER embeddings_update AFTER UPDATE OF content ON messages
+        WHEN (old.content GLOB 'yoenc:*') = (new.content GLOB 'yoenc:*') BEGIN
+            DELETE FROM embeddings WHERE message_id = old.id;
+        END;
+        "#,
+    )
+}
+
+/// Keep messages out of the search index while the database is encrypted, instead of
+/// skipping text that starts with `yoenc:`, which a user can type too. Rebuilds the
+/// index of a plain database, which left such messages out.
+fn index_by_encryption(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        DROP TRIGGER messages_fts_insert;
+        DROP TRIGGER messages_fts_delete;
+        DROP TRIGGER messages_fts_update;
+        DROP TRIGGER embeddings_update;
+        CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages
+        WHEN NOT EXISTS (SELECT 1 FROM encryption) BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages
+        WHEN NOT EXISTS (SELECT 1 FROM encryption) BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages
+        WHEN NOT EXISTS (SELECT 1 FROM encryption) BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        -- sealing and opening content happen while the key is stored, and keep the embedding
+        CREATE TRIGGER embeddings_update AFTER UPDATE OF content ON messages
+        WHEN NOT EXISTS (SELECT 1 FROM encryption) BEGIN
+            DELETE FROM embeddings WHERE message_id = old.id;
+        END;
+        "#,
+    )?;
+    let encrypted: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM encryption)", [], |row| row.get(0))?;
+    if !encrypted {
+        conn.execute("INSERT INTO messages_fts (messages_fts) VALUES ('rebuild')", [])?;
+    }
+    Ok(())
+}
+
+/// Replace `table` with one declared as `columns`, copying `copied` across.
+/// Keeps the AUTOINCREMENT counter, so ids of deleted rows are never reused.
+fn rebuild(conn: &Connection, table: &str, columns: &str, copied: &str) -> Result<()> {
+    let seq: Option<i64> =
+        conn.query_row("SELECT seq FROM sqlite_sequence WHERE name = ?1", [table], |row| row.get(0)).optional()?;
+    conn.execute_batch(&format!(
+        r#"
+        CREATE TABLE {table}_new ({columns});
+        INSERT INTO {table}_new ({copied}) SELECT {copied} FROM {table};
+        DROP TABLE {table};
+        ALTER TABLE {table}_new RENAME TO {table};
+        "#
+    ))?;
+    if let Some(seq) = seq {
+        let params = rusqlite::params![seq, table];
+        if conn.execute("UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = ?2", params)? == 0 {
+            conn.execute("INSERT INTO sqlite_sequence (seq, name) VALUES (?1, ?2)", params)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add a column to an existing table. Returns true if the column was added.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<bool> {
+    let exists = conn
+        .query_row(
+            &format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table),
+            [column],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl), [])?;
+    }
+    Ok(!exists)
 } 
\ No newline at end of file
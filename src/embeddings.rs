@@ -0,0 +1,101 @@
+use crate::config::Config;
+use rusqlite::{params, Connection, Result};
+
+/// Longest text sent for embedding; longer messages are embedded by their start
+pub const MAX_EMBED_CHARS: usize = 8000;
+
+/// Backend and model used to embed messages. Vectors from different models
+/// can't be compared, so every vector is stored with the model's label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingModel {
+    pub source: String,
+    pub model: String,
+}
+
+impl EmbeddingModel {
+    /// The configured embedding model, or the backend's default one
+    pub fn from_config(cfg: &Config) -> Self {
+        let model = cfg.embedding_model.clone().unwrap_or_else(|| {
+            match cfg.source.as_str() {
+                "ollama" => "nomic-embed-text",
+                _ => "text-embedding-3-small",
+            }
+            .to_string()
+        });
+        EmbeddingModel { source: cfg.source.clone(), model }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}:{}", self.source, self.model)
+    }
+}
+
+/// Vectors are stored as little-endian f32s
+pub fn encode(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn decode(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+/// Cosine similarity in [-1, 1]; 0 for empty or mismatched vectors
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 { 0.0 } else { dot / (na.sqrt() * nb.sqrt()) }
+}
+
+/// Text to embed for a message
+pub fn embed_text(content: &str) -> String {
+    content.chars().take(MAX_EMBED_CHARS).collect()
+}
+
+/// Messages without a vector from `model`, oldest first
+pub fn pending(conn: &Connection, model: &str, limit: usize) -> Result<Vec<(i64, String)>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT m.id, yo_open(m.content) FROM messages m
+        LEFT JOIN embeddings e ON e.message_id = m.id AND e.model = ?1
+        WHERE e.message_id IS NULL AND m.role IN ('user', 'assistant') AND TRIM(COALESCE(m.content, '')) != ''
+        ORDER BY m.id
+        LIMIT ?2
+        "#,
+    )?;
+    let rows = stmt.query_map(params![model, limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+pub fn count_pending(conn: &Connection, model: &str) -> Result<usize> {
+    conn.query_row(
+        r#"
+        SELECT COUNT(*) FROM messages m
+        LEFT JOIN embeddings e ON e.message_id = m.id AND e.model = ?1
+        WHERE e.message_id IS NULL AND m.role IN ('user', 'assistant') AND TRIM(COALESCE(m.content, '')) != ''
+        "#,
+        [model],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|n| n as usize)
+}
+
+/// Store a message's vector, replacing one from any other model
+pub fn store(conn: &Connection, message_id: i64, model: &str, vector: &[f32]) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO embeddings (message_id, model, dims, vector) VALUES (?1, ?2, ?3, ?4)",
+        params![message_id, model, vector.len() as i64, encode(vector)],
+    )?;
+    Ok(())
+}
+
+/// Drop every stored vector. Returns how many were removed.
+pub fn clear(conn: &Connection) -> Result<usize> {
+    conn.execute("DELETE FROM embeddings", [])
+}
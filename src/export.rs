@@ -0,0 +1,352 @@
+use crate::branches;
+use crate::chats;
+use crate::tags;
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+/// `schema` field of exported JSON documents
+pub const SCHEMA: &str = "yo.chat-export";
+/// Bumped whenever a field is removed or changes meaning; new optional fields don't bump it
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s.to_lowercase().as_str() {
+            "md" | "markdown" => Some(Format::Markdown),
+            "json" => Some(Format::Json),
+            "html" | "htm" => Some(Format::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Top-level JSON export, see "Export format" in the README
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportDocument {
+    pub schema: String,
+    pub version: u32,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`
+    pub exported_at: String,
+    pub chats: Vec<ExportedChat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedChat {
+    pub id: i64,
+    pub title: Option<String>,
+    pub created_at: Option<String>,
+    pub system_prompt: Option<String>,
+    pub persona: Option<String>,
+    pub source: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub tags: Vec<String>,
+    /// Last message of the active branch
+    pub head_message_id: Option<i64>,
+    /// Every message of the chat on all branches, oldest first
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedMessage {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+    pub hidden: bool,
+    pub pinned: bool,
+    /// Model that wrote the message, when known (imported history)
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl ExportedChat {
+    /// Messages of the active branch, oldest first
+    pub fn active_branch(&self) -> Vec<&ExportedMessage> {
+        let mut path = Vec::new();
+        let mut next = self.head_message_id;
+        while let Some(id) = next {
+            match self.messages.iter().find(|m| m.id == id) {
+                Some(m) if !path.iter().any(|p: &&ExportedMessage| p.id == m.id) => {
+                    path.push(m);
+                    next = m.parent_id;
+                }
+                _ => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    fn title(&self) -> &str {
+        self.title.as_deref().filter(|t| !t.is_empty()).unwrap_or("Untitled chat")
+    }
+
+    fn model_label(&self) -> Option<String> {
+        match (&self.source, &self.model) {
+            (Some(s), Some(m)) => Some(format!("{}:{}", s, m)),
+            _ => None,
+        }
+    }
+}
+
+/// Read one chat with all its messages, or None if it doesn't exist
+pub fn load_chat(conn: &Connection, chat_id: i64) -> Result<Option<ExportedChat>> {
+    let chat = conn
+        .query_row(
+            "SELECT id, yo_open(title), created_at, yo_open(system_prompt), persona FROM chats WHERE id = ?1",
+            [chat_id],
+            |row| {
+                Ok(ExportedChat {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    created_at: row.get(2)?,
+                    system_prompt: row.get(3)?,
+                    persona: row.get(4)?,
+                    source: None,
+                    model: None,
+                    temperature: None,
+                    max_tokens: None,
+                    tags: Vec::new(),
+                    head_message_id: None,
+                    messages: Vec::new(),
+                })
+            },
+        )
+        .optional()?;
+    let mut chat = match chat {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+    if let Some(settings) = chats::settings(conn, chat_id)? {
+        chat.source = Some(settings.source);
+        chat.model = Some(settings.model);
+        chat.temperature = settings.temperature;
+        chat.max_tokens = settings.max_tokens;
+    }
+    chat.tags = tags::tags_for_chat(conn, chat_id)?;
+    chat.head_message_id = branches::active_head(conn, chat_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, parent_id, role, yo_open(content), created_at, hidden, pinned, model FROM messages WHERE chat_id = ?1 ORDER BY id",
+    )?;
+    chat.messages = stmt
+        .query_map([chat_id], |row| {
+            Ok(ExportedMessage {
+                id: row.get(0)?,
+                parent_id: row.get(1)?,
+                role: row.get(2)?,
+                content: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                created_at: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                hidden: row.get(5)?,
+                pinned: row.get(6)?,
+                model: row.get(7)?,
+            })
+        })?
+        .collect::<Result<_>>()?;
+    Ok(Some(chat))
+}
+
+/// Every chat, oldest first
+pub fn load_all(conn: &Connection) -> Result<Vec<ExportedChat>> {
+    let mut stmt = conn.prepare("SELECT id FROM chats ORDER BY id")?;
+    let ids: Vec<i64> = stmt.query_map([], |row| row.get(0))?.collect::<Result<_>>()?;
+    let mut out = Vec::new();
+    for id in ids {
+        if let Some(chat) = load_chat(conn, id)? {
+            out.push(chat);
+        }
+    }
+    Ok(out)
+}
+
+pub fn document(conn: &Connection, chats: Vec<ExportedChat>) -> Result<ExportDocument> {
+    let exported_at: String = conn.query_row("SELECT datetime('now')", [], |row| row.get(0))?;
+    Ok(ExportDocument { schema: SCHEMA.to_string(), version: SCHEMA_VERSION, exported_at, chats })
+}
+
+pub fn render(doc: &ExportDocument, format: Format) -> String {
+    match format {
+        Format::Json => serde_json::to_string_pretty(doc).unwrap_or_default() + "\n",
+        Format::Markdown => to_markdown(doc),
+        Format::Html => to_html(doc),
+    }
+}
+
+fn speaker(role: &str) -> &str {
+    match role {
+        "user" => "You",
+        "assistant" => "AI",
+        "system" => "System",
+        other => other,
+    }
+}
+
+fn flags(m: &ExportedMessage) -> &'static str {
+    if m.pinned { " 📌" } else if m.hidden { " (hidden)" } else { "" }
+}
+
+/// Chat metadata as "label: value" pairs
+fn metadata(chat: &ExportedChat) -> Vec<(&'static str, String)> {
+    let mut out = vec![("Chat", chat.id.to_string())];
+    if let Some(c) = &chat.created_at {
+        out.push(("Created", c.clone()));
+    }
+    if let Some(m) = chat.model_label() {
+        out.push(("Model", m));
+    }
+    if let Some(p) = &chat.persona {
+        out.push(("Persona", p.clone()));
+    }
+    if !chat.tags.is_empty() {
+        out.push(("Tags", chat.tags.iter().map(|t| format!("#{}", t)).collect::<Vec<_>>().join(" ")));
+    }
+    out
+}
+
+/// Markdown with message content written verbatim, so code blocks survive
+pub fn to_markdown(doc: &ExportDocument) -> String {
+    let mut out = String::new();
+    for (i, chat) in doc.chats.iter().enumerate() {
+        if i > 0 {
+            out.push_str("\n---\n\n");
+        }
+        out.push_str(&format!("# {}\n\n", chat.title()));
+        for (label, value) in metadata(chat) {
+            out.push_str(&format!("- **{}:** {}\n", label, value));
+        }
+        if let Some(system) = chat.system_prompt.as_deref().filter(|s| !s.trim().is_empty()) {
+            out.push_str(&format!("\n## System prompt\n\n{}\n", system.trim_end()));
+        }
+        out.push_str("\n## Messages\n");
+        for m in chat.active_branch() {
+            out.push_str(&format!("\n### {} · {}{}\n\n{}\n", speaker(&m.role), m.created_at, flags(m), m.content.trim_end()));
+        }
+    }
+    out
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Message text as HTML: fenced code blocks become <pre>, the rest paragraphs with inline code
+fn content_html(content: &str) -> String {
+    let mut out = String::new();
+    let mut text = String::new();
+    let mut code: Option<(String, String)> = None;
+    let flush_text = |text: &mut String, out: &mut String| {
+        for para in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+            out.push_str(&format!("<p>{}</p>\n", inline_code(&escape_html(para)).replace('\n', "<br>\n")));
+        }
+        text.clear();
+    };
+    for line in content.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (&mut code, fence) {
+            (None, Some(lang)) => {
+                flush_text(&mut text, &mut out);
+                code = Some((lang.trim().to_string(), String::new()));
+            }
+            (Some((lang, body)), Some(_)) => {
+                let class = if lang.is_empty() { String::new() } else { format!(" class=\"language-{}\"", escape_html(lang)) };
+                out.push_str(&format!("<pre><code{}>{}</code></pre>\n", class, escape_html(body)));
+                code = None;
+            }
+            (Some((_, body)), None) => {
+                body.push_str(line);
+                body.push('\n');
+            }
+            (None, None) => {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+    }
+    // an unclosed fence still renders as code
+    if let Some((_, body)) = code {
+        out.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&body)));
+    }
+    flush_text(&mut text, &mut out);
+    out
+}
+
+/// Turn `code` spans of already-escaped text into <code> elements
+fn inline_code(escaped: &str) -> String {
+    let parts: Vec<&str> = escaped.split('`').collect();
+    if parts.len().is_multiple_of(2) {
+        // unbalanced backticks, leave as is
+        return escaped.to_string();
+    }
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, p)| if i % 2 == 1 { format!("<code>{}</code>", p) } else { p.to_string() })
+        .collect()
+}
+
+const HTML_STYLE: &str = r#"
+body { font: 16px/1.5 -apple-system, BlinkMacSystemFont, "Segoe UI", Helvetica, Arial, sans-serif; max-width: 860px; margin: 2rem auto; padding: 0 1rem; color: #1f2328; background: #fff; }
+h1 { margin-bottom: .25rem; }
+.meta { color: #59636e; font-size: .9rem; margin: 0 0 1.5rem; padding: 0; list-style: none; }
+.meta li { display: inline-block; margin-right: 1rem; }
+.system { border-left: 4px solid #d0d7de; padding: .25rem 1rem; color: #59636e; margin-bottom: 1.5rem; }
+.msg { border: 1px solid #d0d7de; border-radius: 8px; padding: .75rem 1rem; margin: 1rem 0; }
+.msg.user { background: #f6f8fa; }
+.msg.hidden { opacity: .6; }
+.who { font-weight: 600; font-size: .9rem; }
+.when { color: #59636e; font-size: .8rem; margin-left: .5rem; }
+pre { background: #0d1117; color: #e6edf3; padding: .75rem; border-radius: 6px; overflow-x: auto; }
+code { font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; font-size: .9em; }
+p code { background: #eff1f3; padding: .1em .3em; border-radius: 4px; }
+hr { border: 0; border-top: 1px solid #d0d7de; margin: 3rem 0; }
+footer { color: #59636e; font-size: .8rem; margin-top: 3rem; }
+"#;
+
+/// A single self-contained HTML page (inline CSS, no scripts or external assets)
+pub fn to_html(doc: &ExportDocument) -> String {
+    let title = match doc.chats.as_slice() {
+        [one] => one.title().to_string(),
+        _ => format!("{} chats", doc.chats.len()),
+    };
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape_html(&title),
+        HTML_STYLE
+    );
+    for (i, chat) in doc.chats.iter().enumerate() {
+        if i > 0 {
+            out.push_str("<hr>\n");
+        }
+        out.push_str(&format!("<article>\n<h1>{}</h1>\n<ul class=\"meta\">\n", escape_html(chat.title())));
+        for (label, value) in metadata(chat) {
+            out.push_str(&format!("<li><strong>{}:</strong> {}</li>\n", label, escape_html(&value)));
+        }
+        out.push_str("</ul>\n");
+        if let Some(system) = chat.system_prompt.as_deref().filter(|s| !s.trim().is_empty()) {
+            out.push_str(&format!("<div class=\"system\"><div class=\"who\">System prompt</div>\n{}</div>\n", content_html(system)));
+        }
+        for m in chat.active_branch() {
+            let class = if m.hidden { format!("{} hidden", m.role) } else { m.role.clone() };
+            out.push_str(&format!(
+                "<section class=\"msg {}\">\n<div><span class=\"who\">{}{}</span><span class=\"when\">{}</span></div>\n{}</section>\n",
+                escape_html(&class),
+                escape_html(speaker(&m.role)),
+                flags(m),
+                escape_html(&m.created_at),
+                content_html(&m.content)
+            ));
+        }
+        out.push_str("</article>\n");
+    }
+    out.push_str(&format!("<footer>Exported with yo on {} UTC</footer>\n</body>\n</html>\n", escape_html(&doc.exported_at)));
+    out
+}
@@ -0,0 +1,544 @@
+use crate::chats;
+use crate::config::ModelSettings;
+use crate::search::preview;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// A chat read from another tool, ready to be stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedChat {
+    /// The chat's id in the source, used to recognise it on later imports
+    pub external_id: String,
+    pub title: String,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`
+    pub created_at: Option<String>,
+    pub system_prompt: Option<String>,
+    /// Backend and model to continue the chat with, if yo supports the source's one
+    pub settings: Option<ModelSettings>,
+    /// One branch of the conversation, oldest first
+    pub messages: Vec<ImportedMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedMessage {
+    pub external_id: String,
+    pub role: String,
+    pub content: String,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`
+    pub created_at: Option<String>,
+    /// Model that wrote the message, as the source names it
+    pub model: Option<String>,
+}
+
+/// A chat an import created or added messages to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportChange {
+    pub title: String,
+    pub new_chat: bool,
+    pub messages: usize,
+}
+
+/// What an import did, or would do for a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    pub created: usize,
+    /// Chats imported before that got new messages
+    pub updated: usize,
+    pub unchanged: usize,
+    pub messages: usize,
+    pub changes: Vec<ImportChange>,
+}
+
+impl ImportStats {
+    fn add_new(&mut self, chat: &ImportedChat) {
+        self.created += 1;
+        self.messages += chat.messages.len();
+        self.changes.push(ImportChange { title: chat.title.clone(), new_chat: true, messages: chat.messages.len() });
+    }
+
+    fn add_update(&mut self, chat: &ImportedChat, added: usize) {
+        self.updated += 1;
+        self.messages += added;
+        self.changes.push(ImportChange { title: chat.title.clone(), new_chat: false, messages: added });
+    }
+}
+
+/// Unix time as `YYYY-MM-DD HH:MM:SS` UTC, the format SQLite's CURRENT_TIMESTAMP uses
+pub fn format_unix(secs: f64) -> Option<String> {
+    if !secs.is_finite() || secs < 0.0 {
+        return None;
+    }
+    let secs = secs as i64;
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    // days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    Some(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    ))
+}
+
+/// Days from 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// An ISO 8601 / RFC 3339 timestamp such as `2024-01-05T10:20:30.5+08:00` as
+/// `YYYY-MM-DD HH:MM:SS` UTC. Timestamps without an offset are taken as UTC.
+pub fn utc_timestamp(s: &str) -> Option<String> {
+    let s = s.trim();
+    let num = |range: std::ops::Range<usize>| s.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !matches!(s.get(10..11), Some("T") | Some("t") | Some(" ")) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let rest = s[19..].trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.get(0..1)? { "+" => 1, "-" => -1, _ => return None };
+            let hours = rest.get(1..3)?.parse::<i64>().ok()?;
+            let minutes = rest.get(3..).map(|m| m.trim_start_matches(':')).filter(|m| !m.is_empty()).map_or(Some(0), |m| m.parse::<i64>().ok())?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    format_unix(secs as f64)
+}
+
+/// Text of a ChatGPT message, or None for messages that aren't part of the visible conversation
+fn chatgpt_text(message: &Value) -> Option<String> {
+    let role = message["author"]["role"].as_str()?;
+    if !matches!(role, "user" | "assistant" | "system") {
+        return None;
+    }
+    if message["metadata"]["is_visually_hidden_from_conversation"].as_bool() == Some(true) {
+        return None;
+    }
+    let content = &message["content"];
+    let text = match content["content_type"].as_str() {
+        Some("text") | Some("multimodal_text") | None => content["parts"]
+            .as_array()?
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Some("code") => {
+            let lang = content["language"].as_str().filter(|l| *l != "unknown").unwrap_or("");
+            format!("```{}\n{}\n```", lang, content["text"].as_str()?.trim_end())
+        }
+        // browsing results, custom instructions, execution output and the like
+        Some(_) => return None,
+    };
+    if text.trim().is_empty() { None } else { Some(text) }
+}
+
+/// The node a conversation was left at: `current_node`, or else the leaf reached
+/// by always taking the newest reply from the root
+fn chatgpt_head(conv: &Value) -> Option<String> {
+    let mapping = conv["mapping"].as_object()?;
+    if let Some(current) = conv["current_node"].as_str().filter(|id| mapping.contains_key(*id)) {
+        return Some(current.to_string());
+    }
+    let mut node = mapping.iter().find(|(_, n)| n["parent"].is_null())?.0.clone();
+    while let Some(child) = mapping[&node]["children"].as_array().and_then(|c| c.last()).and_then(Value::as_str) {
+        if !mapping.contains_key(child) {
+            break;
+        }
+        node = child.to_string();
+    }
+    Some(node)
+}
+
+fn chatgpt_conversation(conv: &Value) -> Option<ImportedChat> {
+    let external_id = conv["conversation_id"].as_str().or(conv["id"].as_str())?.to_string();
+    let mapping = conv["mapping"].as_object()?;
+    let created_at = conv["create_time"].as_f64().and_then(format_unix);
+    // walk up from the head so only the branch the conversation was left on is kept
+    let mut messages = Vec::new();
+    let mut seen = HashSet::new();
+    let mut next = chatgpt_head(conv);
+    while let Some(id) = next {
+        if !seen.insert(id.clone()) {
+            break;
+        }
+        let node = match mapping.get(&id) {
+            Some(n) => n,
+            None => break,
+        };
+        let message = &node["message"];
+        if let Some(content) = chatgpt_text(message) {
+            messages.push(ImportedMessage {
+                external_id: id.clone(),
+                role: message["author"]["role"].as_str().unwrap_or("user").to_string(),
+                content,
+                created_at: message["create_time"].as_f64().and_then(format_unix).or_else(|| created_at.clone()),
+                model: message["metadata"]["model_slug"].as_str().map(String::from),
+            });
+        }
+        next = node["parent"].as_str().map(String::from);
+    }
+    messages.reverse();
+    let title = conv["title"].as_str().map(str::trim).filter(|t| !t.is_empty()).unwrap_or("Untitled chat");
+    Some(ImportedChat { external_id, title: title.to_string(), created_at, system_prompt: None, settings: None, messages })
+}
+
+/// Parse the `conversations.json` of a ChatGPT data export
+pub fn parse_chatgpt(json: &str) -> std::result::Result<Vec<ImportedChat>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let conversations = value.as_array().ok_or("expected a list of conversations")?;
+    Ok(conversations.iter().filter_map(chatgpt_conversation).collect())
+}
+
+/// Read the prompts and responses logged by `llm` (its logs.db). Responses of one
+/// conversation become one chat; responses logged outside a conversation a chat each.
+pub fn read_llm(conn: &Connection) -> Result<Vec<ImportedChat>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT r.id, r.conversation_id, c.name, r.model, r.prompt, r.system, r.response, r.datetime_utc
+        FROM responses r
+        LEFT JOIN conversations c ON c.id = r.conversation_id
+        ORDER BY r.datetime_utc, r.id
+        "#,
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut chats: Vec<ImportedChat> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let conversation: Option<String> = row.get(1)?;
+        let name: Option<String> = row.get(2)?;
+        let model: Option<String> = row.get(3)?;
+        let prompt: Option<String> = row.get(4)?;
+        let system: Option<String> = row.get(5)?;
+        let response: Option<String> = row.get(6)?;
+        let created_at = row.get::<_, Option<String>>(7)?.as_deref().and_then(utc_timestamp);
+
+        let key = conversation.unwrap_or_else(|| id.clone());
+        let i = *index.entry(key.clone()).or_insert_with(|| {
+            let title = name
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or_else(|| preview(prompt.as_deref().unwrap_or(""), 60));
+            chats.push(ImportedChat {
+                external_id: key,
+                title: if title.is_empty() { "Untitled chat".to_string() } else { title },
+                created_at: created_at.clone(),
+                system_prompt: None,
+                settings: None,
+                messages: Vec::new(),
+            });
+            chats.len() - 1
+        });
+        let chat = &mut chats[i];
+        if chat.system_prompt.is_none() {
+            chat.system_prompt = system.filter(|s| !s.trim().is_empty());
+        }
+        for (suffix, role, content, model) in [("prompt", "user", prompt, None), ("response", "assistant", response, model)] {
+            if let Some(content) = content.filter(|c| !c.trim().is_empty()) {
+                chat.messages.push(ImportedMessage {
+                    external_id: format!("{}:{}", id, suffix),
+                    role: role.to_string(),
+                    content,
+                    created_at: created_at.clone(),
+                    model,
+                });
+            }
+        }
+    }
+    Ok(chats)
+}
+
+/// Settings for an aichat model such as `openai:gpt-4o`, if yo has that backend
+fn aichat_settings(model: &str, temperature: Option<f32>) -> Option<ModelSettings> {
+    let (client, name) = model.split_once(':')?;
+    matches!(client, "openai" | "ollama").then(|| ModelSettings {
+        source: client.to_string(),
+        model: name.to_string(),
+        temperature,
+        max_tokens: None,
+    })
+}
+
+/// Text of an aichat message: a string or a list of parts of which the text ones are kept
+fn aichat_text(content: &serde_yaml::Value) -> Option<String> {
+    let text = match content {
+        serde_yaml::Value::String(s) => s.clone(),
+        serde_yaml::Value::Sequence(parts) => parts
+            .iter()
+            .filter(|p| p["type"].as_str() == Some("text"))
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return None,
+    };
+    if text.trim().is_empty() { None } else { Some(text) }
+}
+
+/// FNV-1a, a hash that stays the same across Rust versions and platforms
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Parse an aichat session file. Sessions don't record when messages were sent,
+/// so `modified` (the file's modification time) stands in for them.
+pub fn parse_aichat_session(name: &str, yaml: &str, modified: Option<String>) -> std::result::Result<ImportedChat, String> {
+    let session: serde_yaml::Value = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+    let model = session["model"].as_str().map(String::from);
+    let temperature = session["temperature"].as_f64().map(|t| t as f32);
+    let mut system_prompt = None;
+    let mut messages = Vec::new();
+    // compressed sessions keep their older messages apart; summaries of them are system messages
+    let history = ["compressed_messages", "messages"]
+        .iter()
+        .filter_map(|key| session[*key].as_sequence())
+        .flatten();
+    // Messages have no ids and compressing a session shifts their positions, so they are
+    // known by role and content, counting repeats of the same message
+    let mut seen: HashMap<(String, u64), usize> = HashMap::new();
+    for message in history {
+        let role = message["role"].as_str().unwrap_or("");
+        let content = match aichat_text(&message["content"]) {
+            Some(c) => c,
+            None => continue,
+        };
+        match role {
+            "system" if messages.is_empty() && system_prompt.is_none() => system_prompt = Some(content),
+            "user" | "assistant" => {
+                let hash = fnv1a(&content);
+                let n = seen.entry((role.to_string(), hash)).or_default();
+                *n += 1;
+                messages.push(ImportedMessage {
+                    external_id: format!("{}:{:016x}:{}", role, hash, n),
+                    role: role.to_string(),
+                    content,
+                    created_at: modified.clone(),
+                    model: if role == "assistant" { model.clone() } else { None },
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(ImportedChat {
+        external_id: format!("session:{}", name),
+        title: name.to_string(),
+        created_at: modified,
+        system_prompt,
+        settings: model.as_deref().and_then(|m| aichat_settings(m, temperature)),
+        messages,
+    })
+}
+
+/// Parse aichat's messages.md log, where every question and answer is saved as
+/// `# CHAT: <summary> [<timestamp>] (<scope>)`, the input, `--------`, the output and `--------`.
+/// Each entry becomes a chat of its own.
+pub fn parse_aichat_log(markdown: &str) -> Vec<ImportedChat> {
+    let mut chats = Vec::new();
+    let mut entries: Vec<(String, Vec<&str>)> = Vec::new();
+    for line in markdown.lines() {
+        match line.strip_prefix("# CHAT:") {
+            Some(header) => entries.push((header.trim().to_string(), Vec::new())),
+            None => {
+                if let Some((_, body)) = entries.last_mut() {
+                    body.push(line);
+                }
+            }
+        }
+    }
+    for (header, body) in entries {
+        let body = body.join("\n");
+        let mut parts = body.split("\n--------").map(|p| p.trim_matches('\n'));
+        let (input, output) = match (parts.next(), parts.next()) {
+            (Some(i), Some(o)) if !i.trim().is_empty() => (i.to_string(), o.to_string()),
+            _ => continue,
+        };
+        let timestamp = header
+            .rfind('[')
+            .and_then(|start| header[start + 1..].split(']').next())
+            .and_then(utc_timestamp);
+        let summary = header.split('[').next().unwrap_or("").trim();
+        let title = if summary.is_empty() { preview(&input, 60) } else { summary.to_string() };
+        let mut messages = vec![ImportedMessage {
+            external_id: "input".to_string(),
+            role: "user".to_string(),
+            content: input,
+            created_at: timestamp.clone(),
+            model: None,
+        }];
+        if !output.trim().is_empty() {
+            messages.push(ImportedMessage {
+                external_id: "output".to_string(),
+                role: "assistant".to_string(),
+                content: output,
+                created_at: timestamp.clone(),
+                model: None,
+            });
+        }
+        chats.push(ImportedChat {
+            external_id: format!("log:{}", header),
+            title,
+            created_at: timestamp,
+            system_prompt: None,
+            settings: None,
+            messages,
+        });
+    }
+    chats
+}
+
+fn insert_message(conn: &Connection, chat_id: i64, parent_id: Option<i64>, message: &ImportedMessage) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO messages (chat_id, parent_id, role, content, created_at, model) VALUES (?1, ?2, ?3, yo_seal(?4), COALESCE(?5, CURRENT_TIMESTAMP), ?6)",
+        params![chat_id, parent_id, message.role, message.content, message.created_at, message.model],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// The yo chat a source chat was imported into, with the last message imported so far
+struct PreviousImport {
+    chat_id: i64,
+    last_external_message: Option<String>,
+    /// None if that message was deleted since
+    last_message_id: Option<i64>,
+}
+
+fn previous_import(conn: &Connection, source: &str, external_id: &str) -> Result<Option<PreviousImport>> {
+    conn.query_row(
+        r#"
+        SELECT i.chat_id, i.last_external_message, m.id
+        FROM imports i
+        JOIN chats c ON c.id = i.chat_id
+        LEFT JOIN messages m ON m.id = i.last_message_id AND m.chat_id = i.chat_id
+        WHERE i.source = ?1 AND i.external_id = ?2
+        "#,
+        params![source, external_id],
+        |row| Ok(PreviousImport { chat_id: row.get(0)?, last_external_message: row.get(1)?, last_message_id: row.get(2)? }),
+    )
+    .optional()
+}
+
+fn record_import(conn: &Connection, source: &str, chat: &ImportedChat, chat_id: i64, last_message_id: Option<i64>) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO imports (source, external_id, chat_id, last_external_message, last_message_id)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT(source, external_id) DO UPDATE SET
+            chat_id = excluded.chat_id,
+            last_external_message = excluded.last_external_message,
+            last_message_id = excluded.last_message_id,
+            imported_at = CURRENT_TIMESTAMP
+        "#,
+        params![source, chat.external_id, chat_id, chat.messages.last().map(|m| &m.external_id), last_message_id],
+    )?;
+    Ok(())
+}
+
+/// Index of the first message of `chat` not imported yet. Only a continuation of
+/// what was imported can be appended to.
+fn first_new_message(chat: &ImportedChat, last_external_message: Option<&str>) -> usize {
+    match last_external_message {
+        Some(last) => match chat.messages.iter().position(|m| m.external_id == last) {
+            Some(i) => i + 1,
+            None => chat.messages.len(),
+        },
+        None => 0,
+    }
+}
+
+/// What importing chats from `source` would do, without writing anything. Works on a
+/// read-only connection to a database of any schema; one without an imports table
+/// has nothing imported yet.
+fn plan(conn: &Connection, source: &str, chats: &[ImportedChat]) -> Result<ImportStats> {
+    let has_imports: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'imports')",
+        [],
+        |row| row.get(0),
+    )?;
+    let mut stats = ImportStats::default();
+    for chat in chats.iter().filter(|c| !c.messages.is_empty()) {
+        let previous = if has_imports { previous_import(conn, source, &chat.external_id)? } else { None };
+        match previous {
+            Some(previous) => {
+                let start = first_new_message(chat, previous.last_external_message.as_deref());
+                if start >= chat.messages.len() {
+                    stats.unchanged += 1;
+                } else {
+                    stats.add_update(chat, chat.messages.len() - start);
+                }
+            }
+            None => stats.add_new(chat),
+        }
+    }
+    Ok(stats)
+}
+
+/// Store chats from `source` in one transaction. Chats imported before only get
+/// the messages added since, so importing the same data twice changes nothing.
+/// A dry run only reports, and reads nothing but the import records.
+pub fn import(conn: &Connection, source: &str, chats: &[ImportedChat], dry_run: bool) -> Result<ImportStats> {
+    if dry_run {
+        return plan(conn, source, chats);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let mut stats = ImportStats::default();
+    for chat in chats.iter().filter(|c| !c.messages.is_empty()) {
+        match previous_import(&tx, source, &chat.external_id)? {
+            Some(PreviousImport { chat_id, last_external_message, last_message_id }) => {
+                let start = first_new_message(chat, last_external_message.as_deref());
+                if start >= chat.messages.len() {
+                    stats.unchanged += 1;
+                    continue;
+                }
+                let mut parent = last_message_id;
+                for message in &chat.messages[start..] {
+                    parent = Some(insert_message(&tx, chat_id, parent, message)?);
+                }
+                tx.execute("UPDATE chats SET head_message_id = ?1 WHERE id = ?2", params![parent, chat_id])?;
+                record_import(&tx, source, chat, chat_id, parent)?;
+                stats.add_update(chat, chat.messages.len() - start);
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO chats (title, created_at) VALUES (yo_seal(?1), COALESCE(?2, CURRENT_TIMESTAMP))",
+                    params![chat.title, chat.created_at],
+                )?;
+                let chat_id = tx.last_insert_rowid();
+                if let Some(settings) = &chat.settings {
+                    chats::set_settings(&tx, chat_id, settings)?;
+                }
+                if chat.system_prompt.is_some() {
+                    chats::set_system_prompt(&tx, chat_id, chat.system_prompt.as_deref())?;
+                }
+                let mut parent = None;
+                for message in &chat.messages {
+                    parent = Some(insert_message(&tx, chat_id, parent, message)?);
+                }
+                tx.execute("UPDATE chats SET head_message_id = ?1 WHERE id = ?2", params![parent, chat_id])?;
+                record_import(&tx, source, chat, chat_id, parent)?;
+                stats.add_new(chat);
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(stats)
+}
@@ -1,4 +1,24 @@
+pub mod chats;
 pub mod cli;
 pub mod config;
+pub mod crypto;
 pub mod commands;
+pub mod conversation;
 pub mod db;
+pub mod embeddings;
+pub mod export;
+pub mod import;
+pub mod backup;
+pub mod batch;
+pub mod branches;
+pub mod memory;
+pub mod paths;
+pub mod session;
+pub mod persona;
+pub mod project;
+pub mod search;
+pub mod tags;
+pub mod template;
+pub mod repl;
+pub mod retention;
+pub mod store;
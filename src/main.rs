@@ -1,34 +1,137 @@
 mod cli;
 mod config;
+mod crypto;
 mod commands;
 mod db;
+mod persona;
+mod chats;
+mod conversation;
+mod branches;
+mod tags;
+mod memory;
+mod template;
+mod repl;
+mod retention;
+mod store;
+mod project;
+mod batch;
+mod search;
+mod embeddings;
+mod backup;
+mod export;
+mod import;
+mod paths;
+mod session;
 
 use clap::Parser;
-use cli::{Cli, Command};
+use cli::{Cli, Command, DbCommand, ImportSource, PersonaCommand, TemplateCommand};
 
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
+    if let Some(dir) = &args.data_dir {
+        paths::set_home(dir);
+    }
+    paths::move_legacy_data_on_use();
+    let persona = args.persona.as_deref();
+    let uses_history = args.command.as_ref().is_some_and(Command::uses_history);
     match args.command {
         Some(Command::Setup)            => commands::setup(),
         Some(Command::Config)           => commands::show_config_path(),
         Some(Command::Switch { model }) => commands::switch(&model).await,
         Some(Command::Gpt { model })    => commands::set_gpt(&model).await,
         Some(Command::List)             => commands::list_models().await,
-        Some(Command::Ask { question }) => commands::ask(&question).await,
+        Some(Command::Ask { question, no_history }) => commands::ask(&question, no_history, persona).await,
         Some(Command::Current)          => commands::show_current(),
-        Some(Command::Other(o))         => commands::ask(&o).await,
+        Some(Command::ShellInit { shell }) => commands::shell_init(&shell),
+        Some(Command::Other(o))         => commands::ask(&o, false, persona).await,
         Some(Command::ClearHistory)     => commands::clear_history(),
-        Some(Command::NewChat { title })      => commands::new_chat(title),
-        Some(Command::ListChats)              => commands::list_chats(),
-        Some(Command::SwitchChat { chat_id }) => commands::switch_chat(chat_id),
+        Some(Command::NewChat { title, persona: p }) => commands::new_chat(title, p.as_deref().or(persona)),
+        Some(Command::ListChats { tags })     => commands::list_chats(&tags),
+        Some(Command::SwitchChat { chat_id, use_global }) => commands::switch_chat(chat_id, use_global),
         Some(Command::SetProfile { pair })    => commands::set_profile(&pair),
         Some(Command::SummarizeChat { chat_id }) => commands::summarize_chat(chat_id),
-        Some(Command::Search { query })       => commands::search_chats(&query),
+        Some(Command::Search { query, tags, role, chat_id, since, until, limit, semantic, json }) => commands::search_chats(
+            &query,
+            search::SearchFilter { role, chat_id, tags, since, until, limit, ..Default::default() },
+            semantic,
+            json,
+        ).await,
+        Some(Command::Export { chat_id, all, format, output }) => commands::export_chats(chat_id, all, &format, output),
+        Some(Command::Import { source }) => match source {
+            ImportSource::Chatgpt { file, dry_run } => commands::import_chatgpt(&file, dry_run),
+            ImportSource::Llm { file, dry_run }     => commands::import_llm(file, dry_run),
+            ImportSource::Aichat { path, dry_run }  => commands::import_aichat(path, dry_run),
+        },
+        Some(Command::Backup { output }) => commands::backup_state(&output),
+        Some(Command::Restore { archive, merge, replace }) => {
+            let mode = if merge {
+                Some(backup::RestoreMode::Merge)
+            } else if replace {
+                Some(backup::RestoreMode::Replace)
+            } else {
+                None
+            };
+            commands::restore_state(&archive, mode)
+        }
+        Some(Command::Db { command }) => match command {
+            DbCommand::Encrypt => commands::encrypt_db(),
+            DbCommand::Decrypt => commands::decrypt_db(),
+        },
+        Some(Command::Gc { dry_run })             => commands::gc(dry_run),
+        Some(Command::Reindex { model })          => commands::reindex_embeddings(model).await,
         Some(Command::ViewChat)                 => commands::view_chat(),
-        Some(Command::DeleteChat { chat_id })      => commands::delete_chat(chat_id),
+        Some(Command::DeleteChat { chat_id: Some(chat_id), .. }) => commands::delete_chat(chat_id),
+        Some(Command::DeleteChat { chat_id: None, tags })        => commands::delete_chats_by_tag(&tags),
         Some(Command::ClearAllChats)              => commands::clear_all_chats(),
+        Some(Command::Remember { chat_id, yes })  => commands::extract_memories(chat_id, yes).await,
+        Some(Command::Tag { chat_id, tags })      => commands::tag_chat(chat_id, &tags),
+        Some(Command::Untag { chat_id, tags })    => commands::untag_chat(chat_id, &tags),
+        Some(Command::Tags)                       => commands::list_tags(),
+        Some(Command::Fork { message_id })        => commands::fork(message_id),
+        Some(Command::Branches)                   => commands::list_branches(),
+        Some(Command::SwitchBranch { message_id }) => commands::switch_branch(message_id),
+        Some(Command::ChatModel { model, backend, temperature, max_tokens, no_temperature, no_max_tokens, chat_id }) => {
+            let temperature = if no_temperature { Some(None) } else { temperature.map(Some) };
+            let max_tokens = if no_max_tokens { Some(None) } else { max_tokens.map(Some) };
+            commands::set_chat_model(chat_id, model, backend, temperature, max_tokens)
+        }
+        Some(Command::Personas { command }) => match command {
+            PersonaCommand::List             => commands::list_personas(),
+            PersonaCommand::Show { name }    => commands::show_persona(&name),
+            PersonaCommand::New { name, system, backend, model, temperature, max_tokens } => commands::new_persona(
+                &name,
+                system,
+                persona::Persona { system_prompt: String::new(), source: backend, model, temperature, max_tokens },
+            ),
+            PersonaCommand::Edit { name }    => commands::edit_persona(&name),
+            PersonaCommand::Delete { name }  => commands::delete_persona(&name),
+        },
+        Some(Command::Bind { chat_id, dir })      => commands::bind_project(chat_id, dir),
+        Some(Command::Unbind { dir })             => commands::unbind_project(dir),
+        Some(Command::Projects)                   => commands::list_projects(),
+        Some(Command::Batch { file, output, concurrency, rate, save }) =>
+            commands::run_batch(&file, output, concurrency, rate, save, persona).await,
+        Some(Command::Chat)                       => repl::run(persona).await,
+        Some(Command::Run { template, vars, no_history, dry_run }) =>
+            commands::run_template(&template, &vars, no_history, dry_run, persona).await,
+        Some(Command::Templates { command }) => match command {
+            TemplateCommand::List            => commands::list_templates(),
+            TemplateCommand::Show { name }   => commands::show_template(&name),
+            TemplateCommand::New { name }    => commands::new_template(&name),
+        },
+        Some(Command::Regen { model, temperature }) => commands::regen(model, temperature).await,
+        Some(Command::EditLast)                   => commands::edit_last().await,
+        Some(Command::Alternates)                 => commands::list_alternates(),
+        Some(Command::DeleteMessage { message_id }) => commands::delete_message(message_id),
+        Some(Command::Hide { message_id })        => commands::set_message_flags(message_id, true, false),
+        Some(Command::Unhide { message_id })      => commands::unhide_message(message_id),
+        Some(Command::Pin { message_id })         => commands::set_message_flags(message_id, false, true),
+        Some(Command::Unpin { message_id })       => commands::set_message_flags(message_id, false, false),
         None                            => println!("yo what?"),
     }
+    if uses_history {
+        commands::auto_gc();
+    }
 }
 
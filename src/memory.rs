@@ -0,0 +1,100 @@
+use serde_json::Value;
+
+/// Instruction sent to the model when extracting long-term facts from a chat.
+pub const EXTRACTION_PROMPT: &str = "You maintain a long-term memory about the user. \
+Read the conversation below and extract durable facts about the user that will still be true \
+in future conversations: preferred languages, operating system, editor, project names, coding \
+conventions and similar. Ignore one-off questions and anything about the assistant. \
+Reply with a single JSON object mapping short snake_case keys to string values, \
+e.g. {\"preferred_language\": \"Rust\", \"os\": \"macOS\"}. Reply with {} if there is nothing worth keeping.";
+
+/// A fact the model proposed for the user profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proposal {
+    /// key is not in the profile yet
+    Add { key: String, value: String },
+    /// key exists with a different value
+    Update { key: String, old: String, value: String },
+}
+
+impl Proposal {
+    pub fn key(&self) -> &str {
+        match self {
+            Proposal::Add { key, .. } | Proposal::Update { key, .. } => key,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Proposal::Add { value, .. } | Proposal::Update { value, .. } => value,
+        }
+    }
+}
+
+/// Normalize a profile key to lower snake_case, e.g. "Preferred Language" -> "preferred_language".
+pub fn normalize_key(key: &str) -> String {
+    let mut out = String::new();
+    for c in key.trim().chars() {
+        if c.is_alphanumeric() {
+            out.extend(c.to_lowercase());
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    out.trim_matches('_').to_string()
+}
+
+/// Parse the model's reply into key/value facts.
+/// Tolerates prose or code fences around the JSON object.
+pub fn parse_facts(reply: &str) -> Vec<(String, String)> {
+    let (start, end) = match (reply.find('{'), reply.rfind('}')) {
+        (Some(s), Some(e)) if s < e => (s, e),
+        _ => return Vec::new(),
+    };
+    let obj = match serde_json::from_str::<Value>(&reply[start..=end]) {
+        Ok(Value::Object(map)) => map,
+        _ => return Vec::new(),
+    };
+    let mut facts = Vec::new();
+    for (k, v) in obj {
+        let value = match v {
+            Value::String(s) => s.trim().to_string(),
+            Value::Null => continue,
+            other => other.to_string(),
+        };
+        let key = normalize_key(&k);
+        if key.is_empty() || value.is_empty() {
+            continue;
+        }
+        facts.push((key, value));
+    }
+    facts
+}
+
+/// Compare extracted facts with the existing profile, dropping ones already known.
+pub fn propose(facts: Vec<(String, String)>, profile: &[(String, String)]) -> Vec<Proposal> {
+    let mut proposals: Vec<Proposal> = Vec::new();
+    for (key, value) in facts {
+        if proposals.iter().any(|p| p.key() == key) {
+            continue;
+        }
+        match profile.iter().find(|(k, _)| *k == key) {
+            Some((_, old)) if old.eq_ignore_ascii_case(&value) => {}
+            Some((_, old)) => proposals.push(Proposal::Update { key, old: old.clone(), value }),
+            None => proposals.push(Proposal::Add { key, value }),
+        }
+    }
+    proposals
+}
+
+/// Render profile entries as a block for the system prompt.
+pub fn profile_prompt(profile: &[(String, String)]) -> Option<String> {
+    if profile.is_empty() {
+        return None;
+    }
+    let mut s = String::from("Known facts about the user:");
+    for (k, v) in profile {
+        s.push_str(&format!("\n- {}: {}", k, v));
+    }
+    Some(s)
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Once, OnceLock};
+
+/// Puts all of yo's files in one directory; `--data-dir` sets it for a single run
+pub const HOME_VAR: &str = "YO_HOME";
+
+const DB_FILE: &str = "chats.db";
+const CURRENT_CHAT_FILE: &str = "current_chat";
+const HISTORY_FILE: &str = "repl_history.txt";
+const SESSIONS_DIR: &str = "sessions";
+const GC_COUNTER_FILE: &str = "gc_counter";
+
+/// `--data-dir`, which wins over `$YO_HOME` for this run
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `dir` instead of `$YO_HOME` for the rest of the run. Only the first call counts.
+pub fn set_home(dir: &Path) {
+    let _ = DATA_DIR.set(std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf()));
+}
+
+/// `--data-dir`, or `$YO_HOME` made absolute, if set
+pub fn yo_home() -> Option<PathBuf> {
+    if let Some(dir) = DATA_DIR.get() {
+        return Some(dir.clone());
+    }
+    let dir = PathBuf::from(std::env::var_os(HOME_VAR).filter(|v| !v.is_empty())?);
+    Some(std::path::absolute(&dir).unwrap_or(dir))
+}
+
+/// `$<var>/yo`, or `~/<fallback>/yo` when the variable isn't set
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match std::env::var_os(var).filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("yo"),
+        None => dirs::home_dir().expect("could not determine home directory").join(fallback).join("yo"),
+    }
+}
+
+/// config.toml, personas/ and templates/: `$YO_HOME` or `$XDG_CONFIG_HOME/yo` (~/.config/yo)
+pub fn config_dir() -> PathBuf {
+    yo_home().unwrap_or_else(|| xdg_dir("XDG_CONFIG_HOME", ".config"))
+}
+
+/// chats.db and its backups, the current-chat pointer and REPL history:
+/// `$YO_HOME` or `$XDG_DATA_HOME/yo` (~/.local/share/yo)
+pub fn data_dir() -> PathBuf {
+    yo_home().unwrap_or_else(|| xdg_dir("XDG_DATA_HOME", ".local/share"))
+}
+
+/// `dir`, created if missing
+fn ensure(dir: PathBuf) -> PathBuf {
+    let _ = fs::create_dir_all(&dir);
+    dir
+}
+
+static MOVE_LEGACY: AtomicBool = AtomicBool::new(false);
+static LEGACY_MOVED: Once = Once::new();
+
+/// Move chat data older versions left elsewhere the first time one of its paths is
+/// needed. Only the yo binary turns this on, so tests never touch the real ~/.config/yo.
+pub fn move_legacy_data_on_use() {
+    MOVE_LEGACY.store(true, Ordering::Relaxed);
+}
+
+/// The data directory, with chat data from older versions moved into it. Only the
+/// paths of that data go through here, so commands that never touch it move nothing.
+fn chat_data_dir() -> PathBuf {
+    if MOVE_LEGACY.load(Ordering::Relaxed) {
+        LEGACY_MOVED.call_once(move_legacy_data);
+    }
+    ensure(data_dir())
+}
+
+/// Move chat data that older versions kept in ~/.config/yo to the data directory.
+/// Does nothing when YO_HOME (or --data-dir) picks the directory explicitly.
+fn move_legacy_data() {
+    if yo_home().is_some() {
+        return;
+    }
+    let to = data_dir();
+    for from in legacy_data_dirs() {
+        match migrate_data(&from, &to) {
+            Ok(moved) if !moved.is_empty() => {
+                eprintln!("📦 Moved {} from {} to {}", moved.join(", "), from.display(), to.display())
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to move chat data from {} to {}: {}", from.display(), to.display(), e),
+        }
+    }
+}
+
+pub fn db_path() -> PathBuf {
+    chat_data_dir().join(DB_FILE)
+}
+
+pub fn current_chat_path() -> PathBuf {
+    chat_data_dir().join(CURRENT_CHAT_FILE)
+}
+
+pub fn repl_history_path() -> PathBuf {
+    chat_data_dir().join(HISTORY_FILE)
+}
+
+/// Invocations since `yo gc` last ran, for `auto_gc_every`
+pub fn gc_counter_path() -> PathBuf {
+    ensure(data_dir()).join(GC_COUNTER_FILE)
+}
+
+/// One current-chat pointer per terminal session, named after `$YO_SESSION`
+pub fn sessions_dir() -> PathBuf {
+    ensure(data_dir().join(SESSIONS_DIR))
+}
+
+/// Where older versions kept chat data: ~/.config/yo whatever XDG_CONFIG_HOME said,
+/// and REPL history in the config directory
+pub fn legacy_data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::home_dir().map(|h| h.join(".config").join("yo")).into_iter().collect();
+    let config = config_dir();
+    if !dirs.contains(&config) {
+        dirs.push(config);
+    }
+    dirs
+}
+
+/// Copies of chats.db saved before upgrades and restores, e.g. chats.db.v9.bak
+fn is_db_backup(name: &str) -> bool {
+    name.starts_with("chats.db.") && name.ends_with(".bak")
+}
+
+/// The copies of chats.db in the data directory, by name
+pub fn db_backups() -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = fs::read_dir(chat_data_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_name().to_str().is_some_and(is_db_backup))
+        .map(|e| e.path())
+        .collect();
+    found.sort();
+    found
+}
+
+/// Whether a file in a legacy directory is chat data rather than configuration
+fn is_data_file(name: &str) -> bool {
+    name == CURRENT_CHAT_FILE || name == HISTORY_FILE || is_db_backup(name)
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        // across filesystems
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Move chat data from `from` into `to`, never overwriting anything already in `to`.
+/// The database moves together with its -wal and -shm files. Returns the names moved.
+pub fn migrate_data(from: &Path, to: &Path) -> io::Result<Vec<String>> {
+    let mut moved = Vec::new();
+    if from == to || !from.is_dir() {
+        return Ok(moved);
+    }
+    fs::create_dir_all(to)?;
+    if from.join(DB_FILE).is_file() && !to.join(DB_FILE).exists() {
+        for name in [DB_FILE.to_string(), format!("{}-wal", DB_FILE), format!("{}-shm", DB_FILE)] {
+            if from.join(&name).is_file() {
+                move_file(&from.join(&name), &to.join(&name))?;
+                moved.push(name);
+            }
+        }
+    }
+    let mut names: Vec<String> = fs::read_dir(from)?
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| is_data_file(n))
+        .collect();
+    names.sort();
+    for name in names {
+        if !to.join(&name).exists() {
+            move_file(&from.join(&name), &to.join(&name))?;
+            moved.push(name);
+        }
+    }
+    Ok(moved)
+}
@@ -0,0 +1,103 @@
+use crate::config::{get_config_dir, try_load_config, ModelSettings};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// A reusable preset: system prompt plus optional backend, model and generation parameters.
+/// Stored as `personas/<name>.toml` next to config.toml, or inline as `[personas.<name>]` in config.toml.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Persona {
+    pub system_prompt: String,
+    /// "openai" or "ollama"; the chat's backend is kept when unset
+    pub source: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+impl Persona {
+    /// Overlay the persona's backend, model and parameters on `base`
+    pub fn settings(&self, base: &ModelSettings) -> ModelSettings {
+        ModelSettings {
+            source: self.source.clone().unwrap_or_else(|| base.source.clone()),
+            model: self.model.clone().unwrap_or_else(|| base.model.clone()),
+            temperature: self.temperature.or(base.temperature),
+            max_tokens: self.max_tokens.or(base.max_tokens),
+        }
+    }
+}
+
+/// Where a persona was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    File,
+    Config,
+}
+
+pub fn personas_dir() -> PathBuf {
+    get_config_dir().join("personas")
+}
+
+pub fn persona_path(name: &str) -> PathBuf {
+    personas_dir().join(format!("{}.toml", name))
+}
+
+/// Persona names become file names, so keep them simple
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("invalid persona name '{}': use letters, digits, '-' and '_'", name));
+    }
+    Ok(())
+}
+
+pub fn parse(s: &str) -> Result<Persona, String> {
+    toml::from_str(s).map_err(|e| e.to_string())
+}
+
+/// Look up a persona; files in personas/ take precedence over config.toml
+pub fn load(name: &str) -> Result<Option<(Persona, Origin)>, String> {
+    validate_name(name)?;
+    let path = persona_path(name);
+    if path.exists() {
+        let s = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let persona = parse(&s).map_err(|e| format!("{}: {}", path.display(), e))?;
+        return Ok(Some((persona, Origin::File)));
+    }
+    Ok(try_load_config().and_then(|mut c| c.personas.remove(name)).map(|p| (p, Origin::Config)))
+}
+
+/// All personas by name, files first overriding config.toml entries
+pub fn list() -> Vec<(String, Persona, Origin)> {
+    let mut all: Vec<(String, Persona, Origin)> = try_load_config()
+        .map(|c| c.personas.into_iter().map(|(n, p)| (n, p, Origin::Config)).collect())
+        .unwrap_or_default();
+    if let Ok(entries) = fs::read_dir(personas_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(n) => n.to_string(),
+                None => continue,
+            };
+            let persona = match fs::read_to_string(&path).ok().and_then(|s| parse(&s).ok()) {
+                Some(p) => p,
+                None => { eprintln!("⚠️ skipping invalid persona file {}", path.display()); continue; }
+            };
+            all.retain(|(n, _, _)| *n != name);
+            all.push((name, persona, Origin::File));
+        }
+    }
+    all.sort_by(|a, b| a.0.cmp(&b.0));
+    all
+}
+
+pub fn save(name: &str, persona: &Persona) -> Result<PathBuf, String> {
+    validate_name(name)?;
+    fs::create_dir_all(personas_dir()).map_err(|e| e.to_string())?;
+    let path = persona_path(name);
+    let s = toml::to_string_pretty(persona).map_err(|e| e.to_string())?;
+    fs::write(&path, s).map_err(|e| e.to_string())?;
+    Ok(path)
+}
@@ -0,0 +1,55 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::path::{Path, PathBuf};
+
+/// The directory a project chat is bound to: the enclosing git repository's root,
+/// or `dir` itself outside a repository
+pub fn project_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Absolute form of `dir` as stored in `project_chats`
+pub fn normalize_dir(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+pub fn bind(conn: &Connection, dir: &Path, chat_id: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO project_chats (path, chat_id) VALUES (?1, ?2) ON CONFLICT(path) DO UPDATE SET chat_id = excluded.chat_id",
+        params![dir.to_string_lossy(), chat_id],
+    )?;
+    Ok(())
+}
+
+pub fn unbind(conn: &Connection, dir: &Path) -> Result<bool> {
+    let n = conn.execute("DELETE FROM project_chats WHERE path = ?1", [dir.to_string_lossy()])?;
+    Ok(n > 0)
+}
+
+/// The binding that applies in `dir`: the one for `dir` or its nearest bound parent
+pub fn binding_for(conn: &Connection, dir: &Path) -> Result<Option<(PathBuf, i64)>> {
+    for candidate in dir.ancestors() {
+        let found = conn
+            .query_row(
+                "SELECT p.chat_id FROM project_chats p JOIN chats c ON c.id = p.chat_id WHERE p.path = ?1",
+                [candidate.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(chat_id) = found {
+            return Ok(Some((candidate.to_path_buf(), chat_id)));
+        }
+    }
+    Ok(None)
+}
+
+/// All bindings with their chat titles, by path
+pub fn bindings(conn: &Connection) -> Result<Vec<(String, i64, Option<String>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.path, p.chat_id, yo_open(c.title) FROM project_chats p LEFT JOIN chats c ON c.id = p.chat_id ORDER BY p.path",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
@@ -0,0 +1,157 @@
+use crate::commands;
+use crate::paths;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+
+/// Delimiter that starts and ends a multi-line block
+const BLOCK_DELIMITER: &str = "\"\"\"";
+
+pub const HELP: &str = "\
+Type a message and press Enter to send it.
+End a line with \\ to continue on the next one, or wrap a block in \"\"\".
+
+  /model [name]   show the current model, or switch this chat to another one
+  /new [title]    start a new chat
+  /switch <id>    switch to another chat
+  /system [text]  show or set this chat's system prompt
+  /history        show this chat's messages
+  /clear          clear this chat's messages
+  /help           show this help
+  /exit           leave (Ctrl-D works too)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    Model(Option<String>),
+    New(Option<String>),
+    Switch(i64),
+    System(Option<String>),
+    History,
+    Clear,
+    Help,
+    Exit,
+}
+
+/// Parse a `/command`. Returns None for ordinary messages.
+pub fn parse_slash(line: &str) -> Option<Result<SlashCommand, String>> {
+    let line = line.trim();
+    let rest = line.strip_prefix('/')?;
+    let (name, arg) = match rest.split_once(char::is_whitespace) {
+        Some((n, a)) => (n, Some(a.trim().to_string()).filter(|a| !a.is_empty())),
+        None => (rest, None),
+    };
+    let cmd = match name {
+        "model" => SlashCommand::Model(arg),
+        "new" => SlashCommand::New(arg),
+        "switch" => match arg.as_deref().map(str::parse) {
+            Some(Ok(id)) => SlashCommand::Switch(id),
+            _ => return Some(Err("usage: /switch <chat id>".to_string())),
+        },
+        "system" => SlashCommand::System(arg),
+        "history" => SlashCommand::History,
+        "clear" => SlashCommand::Clear,
+        "help" | "?" => SlashCommand::Help,
+        "exit" | "quit" | "q" => SlashCommand::Exit,
+        _ => return Some(Err(format!("unknown command /{} (try /help)", name))),
+    };
+    Some(Ok(cmd))
+}
+
+/// Collects lines until a complete message is entered
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    lines: Vec<String>,
+    in_block: bool,
+}
+
+impl InputBuffer {
+    /// Whether a message has been started but not finished
+    pub fn is_pending(&self) -> bool {
+        self.in_block || !self.lines.is_empty()
+    }
+
+    /// Feed one line; returns the full message once it is complete
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        if line.trim() == BLOCK_DELIMITER {
+            if self.in_block {
+                return Some(self.take());
+            }
+            self.in_block = true;
+            return None;
+        }
+        if self.in_block {
+            self.lines.push(line.to_string());
+            return None;
+        }
+        if let Some(start) = line.strip_suffix('\\') {
+            self.lines.push(start.to_string());
+            return None;
+        }
+        self.lines.push(line.to_string());
+        Some(self.take())
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.in_block = false;
+    }
+
+    fn take(&mut self) -> String {
+        let text = self.lines.join("\n");
+        self.clear();
+        text
+    }
+}
+
+/// Interactive chat: every message is asked in the current chat, like `yo ask`
+pub async fn run(persona: Option<&str>) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(e) => e,
+        Err(e) => { eprintln!("Failed to start line editor: {}", e); return; }
+    };
+    let history_path = paths::repl_history_path();
+    let _ = editor.load_history(&history_path);
+    println!("💬 yo chat. Type /help for commands, /exit or Ctrl-D to leave.");
+
+    let mut buffer = InputBuffer::default();
+    loop {
+        let prompt = if buffer.is_pending() { "... " } else { "you> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                // Ctrl-C drops the message being typed
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => { eprintln!("Input error: {}", e); break; }
+        };
+        let message = match buffer.push(&line) {
+            Some(m) => m,
+            None => continue,
+        };
+        if message.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(message.as_str());
+
+        match parse_slash(&message) {
+            None => commands::ask(&[message], false, persona).await,
+            Some(Err(e)) => eprintln!("{}", e),
+            Some(Ok(cmd)) => match cmd {
+                SlashCommand::Model(None) => commands::show_current(),
+                SlashCommand::Model(model) => commands::set_chat_model(None, model, None, None, None),
+                SlashCommand::New(title) => commands::new_chat(title, persona),
+                SlashCommand::Switch(id) => commands::switch_chat(id, false),
+                SlashCommand::System(text) => commands::chat_system(text),
+                SlashCommand::History => commands::view_chat(),
+                SlashCommand::Clear => commands::clear_history(),
+                SlashCommand::Help => println!("{}", HELP),
+                SlashCommand::Exit => break,
+            },
+        }
+    }
+    if let Err(e) = editor.save_history(&history_path) {
+        eprintln!("Failed to save input history: {}", e);
+    }
+}
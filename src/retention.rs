@@ -0,0 +1,168 @@
+use crate::chats;
+use crate::tags;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// What `yo gc` deletes, from the `[retention]` table in config.toml
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Retention {
+    /// delete messages older than this many days
+    pub max_age_days: Option<u32>,
+    /// keep at most this many chats, the most recently active ones
+    pub max_chats: Option<usize>,
+    /// never delete from chats containing a pinned message (default: true)
+    pub keep_pinned: Option<bool>,
+    /// never delete anything from tagged chats (default: true)
+    pub keep_tagged: Option<bool>,
+    /// run `yo gc` by itself every this many invocations of yo
+    pub auto_gc_every: Option<u32>,
+}
+
+impl Retention {
+    fn keep_pinned(&self) -> bool {
+        self.keep_pinned.unwrap_or(true)
+    }
+
+    fn keep_tagged(&self) -> bool {
+        self.keep_tagged.unwrap_or(true)
+    }
+}
+
+/// What a collection removed, or would remove on a dry run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GcStats {
+    /// Chats over `max_chats`, and chats left empty by `max_age_days`
+    pub chats: usize,
+    /// Messages older than `max_age_days`
+    pub messages: usize,
+    /// Rows pointing at chats or messages that no longer exist
+    pub orphans: usize,
+}
+
+/// Apply `policy` and remove orphaned rows in one transaction. On a dry run
+/// the transaction is rolled back, so the counts say what would be removed.
+pub fn collect(conn: &Connection, policy: &Retention, dry_run: bool) -> Result<GcStats> {
+    let tx = conn.unchecked_transaction()?;
+    let mut stats = GcStats::default();
+    if let Some(max) = policy.max_chats {
+        for chat_id in chats_over_limit(&tx, policy, max)? {
+            chats::delete(&tx, chat_id)?;
+            stats.chats += 1;
+        }
+    }
+    if let Some(days) = policy.max_age_days {
+        let (messages, emptied) = expire_messages(&tx, policy, days)?;
+        stats.messages += messages;
+        stats.chats += emptied;
+    }
+    stats.orphans = remove_orphans(&tx)?;
+    tags::prune_unused(&tx)?;
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+    Ok(stats)
+}
+
+/// SQL condition on `c` (a chat) for chats that are kept whole:
+/// tagged ones and, with `pinned`, ones containing a pinned message
+fn protected(tagged: bool, pinned: bool) -> String {
+    let mut conditions = vec!["0"];
+    if tagged {
+        conditions.push("EXISTS (SELECT 1 FROM chat_tags t WHERE t.chat_id = c.id)");
+    }
+    if pinned {
+        conditions.push("EXISTS (SELECT 1 FROM messages p WHERE p.chat_id = c.id AND p.pinned = 1)");
+    }
+    format!("({})", conditions.join(" OR "))
+}
+
+/// Unprotected chats beyond the `max` most recently active ones
+fn chats_over_limit(conn: &Connection, policy: &Retention, max: usize) -> Result<Vec<i64>> {
+    let sql = format!(
+        r#"
+        SELECT c.id FROM chats c
+        WHERE NOT {}
+        ORDER BY COALESCE((SELECT MAX(m.created_at) FROM messages m WHERE m.chat_id = c.id), c.created_at) DESC, c.id DESC
+        LIMIT -1 OFFSET ?1
+        "#,
+        protected(policy.keep_tagged(), policy.keep_pinned())
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map([max as i64], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Delete messages older than `days` from unprotected chats, keeping replies attached
+/// to the nearest message that stays, then the chats this leaves empty. Returns both counts.
+fn expire_messages(conn: &Connection, policy: &Retention, days: u32) -> Result<(usize, usize)> {
+    let cutoff = format!("-{} days", days);
+    let kept = protected(policy.keep_tagged(), policy.keep_pinned());
+    let sql = format!(
+        "SELECT m.id FROM messages m JOIN chats c ON c.id = m.chat_id WHERE m.created_at < datetime('now', ?1) AND NOT {}",
+        kept
+    );
+    let doomed: HashSet<i64> = conn.prepare(&sql)?.query_map([&cutoff], |row| row.get(0))?.collect::<Result<_>>()?;
+    if doomed.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let parents: HashMap<i64, Option<i64>> =
+        conn.prepare("SELECT id, parent_id FROM messages")?.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_>>()?;
+    let mut reparent = conn.prepare("UPDATE messages SET parent_id = ?1 WHERE id = ?2")?;
+    for (id, parent) in &parents {
+        if doomed.contains(id) || !parent.is_some_and(|p| doomed.contains(&p)) {
+            continue;
+        }
+        let mut ancestor = *parent;
+        while let Some(a) = ancestor.filter(|a| doomed.contains(a)) {
+            ancestor = parents.get(&a).copied().flatten();
+        }
+        reparent.execute(params![ancestor, id])?;
+    }
+    let mut delete = conn.prepare("DELETE FROM messages WHERE id = ?1")?;
+    for id in &doomed {
+        delete.execute([id])?;
+    }
+
+    let sql = format!(
+        "SELECT c.id FROM chats c WHERE c.created_at < datetime('now', ?1) AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.chat_id = c.id) AND NOT {}",
+        kept
+    );
+    let emptied: Vec<i64> = conn.prepare(&sql)?.query_map([&cutoff], |row| row.get(0))?.collect::<Result<_>>()?;
+    for chat_id in &emptied {
+        chats::delete(conn, *chat_id)?;
+    }
+    Ok((doomed.len(), emptied.len()))
+}
+
+/// Delete rows left behind by chats and messages deleted without their dependents
+fn remove_orphans(conn: &Connection) -> Result<usize> {
+    let mut removed = 0;
+    for sql in [
+        "DELETE FROM messages WHERE chat_id IS NULL OR chat_id NOT IN (SELECT id FROM chats)",
+        "DELETE FROM chat_tags WHERE chat_id NOT IN (SELECT id FROM chats)",
+        "DELETE FROM project_chats WHERE chat_id NOT IN (SELECT id FROM chats)",
+        "DELETE FROM imports WHERE chat_id NOT IN (SELECT id FROM chats)",
+        "DELETE FROM embeddings WHERE message_id NOT IN (SELECT id FROM messages)",
+    ] {
+        removed += conn.execute(sql, [])?;
+    }
+    conn.execute("UPDATE messages SET parent_id = NULL WHERE parent_id NOT IN (SELECT id FROM messages)", [])?;
+    conn.execute("UPDATE chats SET head_message_id = NULL WHERE head_message_id NOT IN (SELECT id FROM messages)", [])?;
+    Ok(removed)
+}
+
+/// Size of the database in bytes
+pub fn db_size(conn: &Connection) -> Result<u64> {
+    conn.query_row("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()", [], |row| row.get(0))
+}
+
+/// Compact the database. Returns the bytes reclaimed.
+pub fn vacuum(conn: &Connection) -> Result<u64> {
+    let before = db_size(conn)?;
+    conn.execute_batch("VACUUM")?;
+    Ok(before.saturating_sub(db_size(conn)?))
+}
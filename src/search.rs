@@ -0,0 +1,221 @@
+use crate::crypto;
+use crate::embeddings;
+use crate::tags;
+use rusqlite::{named_params, Connection, Result};
+use serde::Serialize;
+
+/// Narrows a search down; every field is optional
+#[derive(Debug, Clone)]
+pub struct SearchFilter {
+    pub role: Option<String>,
+    pub chat_id: Option<i64>,
+    /// Only chats carrying all of these tags
+    pub tags: Vec<String>,
+    /// First day to include, `YYYY-MM-DD`
+    pub since: Option<String>,
+    /// Last day to include, `YYYY-MM-DD`
+    pub until: Option<String>,
+    pub limit: usize,
+    /// Markers put around matched terms in snippets
+    pub highlight: (String, String),
+}
+
+impl Default for SearchFilter {
+    fn default() -> Self {
+        SearchFilter {
+            role: None,
+            chat_id: None,
+            tags: Vec::new(),
+            since: None,
+            until: None,
+            limit: 20,
+            highlight: ("[".to_string(), "]".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SearchHit {
+    pub message_id: i64,
+    pub chat_id: i64,
+    pub chat_title: Option<String>,
+    pub role: String,
+    pub created_at: String,
+    pub snippet: String,
+    /// Relevance, lower is better: bm25 for keyword search, negated cosine similarity for semantic search
+    pub rank: f64,
+    /// Preview of the message this one replied to (semantic search)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// Check a `YYYY-MM-DD` date
+pub fn valid_date(date: &str) -> bool {
+    let parts: Vec<&str> = date.split('-').collect();
+    parts.len() == 3
+        && [4, 2, 2].iter().zip(&parts).all(|(len, p)| p.len() == *len && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Turn free text into an FTS5 query that matches all of its words literally,
+/// e.g. `what's "up"?` -> `"what's" """up""?"`
+pub fn quote_terms(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Search message content, best matches first. `query` uses FTS5 syntax: words,
+/// "exact phrases", AND/OR/NOT, prefix* and NEAR(). Text that isn't valid syntax
+/// is searched for word by word instead.
+pub fn search(conn: &Connection, query: &str, filter: &SearchFilter) -> Result<Vec<SearchHit>> {
+    match run(conn, query, filter) {
+        Err(e) if is_query_error(&e) => run(conn, &quote_terms(query), filter),
+        other => other,
+    }
+}
+
+fn is_query_error(e: &rusqlite::Error) -> bool {
+    let msg = e.to_string();
+    msg.contains("fts5") || msg.contains("syntax error") || msg.contains("no such column")
+}
+
+/// Conditions shared by keyword and semantic search, over `messages m`
+const FILTERS: &str = r#"
+          AND (:role IS NULL OR m.role = :role)
+          AND (:chat IS NULL OR m.chat_id = :chat)
+          AND (:since IS NULL OR m.created_at >= date(:since))
+          AND (:until IS NULL OR m.created_at < date(:until, '+1 day'))
+          AND (:chats IS NULL OR m.chat_id IN (SELECT value FROM json_each(:chats)))
+"#;
+
+/// Chats allowed by the tag filter, as a JSON array for `json_each`
+fn tagged_chats(conn: &Connection, filter: &SearchFilter) -> Result<Option<String>> {
+    if filter.tags.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(&tags::chats_with_tags(conn, &filter.tags)?).unwrap_or_default()))
+}
+
+/// The full-text index to search. Encrypted messages are kept out of `messages_fts`,
+/// so for an encrypted database it's built in memory from the decrypted messages.
+fn index(conn: &Connection) -> Result<&'static str> {
+    if !crypto::is_encrypted(conn)? {
+        return Ok("messages_fts");
+    }
+    conn.execute_batch(
+        r#"
+        PRAGMA temp_store = MEMORY;
+        CREATE VIRTUAL TABLE IF NOT EXISTS temp.messages_plain USING fts5(content, tokenize = 'porter unicode61');
+        DELETE FROM temp.messages_plain;
+        INSERT INTO temp.messages_plain (rowid, content) SELECT id, yo_open(content) FROM messages;
+        "#,
+    )?;
+    Ok("messages_plain")
+}
+
+fn run(conn: &Connection, query: &str, filter: &SearchFilter) -> Result<Vec<SearchHit>> {
+    let chats = tagged_chats(conn, filter)?;
+    let sql = format!(
+        r#"
+        SELECT m.id, m.chat_id, yo_open(c.title), m.role, m.created_at,
+               snippet({index}, 0, :open, :close, '…', 16), bm25({index})
+        FROM {index}
+        JOIN messages m ON m.id = {index}.rowid
+        LEFT JOIN chats c ON c.id = m.chat_id
+        WHERE {index} MATCH :query {filters}
+        ORDER BY bm25({index}), m.id DESC
+        LIMIT :limit
+        "#,
+        index = index(conn)?,
+        filters = FILTERS
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(
+        named_params! {
+            ":query": query,
+            ":role": filter.role,
+            ":chat": filter.chat_id,
+            ":since": filter.since,
+            ":until": filter.until,
+            ":chats": chats,
+            ":open": filter.highlight.0,
+            ":close": filter.highlight.1,
+            ":limit": filter.limit as i64,
+        },
+        |row| {
+            Ok(SearchHit {
+                message_id: row.get(0)?,
+                chat_id: row.get(1)?,
+                chat_title: row.get(2)?,
+                role: row.get(3)?,
+                created_at: row.get(4)?,
+                snippet: row.get(5)?,
+                rank: row.get(6)?,
+                context: None,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+/// Shorten text to about `max` characters on one line
+pub fn preview(text: &str, max: usize) -> String {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= max {
+        return flat;
+    }
+    let mut short: String = flat.chars().take(max).collect();
+    short.push('…');
+    short
+}
+
+/// Messages closest in meaning to `query_vector` among those embedded with `model`,
+/// skipping ones with no similarity. Each hit carries the message it replied to as context.
+pub fn semantic(conn: &Connection, model: &str, query_vector: &[f32], filter: &SearchFilter) -> Result<Vec<SearchHit>> {
+    let chats = tagged_chats(conn, filter)?;
+    let sql = format!(
+        r#"
+        SELECT m.id, m.chat_id, yo_open(c.title), m.role, m.created_at, yo_open(m.content), e.vector, yo_open(p.content)
+        FROM embeddings e
+        JOIN messages m ON m.id = e.message_id
+        LEFT JOIN chats c ON c.id = m.chat_id
+        LEFT JOIN messages p ON p.id = m.parent_id
+        WHERE e.model = :model {}
+        "#,
+        FILTERS
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(
+        named_params! {
+            ":model": model,
+            ":role": filter.role,
+            ":chat": filter.chat_id,
+            ":since": filter.since,
+            ":until": filter.until,
+            ":chats": chats,
+        },
+        |row| {
+            let content: String = row.get(5)?;
+            let vector: Vec<u8> = row.get(6)?;
+            let parent: Option<String> = row.get(7)?;
+            Ok(SearchHit {
+                message_id: row.get(0)?,
+                chat_id: row.get(1)?,
+                chat_title: row.get(2)?,
+                role: row.get(3)?,
+                created_at: row.get(4)?,
+                snippet: preview(&content, 200),
+                rank: -(embeddings::cosine(query_vector, &embeddings::decode(&vector)) as f64),
+                context: parent.map(|p| preview(&p, 120)),
+            })
+        },
+    )?;
+    let mut hits = rows.collect::<Result<Vec<_>>>()?;
+    // unrelated messages aren't results, however few there are
+    hits.retain(|h| h.rank < 0.0);
+    hits.sort_by(|a, b| a.rank.total_cmp(&b.rank).then(b.message_id.cmp(&a.message_id)));
+    hits.truncate(filter.limit);
+    Ok(hits)
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Pins a shell to one chat, e.g. `export YO_CHAT=12`; wins over every other scope
+pub const CHAT_VAR: &str = "YO_CHAT";
+/// Set per terminal by `yo shell-init`; each session keeps its own current chat
+pub const SESSION_VAR: &str = "YO_SESSION";
+
+/// Session pointers untouched for this long belong to terminals that are long gone
+pub const SESSION_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Where the current chat came from, in order of precedence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    Env,
+    Project(PathBuf),
+    Session(String),
+    Global,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Env => write!(f, "${}", CHAT_VAR),
+            Scope::Project(dir) => write!(f, "project {}", dir.display()),
+            Scope::Session(id) => write!(f, "terminal session {}", id),
+            Scope::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// The chat `$YO_CHAT` pins this shell to
+pub fn pinned_chat() -> Option<i64> {
+    std::env::var(CHAT_VAR).ok()?.trim().parse().ok()
+}
+
+/// Whether `id` can name a session file
+pub fn valid_session_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && !id.starts_with('.')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// `$YO_SESSION`, if set to something usable as a file name
+pub fn session_id() -> Option<String> {
+    std::env::var(SESSION_VAR).ok().filter(|id| valid_session_id(id))
+}
+
+pub fn read_chat(path: &Path) -> Option<i64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn write_chat(path: &Path, chat_id: i64) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, chat_id.to_string())
+}
+
+/// Remove session pointers in `dir` not written for `ttl`; returns how many went
+pub fn prune(dir: &Path, ttl: Duration) -> io::Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)?.flatten() {
+        let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(now);
+        if now.duration_since(modified).unwrap_or_default() > ttl && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Shell code giving every new interactive shell its own session id
+pub fn hook(shell: &str) -> Option<String> {
+    let line = match shell {
+        "bash" | "zsh" => format!("export {}=\"$$-$(date +%s)\"", SESSION_VAR),
+        "fish" => format!("set -gx {} \"$fish_pid-\"(date +%s)", SESSION_VAR),
+        _ => return None,
+    };
+    Some(format!("# yo: keep a separate current chat in each terminal\n{}\n", line))
+}
@@ -0,0 +1,141 @@
+use crate::chats;
+use crate::config::ModelSettings;
+use crate::db::{self, DbError};
+use crate::tags;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::ops::Deref;
+
+/// A chat without its messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chat {
+    pub id: i64,
+    pub title: String,
+    pub created_at: String,
+    /// None for chats created before settings were remembered
+    pub settings: Option<ModelSettings>,
+}
+
+/// A message on a branch of a chat
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+    pub hidden: bool,
+    pub pinned: bool,
+}
+
+/// A fact about the user, sent along with every question
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileEntry {
+    pub key: String,
+    pub value: String,
+}
+
+/// Typed access to the chat database. Operations spanning several statements run
+/// in one transaction. Modules that work on a plain connection (branches, search,
+/// export…) take the store as one.
+pub struct ChatStore {
+    conn: Connection,
+}
+
+impl ChatStore {
+    /// A store on a connection with the current schema, e.g. an in-memory one after
+    /// `db::init_schema`
+    pub fn new(conn: Connection) -> Self {
+        ChatStore { conn }
+    }
+
+    /// chats.db, asking for the passphrase if it's encrypted
+    pub fn open() -> std::result::Result<Self, DbError> {
+        db::init_db().map(ChatStore::new)
+    }
+
+    /// All chats, newest first
+    pub fn chats(&self) -> Result<Vec<Chat>> {
+        let mut stmt = self.conn.prepare(&format!("{} ORDER BY c.created_at DESC, c.id DESC", CHAT_QUERY))?;
+        let rows = stmt.query_map([], chat_from_row)?;
+        rows.collect()
+    }
+
+    pub fn chat(&self, chat_id: i64) -> Result<Option<Chat>> {
+        self.conn
+            .query_row(&format!("{} WHERE c.id = ?1", CHAT_QUERY), [chat_id], chat_from_row)
+            .optional()
+    }
+
+    /// Delete chats with everything belonging to them, and the tags no chat uses
+    /// anymore. Returns how many chats there were.
+    pub fn delete_chats(&self, chat_ids: &[i64]) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut deleted = 0;
+        for chat_id in chat_ids {
+            deleted += chats::delete(&tx, *chat_id)?;
+        }
+        tags::prune_unused(&tx)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Delete every chat, message and tag. The profile is kept.
+    pub fn clear_all(&self) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        // messages, tag links, project bindings and import records cascade
+        tx.execute("DELETE FROM chats", [])?;
+        tx.execute("DELETE FROM tags", [])?;
+        tx.commit()
+    }
+
+    /// Delete a chat's messages but keep the chat. Returns how many were deleted.
+    pub fn clear_messages(&self, chat_id: i64) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let n = tx.execute("DELETE FROM messages WHERE chat_id = ?1", [chat_id])?;
+        tx.execute("UPDATE chats SET head_message_id = NULL WHERE id = ?1", [chat_id])?;
+        tx.commit()?;
+        Ok(n)
+    }
+
+    /// The user profile, by key
+    pub fn profile(&self) -> Result<Vec<ProfileEntry>> {
+        let mut stmt = self.conn.prepare("SELECT key, yo_open(value) FROM user_profile ORDER BY key")?;
+        let rows = stmt.query_map([], |row| Ok(ProfileEntry { key: row.get(0)?, value: row.get(1)? }))?;
+        rows.collect()
+    }
+
+    /// Add a profile entry, or replace the value of an existing key
+    pub fn set_profile(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO user_profile (key, value) VALUES (?1, yo_seal(?2)) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+}
+
+impl Deref for ChatStore {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+const CHAT_QUERY: &str =
+    "SELECT c.id, COALESCE(yo_open(c.title), ''), COALESCE(c.created_at, ''), c.source, c.model, c.temperature, c.max_tokens FROM chats c";
+
+fn chat_from_row(row: &rusqlite::Row) -> Result<Chat> {
+    let source: Option<String> = row.get(3)?;
+    let model: Option<String> = row.get(4)?;
+    let settings = match (source, model) {
+        (Some(source), Some(model)) => Some(ModelSettings { source, model, temperature: row.get(5)?, max_tokens: row.get(6)? }),
+        _ => None,
+    };
+    Ok(Chat {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        created_at: row.get(2)?,
+        settings,
+    })
+}
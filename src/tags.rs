@@ -0,0 +1,106 @@
+use rusqlite::{params, Connection, Result};
+
+/// Normalize a tag to lowercase with dashes, e.g. " Work Stuff " -> "work-stuff".
+/// Returns None if nothing usable is left.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let mut out = String::new();
+    for c in tag.trim().trim_start_matches('#').chars() {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            out.extend(c.to_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let out = out.trim_end_matches('-').to_string();
+    if out.is_empty() { None } else { Some(out) }
+}
+
+/// Normalize a list of tags, dropping empty ones and duplicates
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for t in tags.iter().flat_map(|t| t.split(',')) {
+        if let Some(n) = normalize_tag(t) {
+            if !out.contains(&n) {
+                out.push(n);
+            }
+        }
+    }
+    out
+}
+
+pub fn add_tags(conn: &Connection, chat_id: i64, tags: &[String]) -> Result<()> {
+    for tag in tags {
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [tag])?;
+        conn.execute(
+            "INSERT OR IGNORE INTO chat_tags (chat_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+            params![chat_id, tag],
+        )?;
+    }
+    Ok(())
+}
+
+/// Remove tags from a chat. Returns the number of tags actually removed.
+pub fn remove_tags(conn: &Connection, chat_id: i64, tags: &[String]) -> Result<usize> {
+    let mut removed = 0;
+    for tag in tags {
+        removed += conn.execute(
+            "DELETE FROM chat_tags WHERE chat_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            params![chat_id, tag],
+        )?;
+    }
+    prune_unused(conn)?;
+    Ok(removed)
+}
+
+/// Drop tags that are no longer attached to any chat
+pub fn prune_unused(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM chat_tags)", [])?;
+    Ok(())
+}
+
+pub fn tags_for_chat(conn: &Connection, chat_id: i64) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name FROM tags t JOIN chat_tags ct ON ct.tag_id = t.id WHERE ct.chat_id = ?1 ORDER BY t.name",
+    )?;
+    let rows = stmt.query_map([chat_id], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// IDs of chats carrying every one of `tags`
+pub fn chats_with_tags(conn: &Connection, tags: &[String]) -> Result<Vec<i64>> {
+    let mut ids: Option<Vec<i64>> = None;
+    for tag in tags {
+        let mut stmt = conn.prepare(
+            "SELECT ct.chat_id FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id WHERE t.name = ?1",
+        )?;
+        let found: Vec<i64> = stmt.query_map([tag], |row| row.get(0))?.collect::<Result<_>>()?;
+        ids = Some(match ids {
+            Some(prev) => prev.into_iter().filter(|id| found.contains(id)).collect(),
+            None => found,
+        });
+    }
+    Ok(ids.unwrap_or_default())
+}
+
+/// All tags with the number of chats using them
+pub fn all_tags(conn: &Connection) -> Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, COUNT(ct.chat_id) FROM tags t LEFT JOIN chat_tags ct ON ct.tag_id = t.id GROUP BY t.id ORDER BY t.name",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Move tags from the legacy free-text `chats.tags` column into `chat_tags`.
+/// Tags are separated by `,` or `;`; spaces inside one join its words, as in `normalize_tag`.
+pub fn migrate_legacy_tags(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT id, tags FROM chats WHERE tags IS NOT NULL AND tags != ''")?;
+    let legacy: Vec<(i64, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_>>()?;
+    for (chat_id, raw) in legacy {
+        let parts: Vec<String> = raw.split([',', ';']).map(String::from).collect();
+        add_tags(conn, chat_id, &normalize_tags(&parts))?;
+        conn.execute("UPDATE chats SET tags = NULL WHERE id = ?1", [chat_id])?;
+    }
+    Ok(())
+}
+
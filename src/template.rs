@@ -0,0 +1,144 @@
+use crate::config::get_config_dir;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions tried, in order, when looking up a template by name
+const EXTENSIONS: [&str; 3] = ["md", "txt", "prompt"];
+
+/// A `{{...}}` placeholder in a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placeholder {
+    /// `{{name}}` or `{{name|default}}`
+    Var { name: String, default: Option<String> },
+    /// `{{file:path}}`, replaced by the file's contents
+    File(String),
+}
+
+pub fn templates_dir() -> PathBuf {
+    get_config_dir().join("templates")
+}
+
+/// Find a template file by name, e.g. "review" -> templates/review.md
+pub fn find(name: &str) -> Option<PathBuf> {
+    let dir = templates_dir();
+    let exact = dir.join(name);
+    if Path::new(name).extension().is_some() && exact.is_file() {
+        return Some(exact);
+    }
+    EXTENSIONS.iter().map(|ext| dir.join(format!("{}.{}", name, ext))).find(|p| p.is_file())
+}
+
+/// Names of all templates, sorted
+pub fn list() -> Vec<(String, PathBuf)> {
+    let mut all: Vec<(String, PathBuf)> = fs::read_dir(templates_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .filter_map(|p| Some((p.file_stem()?.to_str()?.to_string(), p)))
+                .collect()
+        })
+        .unwrap_or_default();
+    all.sort();
+    all
+}
+
+/// Split template text into literal chunks and placeholders
+fn scan(text: &str) -> Vec<Result<&str, Placeholder>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start + 2..].find("}}") {
+            Some(e) => start + 2 + e,
+            None => break,
+        };
+        let inner = rest[start + 2..end].trim();
+        let placeholder = if let Some(path) = inner.strip_prefix("file:") {
+            Some(Placeholder::File(path.trim().to_string()))
+        } else {
+            let (name, default) = match inner.split_once('|') {
+                Some((n, d)) => (n.trim(), Some(d.trim().to_string())),
+                None => (inner, None),
+            };
+            let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.');
+            valid.then(|| Placeholder::Var { name: name.to_string(), default })
+        };
+        match placeholder {
+            Some(p) => {
+                parts.push(Ok(&rest[..start]));
+                parts.push(Err(p));
+            }
+            // not a placeholder, keep the braces as text
+            None => parts.push(Ok(&rest[..end + 2])),
+        }
+        rest = &rest[end + 2..];
+    }
+    parts.push(Ok(rest));
+    parts
+}
+
+/// All placeholders in a template, in order of first appearance
+pub fn placeholders(text: &str) -> Vec<Placeholder> {
+    let mut out: Vec<Placeholder> = Vec::new();
+    for p in scan(text).into_iter().filter_map(Result::err) {
+        if !out.contains(&p) {
+            out.push(p);
+        }
+    }
+    out
+}
+
+/// Variables that must be given because they have no default
+pub fn required_vars(text: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for p in placeholders(text) {
+        if let Placeholder::Var { name, default: None } = p {
+            if !out.contains(&name) {
+                out.push(name);
+            }
+        }
+    }
+    out
+}
+
+/// Fill in a template. Fails listing every required variable that is missing,
+/// or naming a `{{file:...}}` that can't be read.
+pub fn render<F>(text: &str, vars: &HashMap<String, String>, read_file: F) -> Result<String, String>
+where
+    F: Fn(&str) -> std::io::Result<String>,
+{
+    let missing: Vec<String> = required_vars(text).into_iter().filter(|v| !vars.contains_key(v)).collect();
+    if !missing.is_empty() {
+        return Err(format!("missing required variable(s): {}", missing.join(", ")));
+    }
+    let mut out = String::new();
+    for part in scan(text) {
+        match part {
+            Ok(literal) => out.push_str(literal),
+            Err(Placeholder::Var { name, default }) => {
+                out.push_str(vars.get(&name).or(default.as_ref()).map(String::as_str).unwrap_or_default())
+            }
+            Err(Placeholder::File(path)) => {
+                let contents = read_file(&path).map_err(|e| format!("can't read {{{{file:{}}}}}: {}", path, e))?;
+                out.push_str(&contents);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Parse `--var k=v` arguments
+pub fn parse_vars(pairs: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut vars = HashMap::new();
+    for pair in pairs {
+        match pair.split_once('=') {
+            Some((k, v)) if !k.trim().is_empty() => {
+                vars.insert(k.trim().to_string(), v.to_string());
+            }
+            _ => return Err(format!("invalid variable '{}': use key=value", pair)),
+        }
+    }
+    Ok(vars)
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use yo::backup::{self, merge_chats, restore_secrets, strip_secrets, StatePaths, TempDir, FORMAT, FORMAT_VERSION};
+use yo::branches::{active_path, append, set_head};
+use yo::chats;
+use yo::db::{self, schema_version, SCHEMA_VERSION};
+use yo::tags::{add_tags, tags_for_chat};
+
+const CONFIG: &str = "source = \"openai\"\nmodel = \"gpt-4o\"\nopenai_api_key = \"sk-secret\"\nmax_tokens = 500\n";
+
+fn state(dir: &Path) -> StatePaths {
+    StatePaths {
+        db: dir.join("chats.db"),
+        config: dir.join("config.toml"),
+        templates: dir.join("templates"),
+        personas: dir.join("personas"),
+        current_chat: dir.join("current_chat"),
+    }
+}
+
+/// A yo home with one branched, tagged chat, a remembered fact, a template and a config
+fn populated(dir: &Path) -> StatePaths {
+    fs::create_dir_all(dir).unwrap();
+    let paths = state(dir);
+    let conn = db::open(&paths.db).unwrap();
+    let chat = chats::create(&conn, "rust", None).unwrap();
+    let q = append(&conn, chat, None, "user", "What is a lifetime?").unwrap();
+    let a = append(&conn, chat, Some(q), "assistant", "A scope for references.").unwrap();
+    append(&conn, chat, Some(q), "assistant", "An alternate answer").unwrap();
+    set_head(&conn, chat, Some(a)).unwrap();
+    add_tags(&conn, chat, &["work".into()]).unwrap();
+    conn.execute("INSERT INTO user_profile (key, value) VALUES ('name', 'Sam')", []).unwrap();
+    fs::write(&paths.config, CONFIG).unwrap();
+    fs::create_dir_all(&paths.templates).unwrap();
+    fs::write(paths.templates.join("review.md"), "Review {{input}}").unwrap();
+    fs::write(&paths.current_chat, chat.to_string()).unwrap();
+    paths
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("yo_backup_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_secrets_are_stripped_and_restored() {
+    let stripped = strip_secrets(CONFIG).unwrap();
+    assert!(!stripped.contains("sk-secret"));
+    assert!(stripped.contains("max_tokens = 500"));
+    assert!(stripped.contains("model = \"gpt-4o\""));
+    let restored = restore_secrets(&stripped, Some(CONFIG)).unwrap();
+    assert!(restored.contains("openai_api_key = \"sk-secret\""));
+    assert_eq!(restore_secrets(&stripped, None).unwrap(), stripped);
+}
+
+#[test]
+fn test_backup_round_trip() {
+    let dir = scratch("round_trip");
+    let paths = populated(&dir.join("home"));
+    let archive = dir.join("yo-backup.tar.zst");
+    let manifest = backup::create(&paths, &archive).unwrap();
+    assert_eq!((manifest.format.as_str(), manifest.format_version), (FORMAT, FORMAT_VERSION));
+    assert_eq!((manifest.chats, manifest.messages), (1, 3));
+    assert_eq!(manifest.schema_version, SCHEMA_VERSION);
+    let names: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(names, vec!["chats.db", "config.toml", "current_chat", "templates/review.md"]);
+
+    let out = TempDir::new("yo_backup_test").unwrap();
+    assert_eq!(backup::unpack(&archive, out.path()).unwrap(), manifest);
+    let config = fs::read_to_string(out.path().join("config.toml")).unwrap();
+    assert!(!config.contains("sk-secret"));
+    let conn = db::open(&out.path().join("chats.db")).unwrap();
+    let texts: Vec<String> = active_path(&conn, 1).unwrap().into_iter().map(|t| t.content).collect();
+    assert_eq!(texts, vec!["What is a lifetime?", "A scope for references."]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_backup_files_are_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let dir = scratch("private");
+    let paths = populated(&dir.join("home"));
+    let archive = dir.join("yo-backup.tar.zst");
+    // a stale partial file from an interrupted run doesn't keep its permissions
+    fs::write(archive.with_extension("partial"), "stale").unwrap();
+    fs::set_permissions(archive.with_extension("partial"), fs::Permissions::from_mode(0o644)).unwrap();
+    backup::create(&paths, &archive).unwrap();
+    assert_eq!(mode(&archive), 0o600);
+
+    let out = TempDir::new("yo_backup_test").unwrap();
+    assert_eq!(mode(out.path()), 0o700);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_unpack_rejects_bad_archives_and_migrates_old_ones() {
+    let dir = scratch("validate");
+    let junk = dir.join("junk.tar.zst");
+    fs::write(&junk, "not an archive").unwrap();
+    assert!(backup::unpack(&junk, &dir.join("junk")).is_err());
+
+    // a backup made by a yo with an older schema is migrated on restore
+    fs::create_dir_all(dir.join("old")).unwrap();
+    let paths = state(&dir.join("old"));
+    Connection::open(&paths.db).unwrap().execute_batch(include_str!("fixtures/schema_v3.sql")).unwrap();
+    let archive = dir.join("old.tar.zst");
+    let manifest = backup::create(&paths, &archive).unwrap();
+    assert_eq!(manifest.schema_version, 3);
+    let out = TempDir::new("yo_backup_test").unwrap();
+    backup::unpack(&archive, out.path()).unwrap();
+    let conn = Connection::open(out.path().join("chats.db")).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_merge_keeps_branches_and_skips_known_chats() {
+    let dir = scratch("merge");
+    let source_paths = populated(&dir.join("source"));
+    let source = db::open(&source_paths.db).unwrap();
+    let target = empty_db();
+    let mine = chats::create(&target, "mine", None).unwrap();
+    append(&target, mine, None, "user", "hello").unwrap();
+    target.execute("INSERT INTO user_profile (key, value) VALUES ('name', 'Alex')", []).unwrap();
+
+    let stats = merge_chats(&target, &source).unwrap();
+    assert_eq!((stats.chats, stats.skipped, stats.messages, stats.memories), (1, 0, 3, 0));
+    let merged = 2;
+    let texts: Vec<String> = active_path(&target, merged).unwrap().into_iter().map(|t| t.content).collect();
+    assert_eq!(texts, vec!["What is a lifetime?", "A scope for references."]);
+    assert_eq!(tags_for_chat(&target, merged).unwrap(), vec!["work".to_string()]);
+    // existing facts win
+    let name: String = target.query_row("SELECT value FROM user_profile WHERE key = 'name'", [], |row| row.get(0)).unwrap();
+    assert_eq!(name, "Alex");
+
+    let again = merge_chats(&target, &source).unwrap();
+    assert_eq!((again.chats, again.skipped), (0, 1));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_replace_keeps_api_key_and_previous_database() {
+    let dir = scratch("replace");
+    let archive = dir.join("b.tar.zst");
+    backup::create(&populated(&dir.join("source")), &archive).unwrap();
+
+    let home = dir.join("home");
+    fs::create_dir_all(&home).unwrap();
+    let paths = state(&home);
+    db::open(&paths.db).unwrap();
+    fs::write(&paths.config, "source = \"ollama\"\nmodel = \"llama3\"\nopenai_api_key = \"sk-mine\"\n").unwrap();
+    fs::create_dir_all(&paths.templates).unwrap();
+    fs::write(paths.templates.join("old.md"), "old").unwrap();
+
+    let out = TempDir::new("yo_backup_test").unwrap();
+    backup::unpack(&archive, out.path()).unwrap();
+    let previous = backup::replace(&paths, out.path()).unwrap();
+    let config = fs::read_to_string(&paths.config).unwrap();
+    assert!(config.contains("model = \"gpt-4o\""));
+    assert!(config.contains("openai_api_key = \"sk-mine\""));
+    assert!(paths.templates.join("review.md").exists());
+    assert!(!paths.templates.join("old.md").exists());
+    assert_eq!(fs::read_to_string(&paths.current_chat).unwrap(), "1");
+    assert_eq!(previous, Some(home.join("chats.db.pre-restore.bak")));
+    assert!(home.join("chats.db.pre-restore.bak").exists());
+    let conn = Connection::open(&paths.db).unwrap();
+    assert_eq!(conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get::<_, i64>(0)).unwrap(), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
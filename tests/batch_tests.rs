@@ -0,0 +1,82 @@
+use std::path::Path;
+use std::time::Duration;
+use yo::batch::{answered_ids, default_output, parse_csv, parse_file, parse_jsonl, BatchResult, RateLimiter};
+
+#[test]
+fn test_parse_jsonl() {
+    let text = r#"{"id": "q1", "prompt": "Translate {{word}}", "model": "gpt-4o", "vars": {"word": "cat", "n": 2}}
+
+{"id": 7, "prompt": "plain", "system": "Be brief."}
+{"prompt": "no id"}
+"#;
+    let items = parse_jsonl(text).unwrap();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].id, "q1");
+    assert_eq!(items[0].model.as_deref(), Some("gpt-4o"));
+    assert_eq!(items[0].vars["n"], "2");
+    assert_eq!(items[0].render().unwrap(), "Translate cat");
+    assert_eq!((items[1].id.as_str(), items[1].line), ("7", 3));
+    assert_eq!(items[1].system.as_deref(), Some("Be brief."));
+    assert_eq!(items[2].id, "4");
+
+    assert!(parse_jsonl("{\"model\": \"x\"}").unwrap_err().contains("line 1"));
+    assert!(parse_jsonl("{\"prompt\": \"a\"}\nnot json").unwrap_err().contains("line 2"));
+}
+
+#[test]
+fn test_prompts_without_vars_are_sent_verbatim() {
+    let items = parse_jsonl(r#"{"prompt": "fill in {{this}}"}"#).unwrap();
+    assert_eq!(items[0].render().unwrap(), "fill in {{this}}");
+    let items = parse_jsonl(r#"{"prompt": "{{a}} {{b}}", "vars": {"a": "x"}}"#).unwrap();
+    assert!(items[0].render().unwrap_err().contains("b"));
+}
+
+#[test]
+fn test_parse_csv() {
+    let text = "id,prompt,model,lang\nr1,\"Explain {{lang}}, briefly\",,Rust\n,Hello,gpt-4o,Go\n";
+    let items = parse_csv(text).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].id, "r1");
+    assert_eq!(items[0].model, None);
+    assert_eq!(items[0].render().unwrap(), "Explain Rust, briefly");
+    assert_eq!(items[1].id, "2");
+    assert_eq!(items[1].model.as_deref(), Some("gpt-4o"));
+    assert!(parse_csv("id,question\n1,hi\n").is_err());
+
+    assert_eq!(parse_file(Path::new("x.CSV"), text).unwrap().len(), 2);
+    assert_eq!(default_output(Path::new("/tmp/prompts.jsonl")), Path::new("/tmp/prompts.results.jsonl"));
+}
+
+#[test]
+fn test_answered_ids_skip_failures_and_partial_lines() {
+    let ok = BatchResult {
+        id: "a".into(), line: 1, model: "m".into(), response: Some("yes".into()), usage: None, error: None, elapsed_ms: 3,
+    };
+    let failed = BatchResult { id: "b".into(), response: None, error: Some("timeout".into()), ..ok.clone() };
+    let results = format!(
+        "{}\n{}\n{{\"id\":\"c\",\"line\":3,\"mod",
+        serde_json::to_string(&ok).unwrap(),
+        serde_json::to_string(&failed).unwrap()
+    );
+    let ids = answered_ids(&results);
+    assert!(ids.contains("a"));
+    assert!(!ids.contains("b"));
+    assert!(!ids.contains("c"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_rate_limiter_spaces_requests() {
+    let limiter = RateLimiter::per_minute(Some(60));
+    let start = tokio::time::Instant::now();
+    for _ in 0..3 {
+        limiter.wait().await;
+    }
+    assert_eq!(start.elapsed().as_secs(), 2);
+
+    let unlimited = RateLimiter::per_minute(None);
+    let start = tokio::time::Instant::now();
+    for _ in 0..3 {
+        unlimited.wait().await;
+    }
+    assert!(start.elapsed() < Duration::from_millis(1));
+}
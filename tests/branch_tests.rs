@@ -0,0 +1,186 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use yo::branches::{
+    active_head, active_path, append, children, delete_message, fork_points, latest_leaf_under, leaves, path_to,
+    question_context, select_context, set_flags, set_head, unhide,
+};
+use yo::db::init_schema;
+
+fn test_db() -> Connection {
+    let conn = empty_db();
+    conn.execute("INSERT INTO chats (title) VALUES ('test')", []).unwrap();
+    conn
+}
+
+fn contents(conn: &Connection) -> Vec<String> {
+    active_path(conn, 1).unwrap().into_iter().map(|t| t.content).collect()
+}
+
+#[test]
+fn test_append_builds_linear_branch() {
+    let conn = test_db();
+    let q = append(&conn, 1, None, "user", "q1").unwrap();
+    let a = append(&conn, 1, Some(q), "assistant", "a1").unwrap();
+    assert_eq!(active_head(&conn, 1).unwrap(), Some(a));
+    assert_eq!(contents(&conn), vec!["q1", "a1"]);
+}
+
+#[test]
+fn test_fork_keeps_original_branch() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    let q2 = append(&conn, 1, Some(a1), "user", "q2").unwrap();
+    let a2 = append(&conn, 1, Some(q2), "assistant", "a2").unwrap();
+
+    // fork at a1 and continue differently
+    set_head(&conn, 1, Some(a1)).unwrap();
+    let q3 = append(&conn, 1, Some(a1), "user", "q3").unwrap();
+    assert_eq!(contents(&conn), vec!["q1", "a1", "q3"]);
+
+    let tips: Vec<i64> = leaves(&conn, 1).unwrap().into_iter().map(|t| t.id).collect();
+    assert_eq!(tips, vec![a2, q3]);
+    assert_eq!(fork_points(&conn, 1).unwrap().get(&a1), Some(&2));
+
+    // switching back to the original branch lands on its tip
+    set_head(&conn, 1, Some(latest_leaf_under(&conn, q2).unwrap())).unwrap();
+    assert_eq!(contents(&conn), vec!["q1", "a1", "q2", "a2"]);
+}
+
+#[test]
+fn test_legacy_messages_are_linked() {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE chats (id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT, created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP, system_prompt TEXT, tags TEXT);
+        CREATE TABLE messages (id INTEGER PRIMARY KEY AUTOINCREMENT, chat_id INTEGER, role TEXT, content TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP, FOREIGN KEY(chat_id) REFERENCES chats(id));
+        INSERT INTO chats (title) VALUES ('a'), ('b');
+        INSERT INTO messages (chat_id, role, content) VALUES (1, 'user', 'q1'), (2, 'user', 'other'), (1, 'assistant', 'a1');
+        "#,
+    ).unwrap();
+    init_schema(&conn).unwrap();
+    assert_eq!(contents(&conn), vec!["q1", "a1"]);
+}
+
+#[test]
+fn test_regenerated_answers_are_alternates() {
+    let conn = test_db();
+    let q = append(&conn, 1, None, "user", "q").unwrap();
+    let first = append(&conn, 1, Some(q), "assistant", "first").unwrap();
+    let second = append(&conn, 1, Some(q), "assistant", "second").unwrap();
+    assert_eq!(contents(&conn), vec!["q", "second"]);
+    let alts: Vec<i64> = children(&conn, 1, Some(q)).unwrap().into_iter().map(|t| t.id).collect();
+    assert_eq!(alts, vec![first, second]);
+
+    // picking the first answer makes it current again
+    set_head(&conn, 1, Some(latest_leaf_under(&conn, first).unwrap())).unwrap();
+    assert_eq!(contents(&conn), vec!["q", "first"]);
+
+    // an edited question is a sibling of the original
+    let edited = append(&conn, 1, None, "user", "q edited").unwrap();
+    let roots: Vec<i64> = children(&conn, 1, None).unwrap().into_iter().map(|t| t.id).collect();
+    assert_eq!(roots, vec![q, edited]);
+}
+
+#[test]
+fn test_context_skips_hidden_and_keeps_pinned() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    let q2 = append(&conn, 1, Some(a1), "user", "q2").unwrap();
+    let a2 = append(&conn, 1, Some(q2), "assistant", "a2").unwrap();
+    let q3 = append(&conn, 1, Some(a2), "user", "q3").unwrap();
+    set_flags(&conn, q1, false, true).unwrap();
+    set_flags(&conn, a2, true, false).unwrap();
+
+    let ctx = |limit| -> Vec<String> {
+        let path = path_to(&conn, q3).unwrap();
+        select_context(path, limit).into_iter().map(|t| t.content).collect()
+    };
+    assert_eq!(ctx(None), vec!["q1", "a1", "q2", "q3"]);
+    // only the newest two visible messages, but the pinned one survives
+    assert_eq!(ctx(Some(2)), vec!["q1", "q2", "q3"]);
+}
+
+#[test]
+fn test_context_leaves_out_pins_on_other_branches() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    let alternate = append(&conn, 1, Some(q1), "assistant", "a1 alternate").unwrap();
+    set_flags(&conn, alternate, false, true).unwrap();
+    let q2 = append(&conn, 1, Some(a1), "user", "q2").unwrap();
+
+    let context: Vec<String> =
+        select_context(path_to(&conn, q2).unwrap(), Some(1)).into_iter().map(|t| t.content).collect();
+    assert_eq!(context, vec!["q2"]);
+}
+
+#[test]
+fn test_regen_and_edit_with_a_newer_pinned_message() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    let q2 = append(&conn, 1, Some(a1), "user", "q2").unwrap();
+    let a2 = append(&conn, 1, Some(q2), "assistant", "a2").unwrap();
+    set_flags(&conn, a1, false, true).unwrap();
+    set_flags(&conn, a2, false, true).unwrap();
+
+    // regen of the first question
+    let (history, question) = question_context(&conn, q1, None).unwrap().unwrap();
+    assert!(history.is_empty());
+    assert_eq!(question, "q1");
+    // edit-last: the edited question is a sibling of q2
+    let edited = append(&conn, 1, Some(a1), "user", "q2 edited").unwrap();
+    let (history, question) = question_context(&conn, edited, Some(1)).unwrap().unwrap();
+    assert_eq!(history.into_iter().map(|t| t.content).collect::<Vec<_>>(), vec!["a1"]);
+    assert_eq!(question, "q2 edited");
+    assert!(question_context(&conn, 99, None).unwrap().is_none());
+}
+
+#[test]
+fn test_unhide_keeps_pins() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    set_flags(&conn, q1, false, true).unwrap();
+    set_flags(&conn, a1, true, false).unwrap();
+
+    assert!(unhide(&conn, q1).unwrap());
+    assert!(unhide(&conn, a1).unwrap());
+    assert!(!unhide(&conn, 99).unwrap());
+    let flags: Vec<(bool, bool)> = active_path(&conn, 1).unwrap().iter().map(|m| (m.hidden, m.pinned)).collect();
+    assert_eq!(flags, vec![(false, true), (false, false)]);
+}
+
+#[test]
+fn test_delete_message_reattaches_replies() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    let q2 = append(&conn, 1, Some(a1), "user", "q2").unwrap();
+    assert!(delete_message(&conn, a1).unwrap());
+    assert!(!delete_message(&conn, a1).unwrap());
+    assert_eq!(contents(&conn), vec!["q1", "q2"]);
+
+    // deleting the head moves it to the parent
+    assert!(delete_message(&conn, q2).unwrap());
+    assert_eq!(active_head(&conn, 1).unwrap(), Some(q1));
+}
+
+#[test]
+fn test_failed_delete_message_changes_nothing() {
+    let conn = test_db();
+    let q1 = append(&conn, 1, None, "user", "q1").unwrap();
+    let a1 = append(&conn, 1, Some(q1), "assistant", "a1").unwrap();
+    // fail the last statement, after the message is already gone
+    conn.execute_batch("CREATE TRIGGER refuse BEFORE UPDATE OF head_message_id ON chats BEGIN SELECT RAISE(ABORT, 'no'); END;").unwrap();
+
+    assert!(delete_message(&conn, a1).is_err());
+    assert_eq!(contents(&conn), vec!["q1", "a1"]);
+    assert_eq!(active_head(&conn, 1).unwrap(), Some(a1));
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::empty_db;
+use yo::chats;
+use yo::config::{Config, ModelSettings};
+
+fn gpt() -> ModelSettings {
+    ModelSettings { source: "openai".into(), model: "gpt-4o".into(), temperature: Some(0.3), max_tokens: None }
+}
+
+#[test]
+fn test_chat_remembers_settings() {
+    let conn = empty_db();
+    let id = chats::create(&conn, "work", Some(&gpt())).unwrap();
+    assert_eq!(chats::settings(&conn, id).unwrap(), Some(gpt()));
+
+    let llama = ModelSettings { source: "ollama".into(), model: "llama3".into(), ..Default::default() };
+    assert!(chats::set_settings(&conn, id, &llama).unwrap());
+    assert_eq!(chats::settings(&conn, id).unwrap(), Some(llama));
+}
+
+#[test]
+fn test_chat_without_settings() {
+    let conn = empty_db();
+    let id = chats::create(&conn, "legacy", None).unwrap();
+    assert!(chats::exists(&conn, id).unwrap());
+    assert_eq!(chats::settings(&conn, id).unwrap(), None);
+    assert!(!chats::exists(&conn, id + 1).unwrap());
+}
+
+#[test]
+fn test_config_apply_chat_settings() {
+    let mut cfg = Config { source: "ollama".into(), model: "llama3".into(), ..Default::default() };
+    cfg.apply(&gpt());
+    assert_eq!(cfg.source, "openai");
+    assert_eq!(cfg.model, "gpt-4o");
+    assert_eq!(cfg.temperature, Some(0.3));
+    assert_eq!(cfg.settings().label(), "openai:gpt-4o t=0.3");
+}
@@ -2,7 +2,7 @@ use std::{env, fs, path::PathBuf};
 use std::process::Command as ProcessCommand;
 use clap::Parser;
 use dotenv::{dotenv, from_filename};
-use yo::cli::{Cli, Command as CliCommand};
+use yo::cli::{Cli, Command as CliCommand, ImportSource};
 use yo::commands;
 use serial_test::serial;
 
@@ -12,20 +12,29 @@ fn load_test_env() {
     let _ = dotenv();
 }
 
-// Manage a temporary config directory for isolated tests
+// Variables pointing yo at its config, data and current chat, replaced by a temporary directory in tests
+const PATH_VARS: [&str; 5] = ["XDG_CONFIG_HOME", "XDG_DATA_HOME", "YO_HOME", "YO_CHAT", "YO_SESSION"];
+
+// Manage a temporary config and data directory for isolated tests
 struct TestEnv {
-    original: Option<String>,
+    original: Vec<(&'static str, Option<String>)>,
     temp_dir: PathBuf,
 }
 
 impl TestEnv {
     fn new() -> Self {
         load_test_env();
-        let original = env::var("XDG_CONFIG_HOME").ok();
+        let original = PATH_VARS.iter().map(|v| (*v, env::var(v).ok())).collect();
         let temp_dir = env::temp_dir().join("yo_test_config");
         let yo_dir = temp_dir.join("yo");
         fs::create_dir_all(&yo_dir).unwrap();
-        unsafe { env::set_var("XDG_CONFIG_HOME", &temp_dir); }
+        unsafe {
+            env::set_var("XDG_CONFIG_HOME", &temp_dir);
+            env::set_var("XDG_DATA_HOME", &temp_dir);
+            env::remove_var("YO_HOME");
+            env::remove_var("YO_CHAT");
+            env::remove_var("YO_SESSION");
+        }
 
         // Write default config
         let config_path = yo_dir.join("config.toml");
@@ -41,10 +50,11 @@ impl TestEnv {
 
 impl Drop for TestEnv {
     fn drop(&mut self) {
-        if let Some(val) = &self.original {
-            unsafe { env::set_var("XDG_CONFIG_HOME", val); }
-        } else {
-            unsafe { env::remove_var("XDG_CONFIG_HOME"); }
+        for (var, value) in &self.original {
+            match value {
+                Some(val) => unsafe { env::set_var(var, val) },
+                None => unsafe { env::remove_var(var) },
+            }
         }
         let _ = fs::remove_dir_all(&self.temp_dir);
     }
@@ -52,7 +62,7 @@ impl Drop for TestEnv {
 
 // Ollama helpers
 fn is_ollama_available() -> bool {
-    ProcessCommand::new("which").arg("ollama").output().map_or(false, |o| o.status.success())
+    ProcessCommand::new("which").arg("ollama").output().is_ok_and(|o| o.status.success())
 }
 
 fn is_ollama_model_available(model: &str) -> bool {
@@ -60,40 +70,71 @@ fn is_ollama_model_available(model: &str) -> bool {
         return false;
     }
     ProcessCommand::new("ollama").arg("list").output()
-        .map_or(false, |o| String::from_utf8_lossy(&o.stdout).contains(model))
+        .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains(model))
 }
 
 // Decide if external tests should run
 fn should_run_external_api_tests() -> bool {
     load_test_env();
-    env::var("ENABLE_EXTERNAL_API_TESTS").map_or(false, |v| v == "true")
+    env::var("ENABLE_EXTERNAL_API_TESTS").is_ok_and(|v| v == "true")
 }
 
 // --- CLI parsing tests ---
 #[test]
 fn test_ask_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "ask", "Hello"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "ask", "Hello"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Ask { question, no_history }) => {
+            assert_eq!(question, vec!["Hello"]);
+            assert!(!no_history);
+        }
+        _ => panic!("Expected Ask"),
+    }
+}
+
+#[test]
+fn test_ask_incognito_parsing() {
+    let cli = Cli::try_parse_from(["yo", "ask", "--incognito", "secret", "stuff"]).unwrap();
     match cli.command {
-        Some(CliCommand::Ask { question }) => assert_eq!(question, vec!["Hello"]),
+        Some(CliCommand::Ask { question, no_history }) => {
+            assert_eq!(question, vec!["secret", "stuff"]);
+            assert!(no_history);
+        }
         _ => panic!("Expected Ask"),
     }
 }
 
+#[test]
+fn test_incognito_ask_leaves_chats_db_alone() {
+    let home = env::temp_dir().join(format!("yo_incognito_gc_{}", std::process::id()));
+    fs::create_dir_all(&home).unwrap();
+    // a backend that fails at once, and auto gc due on every run
+    fs::write(home.join("config.toml"), "source = \"ollama\"\nmodel = \"none\"\n\n[retention]\nauto_gc_every = 1\n").unwrap();
+    let yo = |args: &[&str]| ProcessCommand::new(env!("CARGO_BIN_EXE_yo")).arg("--data-dir").arg(&home).args(args).output().unwrap();
+
+    yo(&["ask", "--no-history", "hi"]);
+    assert!(!home.join("chats.db").exists());
+    assert!(!home.join("gc_counter").exists());
+    yo(&["list-chats"]);
+    assert!(home.join("chats.db").exists());
+    let _ = fs::remove_dir_all(&home);
+}
+
 #[test]
 fn test_setup_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "setup"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "setup"]).unwrap();
     assert!(matches!(cli.command, Some(CliCommand::Setup)));
 }
 
 #[test]
 fn test_config_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "config"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "config"]).unwrap();
     assert!(matches!(cli.command, Some(CliCommand::Config)));
 }
 
 #[test]
 fn test_switch_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "switch", "openai"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "switch", "openai"]).unwrap();
     match cli.command {
         Some(CliCommand::Switch { model }) => assert_eq!(model, "openai"),
         _ => panic!("Expected Switch openai"),
@@ -102,7 +143,7 @@ fn test_switch_parsing() {
 
 #[test]
 fn test_gpt_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "gpt", "gpt-4"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "gpt", "gpt-4"]).unwrap();
     match cli.command {
         Some(CliCommand::Gpt { model }) => assert_eq!(model, "gpt-4"),
         _ => panic!("Expected Gpt gpt-4"),
@@ -111,25 +152,272 @@ fn test_gpt_parsing() {
 
 #[test]
 fn test_list_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "list"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "list"]).unwrap();
     assert!(matches!(cli.command, Some(CliCommand::List)));
 }
 
 #[test]
 fn test_current_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "current"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "current"]).unwrap();
     assert!(matches!(cli.command, Some(CliCommand::Current)));
 }
 
 #[test]
 fn test_other_parsing() {
-    let cli = Cli::try_parse_from(&["yo", "foo", "bar"]).unwrap();
+    let cli = Cli::try_parse_from(["yo", "foo", "bar"]).unwrap();
     match cli.command {
         Some(CliCommand::Other(args)) => assert_eq!(args, vec!["foo", "bar"]),
         _ => panic!("Expected Other"),
     }
 }
 
+#[test]
+fn test_remember_parsing() {
+    let cli = Cli::try_parse_from(["yo", "remember", "3", "--yes"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Remember { chat_id, yes }) => {
+            assert_eq!(chat_id, Some(3));
+            assert!(yes);
+        }
+        _ => panic!("Expected Remember"),
+    }
+}
+
+#[test]
+fn test_tag_parsing() {
+    let cli = Cli::try_parse_from(["yo", "tag", "3", "work", "rust"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Tag { chat_id, tags }) => {
+            assert_eq!(chat_id, 3);
+            assert_eq!(tags, vec!["work", "rust"]);
+        }
+        _ => panic!("Expected Tag"),
+    }
+}
+
+#[test]
+fn test_delete_chat_by_tag_parsing() {
+    let cli = Cli::try_parse_from(["yo", "delete-chat", "--tag", "old"]).unwrap();
+    match cli.command {
+        Some(CliCommand::DeleteChat { chat_id, tags }) => {
+            assert_eq!(chat_id, None);
+            assert_eq!(tags, vec!["old"]);
+        }
+        _ => panic!("Expected DeleteChat"),
+    }
+    assert!(Cli::try_parse_from(["yo", "delete-chat"]).is_err());
+}
+
+#[test]
+fn test_regen_parsing() {
+    let cli = Cli::try_parse_from(["yo", "regen", "--model", "gpt-4o", "-t", "0.2"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Regen { model, temperature }) => {
+            assert_eq!(model.as_deref(), Some("gpt-4o"));
+            assert_eq!(temperature, Some(0.2));
+        }
+        _ => panic!("Expected Regen"),
+    }
+}
+
+#[test]
+fn test_pick_alias_parsing() {
+    let cli = Cli::try_parse_from(["yo", "pick", "7"]).unwrap();
+    assert!(matches!(cli.command, Some(CliCommand::SwitchBranch { message_id: 7 })));
+}
+
+#[test]
+fn test_chat_model_parsing() {
+    let cli = Cli::try_parse_from(["yo", "chat-model", "llama3", "--backend", "ollama", "--chat-id", "2"]).unwrap();
+    match cli.command {
+        Some(CliCommand::ChatModel { model, backend, temperature, max_tokens, no_temperature, no_max_tokens, chat_id }) => {
+            assert_eq!(model.as_deref(), Some("llama3"));
+            assert_eq!(backend.as_deref(), Some("ollama"));
+            assert_eq!(temperature, None);
+            assert_eq!(max_tokens, None);
+            assert!(!no_temperature && !no_max_tokens);
+            assert_eq!(chat_id, Some(2));
+        }
+        _ => panic!("Expected ChatModel"),
+    }
+    assert!(Cli::try_parse_from(["yo", "chat-model", "-t", "0.5", "--no-temperature"]).is_err());
+}
+
+#[test]
+#[serial]
+fn test_chat_model_backend_and_unset() {
+    let env = TestEnv::new();
+    commands::new_chat(Some("models".into()), None);
+    let settings = || {
+        let conn = yo::db::open(&env.temp_dir.join("yo/chats.db")).unwrap();
+        yo::chats::settings(&conn, 1).unwrap().unwrap()
+    };
+    commands::set_chat_model(None, None, None, Some(Some(0.3)), Some(Some(500)));
+    assert_eq!((settings().temperature, settings().max_tokens), (Some(0.3), Some(500)));
+
+    // no ollama model is configured, so the gpt model isn't carried over
+    commands::set_chat_model(None, None, Some("ollama".into()), None, None);
+    assert_eq!((settings().source.as_str(), settings().model.as_str()), ("openai", "gpt-3.5-turbo"));
+    commands::set_chat_model(None, Some("llama3".into()), Some("ollama".into()), None, None);
+    assert_eq!(settings().model, "llama3");
+    // back to the backend of config.toml, which has a model for it
+    commands::set_chat_model(None, None, Some("openai".into()), None, None);
+    assert_eq!(settings().model, "gpt-3.5-turbo");
+
+    commands::set_chat_model(None, None, None, Some(None), Some(None));
+    assert_eq!((settings().temperature, settings().max_tokens), (None, None));
+}
+
+#[test]
+fn test_as_persona_parsing() {
+    let cli = Cli::try_parse_from(["yo", "--as", "reviewer", "check", "this"]).unwrap();
+    assert_eq!(cli.persona.as_deref(), Some("reviewer"));
+    match cli.command {
+        Some(CliCommand::Other(args)) => assert_eq!(args, vec!["check", "this"]),
+        _ => panic!("Expected Other"),
+    }
+    let cli = Cli::try_parse_from(["yo", "new-chat", "--persona", "reviewer"]).unwrap();
+    assert!(matches!(cli.command, Some(CliCommand::NewChat { persona: Some(_), .. })));
+}
+
+#[test]
+fn test_bind_parsing() {
+    let cli = Cli::try_parse_from(["yo", "bind", "3", "--dir", "/work/repo"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Bind { chat_id, dir }) => {
+            assert_eq!(chat_id, Some(3));
+            assert_eq!(dir.as_deref(), Some(std::path::Path::new("/work/repo")));
+        }
+        _ => panic!("Expected Bind"),
+    }
+    let cli = Cli::try_parse_from(["yo", "unbind"]).unwrap();
+    assert!(matches!(cli.command, Some(CliCommand::Unbind { dir: None })));
+}
+
+#[test]
+fn test_batch_parsing() {
+    let cli = Cli::try_parse_from(["yo", "batch", "prompts.jsonl", "-j", "8", "--rate", "30"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Batch { file, output, concurrency, rate, save }) => {
+            assert_eq!(file, std::path::PathBuf::from("prompts.jsonl"));
+            assert_eq!(output, None);
+            assert_eq!(concurrency, 8);
+            assert_eq!(rate, Some(30));
+            assert!(!save);
+        }
+        _ => panic!("Expected Batch"),
+    }
+}
+
+#[test]
+fn test_search_parsing() {
+    let cli = Cli::try_parse_from(["yo", "search", "\"borrow checker\" OR lifetimes", "--role", "ai", "--chat", "3", "--since", "2024-01-01", "--json"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Search { query, tags, role, chat_id, since, until, limit, semantic, json }) => {
+            assert_eq!(query, "\"borrow checker\" OR lifetimes");
+            assert!(tags.is_empty());
+            assert_eq!(role.as_deref(), Some("ai"));
+            assert_eq!(chat_id, Some(3));
+            assert_eq!(since.as_deref(), Some("2024-01-01"));
+            assert_eq!(until, None);
+            assert_eq!(limit, 20);
+            assert!(!semantic);
+            assert!(json);
+        }
+        _ => panic!("Expected Search"),
+    }
+}
+
+#[test]
+fn test_export_parsing() {
+    let cli = Cli::try_parse_from(["yo", "export", "--all", "--format", "json", "-o", "chats.json"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Export { chat_id, all, format, output }) => {
+            assert_eq!(chat_id, None);
+            assert!(all);
+            assert_eq!(format, "json");
+            assert_eq!(output, Some(std::path::PathBuf::from("chats.json")));
+        }
+        _ => panic!("Expected Export"),
+    }
+    assert!(Cli::try_parse_from(["yo", "export", "3", "--all"]).is_err());
+}
+
+#[test]
+fn test_import_parsing() {
+    let cli = Cli::try_parse_from(["yo", "import", "chatgpt", "conversations.json"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Import { source: ImportSource::Chatgpt { file, dry_run } }) => {
+            assert_eq!(file, std::path::PathBuf::from("conversations.json"));
+            assert!(!dry_run);
+        }
+        _ => panic!("Expected Import"),
+    }
+    let cli = Cli::try_parse_from(["yo", "import", "llm", "--dry-run"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Import { source: ImportSource::Llm { file, dry_run } }) => {
+            assert_eq!(file, None);
+            assert!(dry_run);
+        }
+        _ => panic!("Expected Import"),
+    }
+}
+
+#[test]
+fn test_backup_restore_parsing() {
+    let cli = Cli::try_parse_from(["yo", "backup"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Backup { output }) => assert_eq!(output, std::path::PathBuf::from("yo-backup.tar.zst")),
+        _ => panic!("Expected Backup"),
+    }
+    let cli = Cli::try_parse_from(["yo", "restore", "b.tar.zst", "--merge"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Restore { archive, merge, replace }) => {
+            assert_eq!(archive, std::path::PathBuf::from("b.tar.zst"));
+            assert!(merge);
+            assert!(!replace);
+        }
+        _ => panic!("Expected Restore"),
+    }
+    assert!(Cli::try_parse_from(["yo", "restore", "b.tar.zst", "--merge", "--replace"]).is_err());
+}
+
+#[test]
+fn test_data_dir_parsing() {
+    let cli = Cli::try_parse_from(["yo", "list-chats", "--data-dir", "/tmp/yo-work"]).unwrap();
+    assert_eq!(cli.data_dir, Some(PathBuf::from("/tmp/yo-work")));
+}
+
+#[test]
+fn test_shell_init_parsing() {
+    let cli = Cli::try_parse_from(["yo", "shell-init", "zsh"]).unwrap();
+    match cli.command {
+        Some(CliCommand::ShellInit { shell }) => assert_eq!(shell, "zsh"),
+        _ => panic!("Expected ShellInit"),
+    }
+}
+
+#[test]
+fn test_chat_parsing() {
+    let cli = Cli::try_parse_from(["yo", "--as", "reviewer", "chat"]).unwrap();
+    assert!(matches!(cli.command, Some(CliCommand::Chat)));
+    assert_eq!(cli.persona.as_deref(), Some("reviewer"));
+}
+
+#[test]
+fn test_run_template_parsing() {
+    let cli = Cli::try_parse_from(["yo", "run", "review", "--var", "lang=Rust", "--var", "focus=bugs", "--dry-run"]).unwrap();
+    match cli.command {
+        Some(CliCommand::Run { template, vars, no_history, dry_run }) => {
+            assert_eq!(template, "review");
+            assert_eq!(vars, vec!["lang=Rust", "focus=bugs"]);
+            assert!(!no_history);
+            assert!(dry_run);
+        }
+        _ => panic!("Expected Run"),
+    }
+}
+
 // --- Command functionality tests ---
 #[test]
 fn test_show_config_path() {
@@ -143,6 +431,23 @@ fn test_show_current() {
     commands::show_current();
 }
 
+#[test]
+#[serial]
+fn test_current_chat_per_session() {
+    let env = TestEnv::new();
+    let data = env.temp_dir.join("yo");
+    let read = |name: &str| fs::read_to_string(data.join(name)).unwrap();
+    unsafe { env::set_var("YO_SESSION", "tab-1") };
+    commands::new_chat(Some("first".into()), None);
+    unsafe { env::set_var("YO_SESSION", "tab-2") };
+    commands::new_chat(Some("second".into()), None);
+
+    // each terminal keeps its own chat; the global pointer follows the latest switch
+    assert_eq!(read("sessions/tab-1"), "1");
+    assert_eq!(read("sessions/tab-2"), "2");
+    assert_eq!(read("current_chat"), "2");
+}
+
 #[test]
 fn test_ollama_avail() {
     if !is_ollama_available() {
@@ -174,7 +479,7 @@ async fn test_ask_openai() {
     if let Ok(contents) = std::fs::read_to_string(&config_path) {
         println!("Config contents before ask_openai:\n{}", contents);
     }
-    commands::ask(&["Ping".into()]).await;
+    commands::ask(&["Ping".into()], false, None).await;
 }
 
 #[tokio::test]
@@ -185,7 +490,7 @@ async fn test_ask_ollama() {
     if !is_ollama_model_available(&model) { return; }
     let _env = TestEnv::new();
     commands::switch("ollama").await;
-    commands::ask(&["Ping".into()]).await;
+    commands::ask(&["Ping".into()], false, None).await;
 }
 
 #[tokio::test]
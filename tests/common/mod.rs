@@ -0,0 +1,11 @@
+//! Fixtures shared by the integration tests
+
+use rusqlite::Connection;
+use yo::db::init_schema;
+
+/// An in-memory database with the current schema and no chats
+pub fn empty_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    init_schema(&conn).unwrap();
+    conn
+}
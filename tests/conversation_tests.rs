@@ -0,0 +1,58 @@
+use serde_json::json;
+use yo::store::Message;
+use yo::conversation::{Conversation, Role, DEFAULT_SYSTEM_PROMPT};
+
+fn turn(id: i64, role: &str, content: &str) -> Message {
+    Message {
+        id,
+        parent_id: None,
+        role: role.into(),
+        content: content.into(),
+        // same second for every message, so only the id can order them
+        created_at: "2025-01-01 00:00:00".into(),
+        hidden: false,
+        pinned: false,
+    }
+}
+
+#[test]
+fn test_history_is_ordered_by_id() {
+    let history = vec![turn(3, "user", "second question"), turn(1, "user", "first question"), turn(2, "assistant", "first answer")];
+    let conv = Conversation::build("sys", &[], history, Some("third question"));
+    assert_eq!(conv.openai_messages(), vec![
+        json!({"role": "system", "content": "sys"}),
+        json!({"role": "user", "content": "first question"}),
+        json!({"role": "assistant", "content": "first answer"}),
+        json!({"role": "user", "content": "second question"}),
+        json!({"role": "user", "content": "third question"}),
+    ]);
+}
+
+#[test]
+fn test_input_is_sent_once() {
+    let conv = Conversation::build(DEFAULT_SYSTEM_PROMPT, &[], Vec::new(), Some("Ping"));
+    let users: Vec<_> = conv.openai_messages().into_iter().filter(|m| m["role"] == "user").collect();
+    assert_eq!(users, vec![json!({"role": "user", "content": "Ping"})]);
+}
+
+#[test]
+fn test_profile_goes_into_system_prompt() {
+    let profile = vec![("os".to_string(), "macOS".to_string())];
+    let conv = Conversation::build("sys", &profile, Vec::new(), None);
+    assert_eq!(conv.openai_messages(), vec![json!({"role": "system", "content": "sys\n\nKnown facts about the user:\n- os: macOS"})]);
+}
+
+#[test]
+fn test_ollama_role_mapping() {
+    let history = vec![turn(1, "user", "hi"), turn(2, "assistant", "hello"), turn(3, "tool", "ignored")];
+    let conv = Conversation::build("sys", &[], history, Some("bye"));
+    assert_eq!(conv.ollama_prompt(), "System: sys\nUser: hi\nAI: hello\nUser: bye\n");
+}
+
+#[test]
+fn test_role_parse() {
+    assert_eq!(Role::parse("assistant"), Some(Role::Assistant));
+    assert_eq!(Role::parse("function"), None);
+    assert_eq!(Role::Assistant.openai(), "assistant");
+    assert_eq!(Role::Assistant.ollama(), "AI");
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use yo::branches::{active_path, append};
+use yo::chats;
+use yo::crypto::{self, Cipher, CryptoError};
+use yo::embeddings;
+use yo::export::load_chat;
+use yo::search::{search, SearchFilter};
+use yo::store::{ChatStore, ProfileEntry};
+
+const PASSPHRASE: &str = "correct horse battery staple";
+
+fn test_db() -> (Connection, i64) {
+    let conn = empty_db();
+    let chat = chats::create(&conn, "rust", None).unwrap();
+    chats::set_system_prompt(&conn, chat, Some("You are a Rust mentor.")).unwrap();
+    let q = append(&conn, chat, None, "user", "How do lifetimes work?").unwrap();
+    append(&conn, chat, Some(q), "assistant", "Lifetimes describe how long references stay valid.").unwrap();
+    (conn, chat)
+}
+
+fn raw_contents(conn: &Connection) -> Vec<String> {
+    let mut stmt = conn.prepare("SELECT content FROM messages ORDER BY id").unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+}
+
+fn texts(conn: &Connection, chat: i64) -> Vec<String> {
+    active_path(conn, chat).unwrap().into_iter().map(|t| t.content).collect()
+}
+
+#[test]
+fn test_seal_and_open() {
+    let cipher = Cipher::derive(PASSPHRASE, b"0123456789abcdef").unwrap();
+    let sealed = cipher.seal("secret plans");
+    assert!(crypto::is_sealed(&sealed));
+    assert!(!sealed.contains("secret"));
+    assert_ne!(sealed, cipher.seal("secret plans"), "every value gets its own nonce");
+    assert_eq!(cipher.open(&sealed).unwrap(), "secret plans");
+
+    let other = Cipher::derive("wrong", b"0123456789abcdef").unwrap();
+    assert!(matches!(other.open(&sealed), Err(CryptoError::WrongPassphrase)));
+    assert!(matches!(cipher.open("yoenc:v1:not base64!"), Err(CryptoError::Corrupt)));
+}
+
+#[test]
+fn test_encrypted_history_reads_transparently() {
+    let (conn, chat) = test_db();
+    let first = raw_contents(&conn)[0].clone();
+    let message_id: i64 = conn.query_row("SELECT MIN(id) FROM messages", [], |row| row.get(0)).unwrap();
+    embeddings::store(&conn, message_id, "test-model", &[0.5, 0.5]).unwrap();
+
+    assert_eq!(crypto::encrypt_all(&conn, PASSPHRASE).unwrap(), 4);
+    assert!(crypto::is_encrypted(&conn).unwrap());
+    assert!(raw_contents(&conn).iter().all(|c| crypto::is_sealed(c) && !c.contains("lifetimes")));
+    assert_ne!(raw_contents(&conn)[0], first);
+
+    // reads, new messages, export and search all see plaintext
+    assert_eq!(texts(&conn, chat), vec!["How do lifetimes work?", "Lifetimes describe how long references stay valid."]);
+    assert_eq!(chats::system_prompt(&conn, chat).unwrap().as_deref(), Some("You are a Rust mentor."));
+    let head = active_path(&conn, chat).unwrap().last().unwrap().id;
+    append(&conn, chat, Some(head), "user", "And borrowing?").unwrap();
+    assert!(crypto::is_sealed(raw_contents(&conn).last().unwrap()));
+    let exported = load_chat(&conn, chat).unwrap().unwrap();
+    assert_eq!(exported.messages[2].content, "And borrowing?");
+    assert_eq!(exported.system_prompt.as_deref(), Some("You are a Rust mentor."));
+    let hits = search(&conn, "lifetimes", &SearchFilter::default()).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits[0].snippet.contains("[Lifetimes]") || hits[0].snippet.contains("[lifetimes]"));
+
+    // nothing readable is left in the on-disk index, and embeddings survive
+    let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'lifetimes OR borrowing'", [], |row| row.get(0)).unwrap();
+    assert_eq!(indexed, 0);
+    let embedded: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0)).unwrap();
+    assert_eq!(embedded, 1);
+    assert!(matches!(crypto::encrypt_all(&conn, PASSPHRASE), Err(CryptoError::AlreadyEncrypted)));
+}
+
+#[test]
+fn test_wrong_or_missing_key() {
+    let (conn, chat) = test_db();
+    crypto::encrypt_all(&conn, PASSPHRASE).unwrap();
+    assert!(matches!(crypto::key_for(&conn, "hunter2"), Err(CryptoError::WrongPassphrase)));
+
+    crypto::register(&conn, None).unwrap();
+    let err = active_path(&conn, chat).unwrap_err();
+    assert!(err.to_string().contains("encrypted"), "{}", err);
+
+    crypto::register(&conn, Some(crypto::key_for(&conn, PASSPHRASE).unwrap())).unwrap();
+    assert_eq!(texts(&conn, chat).len(), 2);
+}
+
+#[test]
+fn test_decrypt_restores_plaintext() {
+    let (conn, chat) = test_db();
+    crypto::encrypt_all(&conn, PASSPHRASE).unwrap();
+    assert_eq!(crypto::decrypt_all(&conn).unwrap(), 4);
+    assert!(!crypto::is_encrypted(&conn).unwrap());
+    assert_eq!(raw_contents(&conn), vec!["How do lifetimes work?", "Lifetimes describe how long references stay valid."]);
+    let prompt: String = conn.query_row("SELECT system_prompt FROM chats WHERE id = ?1", [chat], |row| row.get(0)).unwrap();
+    assert_eq!(prompt, "You are a Rust mentor.");
+
+    // new messages are stored as they are again and the on-disk index is back
+    append(&conn, chat, None, "user", "What about borrowing?").unwrap();
+    assert_eq!(raw_contents(&conn).last().unwrap(), "What about borrowing?");
+    let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'lifetimes OR borrowing'", [], |row| row.get(0)).unwrap();
+    assert_eq!(indexed, 3);
+    assert!(matches!(crypto::decrypt_all(&conn), Err(CryptoError::NotEncrypted)));
+}
+
+#[test]
+fn test_titles_and_profile_are_sealed() {
+    let (conn, chat) = test_db();
+    let store = ChatStore::new(conn);
+    store.set_profile("project", "a borrow checker tutorial").unwrap();
+    crypto::encrypt_all(&store, PASSPHRASE).unwrap();
+
+    let raw = |sql: &str| -> String { store.query_row(sql, [], |row| row.get(0)).unwrap() };
+    assert!(crypto::is_sealed(&raw("SELECT title FROM chats")));
+    assert!(crypto::is_sealed(&raw("SELECT value FROM user_profile")));
+    let later = chats::create(&store, "secret plans", None).unwrap();
+    assert!(crypto::is_sealed(&raw(&format!("SELECT title FROM chats WHERE id = {}", later))));
+
+    // listing, search and the profile see plaintext
+    assert_eq!(store.chat(chat).unwrap().unwrap().title, "rust");
+    assert_eq!(store.chats().unwrap()[0].title, "secret plans");
+    let hits = search(&store, "lifetimes", &SearchFilter::default()).unwrap();
+    assert_eq!(hits[0].chat_title.as_deref(), Some("rust"));
+    store.set_profile("name", "Sam").unwrap();
+    assert_eq!(
+        store.profile().unwrap(),
+        vec![
+            ProfileEntry { key: "name".into(), value: "Sam".into() },
+            ProfileEntry { key: "project".into(), value: "a borrow checker tutorial".into() },
+        ]
+    );
+
+    crypto::decrypt_all(&store).unwrap();
+    assert_eq!(raw(&format!("SELECT title FROM chats WHERE id = {}", chat)), "rust");
+    assert_eq!(raw("SELECT value FROM user_profile WHERE key = 'name'"), "Sam");
+}
+
+#[test]
+fn test_text_that_looks_sealed_is_plain_text() {
+    let store = ChatStore::new(empty_db());
+    let chat = chats::create(&store, "yoenc: notes", None).unwrap();
+    let pasted = Cipher::derive("someone else's", b"0123456789abcdef").unwrap().seal("not ours");
+    let q = append(&store, chat, None, "user", "yoenc: what does this prefix mean?").unwrap();
+    append(&store, chat, Some(q), "assistant", &pasted).unwrap();
+    let written = vec!["yoenc: what does this prefix mean?".to_string(), pasted];
+
+    // a plain database returns everything as written and indexes it
+    assert_eq!(texts(&store, chat), written);
+    assert_eq!(store.chats().unwrap()[0].title, "yoenc: notes");
+    assert_eq!(search(&store, "prefix", &SearchFilter::default()).unwrap().len(), 1);
+
+    // encrypting seals it like any other text
+    crypto::encrypt_all(&store, PASSPHRASE).unwrap();
+    assert!(raw_contents(&store).iter().all(|c| !written.contains(c)));
+    assert_eq!(texts(&store, chat), written);
+    assert_eq!(store.chat(chat).unwrap().unwrap().title, "yoenc: notes");
+    assert_eq!(search(&store, "prefix", &SearchFilter::default()).unwrap().len(), 1);
+    let later = chats::create(&store, "yoenc: later", None).unwrap();
+    assert_eq!(store.chat(later).unwrap().unwrap().title, "yoenc: later");
+
+    crypto::decrypt_all(&store).unwrap();
+    assert_eq!(raw_contents(&store), written);
+    assert_eq!(store.chat(later).unwrap().unwrap().title, "yoenc: later");
+    let indexed: i64 = store.query_row("SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'prefix'", [], |row| row.get(0)).unwrap();
+    assert_eq!(indexed, 1);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use yo::branches::{append, delete_message};
+use yo::chats;
+use yo::config::Config;
+use yo::embeddings::{self, cosine, decode, encode, EmbeddingModel};
+use yo::search::{semantic, SearchFilter};
+
+fn test_db() -> Connection {
+    let conn = empty_db();
+    let chat = chats::create(&conn, "rust", None).unwrap();
+    let q = append(&conn, chat, None, "user", "borrow checker lifetimes").unwrap();
+    append(&conn, chat, Some(q), "assistant", "references must not outlive their data").unwrap();
+    append(&conn, chat, None, "system", "not embedded").unwrap();
+    conn
+}
+
+#[test]
+fn test_vector_encoding_and_cosine() {
+    let v = vec![0.5, -1.25, 3.0];
+    assert_eq!(decode(&encode(&v)), v);
+    assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+    assert!(cosine(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+    assert_eq!(cosine(&[1.0], &[1.0, 2.0]), 0.0);
+    assert_eq!(cosine(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+}
+
+#[test]
+fn test_default_embedding_models() {
+    let mut cfg = Config { source: "ollama".into(), model: "llama3".into(), ..Default::default() };
+    assert_eq!(EmbeddingModel::from_config(&cfg).label(), "ollama:nomic-embed-text");
+    cfg.source = "openai".into();
+    assert_eq!(EmbeddingModel::from_config(&cfg).label(), "openai:text-embedding-3-small");
+    cfg.embedding_model = Some("text-embedding-3-large".into());
+    assert_eq!(EmbeddingModel::from_config(&cfg).label(), "openai:text-embedding-3-large");
+}
+
+#[test]
+fn test_indexing_is_incremental_per_model() {
+    let conn = test_db();
+    assert_eq!(embeddings::count_pending(&conn, "a").unwrap(), 2);
+    embeddings::store(&conn, 1, "a", &[1.0, 0.0]).unwrap();
+    let pending: Vec<i64> = embeddings::pending(&conn, "a", 10).unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(pending, vec![2]);
+
+    // vectors from another model don't count
+    assert_eq!(embeddings::count_pending(&conn, "b").unwrap(), 2);
+    embeddings::store(&conn, 1, "b", &[0.0, 1.0]).unwrap();
+    assert_eq!(embeddings::count_pending(&conn, "a").unwrap(), 2);
+
+    assert_eq!(embeddings::clear(&conn).unwrap(), 1);
+}
+
+#[test]
+fn test_edited_and_deleted_messages_drop_their_vectors() {
+    let conn = test_db();
+    embeddings::store(&conn, 1, "a", &[1.0, 0.0]).unwrap();
+    embeddings::store(&conn, 2, "a", &[0.0, 1.0]).unwrap();
+    conn.execute("UPDATE messages SET content = 'changed' WHERE id = 1", []).unwrap();
+    assert_eq!(embeddings::count_pending(&conn, "a").unwrap(), 1);
+    delete_message(&conn, 2).unwrap();
+    let left: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |r| r.get(0)).unwrap();
+    assert_eq!(left, 0);
+}
+
+#[test]
+fn test_semantic_search_ranks_by_similarity() {
+    let conn = test_db();
+    embeddings::store(&conn, 1, "a", &[1.0, 0.0]).unwrap();
+    embeddings::store(&conn, 2, "a", &[0.6, 0.8]).unwrap();
+
+    let hits = semantic(&conn, "a", &[0.0, 1.0], &SearchFilter::default()).unwrap();
+    assert_eq!(hits.len(), 1, "orthogonal messages are not results");
+    assert_eq!(hits[0].message_id, 2);
+    assert_eq!(hits[0].context.as_deref(), Some("borrow checker lifetimes"));
+
+    let hits = semantic(&conn, "a", &[1.0, 0.1], &SearchFilter::default()).unwrap();
+    let ids: Vec<i64> = hits.iter().map(|h| h.message_id).collect();
+    assert_eq!(ids, vec![1, 2]);
+
+    let users = SearchFilter { role: Some("user".into()), ..Default::default() };
+    assert_eq!(semantic(&conn, "a", &[1.0, 0.1], &users).unwrap().len(), 1);
+    assert!(semantic(&conn, "other-model", &[1.0, 0.1], &SearchFilter::default()).unwrap().is_empty());
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use yo::branches::{append, set_flags, set_head};
+use yo::chats;
+use yo::config::ModelSettings;
+use yo::export::{document, load_all, load_chat, render, to_html, to_markdown, ExportDocument, Format, SCHEMA, SCHEMA_VERSION};
+use yo::tags::add_tags;
+
+fn test_db() -> Connection {
+    let conn = empty_db();
+    let settings = ModelSettings { source: "ollama".into(), model: "llama3".into(), temperature: Some(0.2), max_tokens: None };
+    let chat = chats::create(&conn, "deploy <outage>", Some(&settings)).unwrap();
+    chats::set_system_prompt(&conn, chat, Some("You are an SRE.")).unwrap();
+    add_tags(&conn, chat, &["incident".into()]).unwrap();
+    let q = append(&conn, chat, None, "user", "Why did the deploy fail?").unwrap();
+    append(&conn, chat, Some(q), "assistant", "An old answer").unwrap();
+    let a = append(&conn, chat, Some(q), "assistant", "Run this:\n\n```sh\necho \"<ok>\" && exit 1\n```\n\nThen check `status`.").unwrap();
+    set_head(&conn, chat, Some(a)).unwrap();
+    set_flags(&conn, q, false, true).unwrap();
+    chats::create(&conn, "empty", None).unwrap();
+    conn
+}
+
+fn doc(conn: &Connection, id: i64) -> ExportDocument {
+    document(conn, vec![load_chat(conn, id).unwrap().unwrap()]).unwrap()
+}
+
+#[test]
+fn test_load_chat_with_metadata_and_branches() {
+    let conn = test_db();
+    let chat = load_chat(&conn, 1).unwrap().unwrap();
+    assert_eq!(chat.title.as_deref(), Some("deploy <outage>"));
+    assert_eq!(chat.system_prompt.as_deref(), Some("You are an SRE."));
+    assert_eq!(chat.model.as_deref(), Some("llama3"));
+    assert_eq!(chat.temperature, Some(0.2));
+    assert_eq!(chat.tags, vec!["incident".to_string()]);
+    // all branches are exported, the active one is reachable from the head
+    assert_eq!(chat.messages.len(), 3);
+    assert!(chat.messages[0].pinned);
+    let active: Vec<i64> = chat.active_branch().iter().map(|m| m.id).collect();
+    assert_eq!(active, vec![1, 3]);
+    assert!(load_chat(&conn, 99).unwrap().is_none());
+    assert_eq!(load_all(&conn).unwrap().len(), 2);
+}
+
+#[test]
+fn test_markdown_keeps_code_blocks() {
+    let conn = test_db();
+    let md = to_markdown(&doc(&conn, 1));
+    assert!(md.starts_with("# deploy <outage>\n"));
+    assert!(md.contains("- **Model:** ollama:llama3"));
+    assert!(md.contains("- **Tags:** #incident"));
+    assert!(md.contains("## System prompt\n\nYou are an SRE."));
+    assert!(md.contains("```sh\necho \"<ok>\" && exit 1\n```"));
+    assert!(md.contains("### You · "));
+    assert!(!md.contains("An old answer"));
+}
+
+#[test]
+fn test_html_is_escaped_and_self_contained() {
+    let conn = test_db();
+    let html = to_html(&doc(&conn, 1));
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>deploy &lt;outage&gt;</title>"));
+    assert!(html.contains("<pre><code class=\"language-sh\">echo &quot;&lt;ok&gt;&quot; &amp;&amp; exit 1\n</code></pre>"));
+    assert!(html.contains("<code>status</code>"));
+    assert!(!html.contains("<script") && !html.contains("<link"));
+    assert!(!html.contains("An old answer"));
+}
+
+#[test]
+fn test_json_follows_schema() {
+    let conn = test_db();
+    let json = render(&doc(&conn, 1), Format::Json);
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["schema"], SCHEMA);
+    assert_eq!(value["version"], SCHEMA_VERSION);
+    assert_eq!(value["chats"][0]["head_message_id"], 3);
+    assert_eq!(value["chats"][0]["messages"][1]["parent_id"], 1);
+    let back: ExportDocument = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.chats, doc(&conn, 1).chats);
+    assert_eq!(Format::parse("MD"), Some(Format::Markdown));
+    assert_eq!(Format::parse("pdf"), None);
+}
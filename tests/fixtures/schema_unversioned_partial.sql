@@ -0,0 +1,31 @@
+-- chats.db upgraded column by column before versioning: it has tags and message
+-- branches but no hidden/pinned flags or chat settings, and user_version is still 0
+CREATE TABLE chats (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    system_prompt TEXT,
+    tags TEXT,
+    head_message_id INTEGER
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER,
+    role TEXT,
+    content TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    parent_id INTEGER REFERENCES messages(id),
+    FOREIGN KEY(chat_id) REFERENCES chats(id)
+);
+CREATE TABLE user_profile (key TEXT PRIMARY KEY, value TEXT);
+CREATE TABLE tags (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE);
+CREATE TABLE chat_tags (chat_id INTEGER NOT NULL, tag_id INTEGER NOT NULL, PRIMARY KEY(chat_id, tag_id));
+INSERT INTO chats (id, title, head_message_id) VALUES (1, 'branched', 2);
+-- two answers to the same question: the parent links must survive the upgrade
+INSERT INTO messages (id, chat_id, role, content, parent_id) VALUES
+    (1, 1, 'user', 'q', NULL),
+    (2, 1, 'assistant', 'first', 1),
+    (3, 1, 'assistant', 'second', 1);
+INSERT INTO tags (id, name) VALUES (1, 'work');
+INSERT INTO chat_tags (chat_id, tag_id) VALUES (1, 1);
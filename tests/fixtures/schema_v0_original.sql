@@ -0,0 +1,28 @@
+-- chats.db as created by yo before tags, branches or versioning (user_version 0)
+CREATE TABLE chats (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    system_prompt TEXT,
+    tags TEXT
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER,
+    role TEXT,
+    content TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(chat_id) REFERENCES chats(id)
+);
+CREATE TABLE user_profile (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+INSERT INTO chats (id, title, tags) VALUES (1, 'rust questions', 'Rust, work'), (2, 'recipes', NULL);
+INSERT INTO messages (chat_id, role, content) VALUES
+    (1, 'user', 'what is a lifetime?'),
+    (2, 'user', 'pancakes?'),
+    (1, 'assistant', 'a scope for references'),
+    (1, 'user', 'thanks');
+INSERT INTO user_profile (key, value) VALUES ('name', 'Sam');
@@ -0,0 +1,33 @@
+-- chats.db at schema version 3 (message branches, no flags or chat settings)
+CREATE TABLE chats (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    system_prompt TEXT,
+    tags TEXT,
+    head_message_id INTEGER
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER,
+    role TEXT,
+    content TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    parent_id INTEGER REFERENCES messages(id),
+    FOREIGN KEY(chat_id) REFERENCES chats(id)
+);
+CREATE TABLE user_profile (key TEXT PRIMARY KEY, value TEXT);
+CREATE TABLE tags (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE);
+CREATE TABLE chat_tags (
+    chat_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    PRIMARY KEY(chat_id, tag_id),
+    FOREIGN KEY(chat_id) REFERENCES chats(id),
+    FOREIGN KEY(tag_id) REFERENCES tags(id)
+);
+CREATE INDEX idx_chat_tags_tag ON chat_tags(tag_id);
+CREATE INDEX idx_messages_parent ON messages(parent_id);
+INSERT INTO chats (id, title, head_message_id) VALUES (1, 'v3 chat', 2);
+INSERT INTO messages (id, chat_id, role, content, parent_id) VALUES (1, 1, 'user', 'hi', NULL), (2, 1, 'assistant', 'hello', 1);
+PRAGMA user_version = 3;
@@ -0,0 +1,254 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use yo::branches::active_path;
+use yo::chats;
+use yo::import::{format_unix, import, parse_aichat_log, parse_aichat_session, parse_chatgpt, read_llm, utc_timestamp, ImportStats};
+
+const EXPORT: &str = include_str!("fixtures/chatgpt_conversations.json");
+
+/// (created, updated, unchanged, messages)
+fn counts(stats: &ImportStats) -> (usize, usize, usize, usize) {
+    (stats.created, stats.updated, stats.unchanged, stats.messages)
+}
+
+fn count(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn test_format_unix() {
+    assert_eq!(format_unix(0.0).as_deref(), Some("1970-01-01 00:00:00"));
+    assert_eq!(format_unix(1700000000.5).as_deref(), Some("2023-11-14 22:13:20"));
+    assert_eq!(format_unix(951782400.0).as_deref(), Some("2000-02-29 00:00:00"));
+    assert_eq!(format_unix(-1.0), None);
+}
+
+#[test]
+fn test_parse_chatgpt_follows_current_branch() {
+    let chats = parse_chatgpt(EXPORT).unwrap();
+    assert_eq!(chats.len(), 2);
+    let eggs = &chats[0];
+    assert_eq!(eggs.external_id, "conv-eggs");
+    assert_eq!(eggs.title, "Boiling eggs");
+    assert_eq!(eggs.created_at.as_deref(), Some("2023-11-14 22:13:20"));
+    // the hidden system message, the tool output and the abandoned branch are left out
+    let texts: Vec<(&str, &str)> = eggs.messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+    assert_eq!(texts, vec![("user", "How long to boil a soft egg?"), ("assistant", "6 minutes for a runny yolk.")]);
+
+    // no current_node: the newest branch is followed
+    let code = &chats[1];
+    assert_eq!(code.title, "Untitled chat");
+    assert_eq!(code.messages[0].created_at, code.created_at);
+    assert_eq!(code.messages[1].content, "```python\nprint(sum(range(11)))\n```");
+    assert!(parse_chatgpt("{}").is_err());
+}
+
+#[test]
+fn test_import_keeps_timestamps() {
+    let conn = empty_db();
+    let stats = import(&conn, "chatgpt", &parse_chatgpt(EXPORT).unwrap(), false).unwrap();
+    assert_eq!(counts(&stats), (2, 0, 0, 4));
+    let (title, created): (String, String) =
+        conn.query_row("SELECT title, created_at FROM chats WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+    assert_eq!(title, "Boiling eggs");
+    assert_eq!(created, "2023-11-14 22:13:20");
+    let path = active_path(&conn, 1).unwrap();
+    assert_eq!(path.len(), 2);
+    assert_eq!(path[1].role, "assistant");
+    assert_eq!(path[1].created_at, "2023-11-14 22:15:20");
+}
+
+#[test]
+fn test_import_is_idempotent_and_appends_new_messages() {
+    let conn = empty_db();
+    let mut chats = parse_chatgpt(EXPORT).unwrap();
+    import(&conn, "chatgpt", &chats, false).unwrap();
+    let again = import(&conn, "chatgpt", &chats, false).unwrap();
+    assert_eq!(counts(&again), (0, 0, 2, 0));
+    assert!(again.changes.is_empty());
+    assert_eq!(count(&conn, "chats"), 2);
+    assert_eq!(count(&conn, "messages"), 4);
+
+    // the conversation went on in ChatGPT
+    let mut more = chats[0].messages[1].clone();
+    more.external_id = "q3".into();
+    more.role = "user".into();
+    more.content = "And a hard one?".into();
+    chats[0].messages.push(more);
+    let stats = import(&conn, "chatgpt", &chats, false).unwrap();
+    assert_eq!(counts(&stats), (0, 1, 1, 1));
+    let path = active_path(&conn, 1).unwrap();
+    assert_eq!(path.len(), 3);
+    assert_eq!(path[2].content, "And a hard one?");
+    assert_eq!(path[2].parent_id, Some(path[1].id));
+
+    // the same conversation from another source is a different chat
+    import(&conn, "other", &chats[..1], false).unwrap();
+    assert_eq!(count(&conn, "chats"), 3);
+}
+
+#[test]
+fn test_utc_timestamp() {
+    assert_eq!(utc_timestamp("2023-07-11T16:08:36.105093").as_deref(), Some("2023-07-11 16:08:36"));
+    assert_eq!(utc_timestamp("2024-01-05T02:20:30+08:00").as_deref(), Some("2024-01-04 18:20:30"));
+    assert_eq!(utc_timestamp("2024-03-01T00:30:00Z").as_deref(), Some("2024-03-01 00:30:00"));
+    assert_eq!(utc_timestamp("2024-12-31T23:30:00-0100").as_deref(), Some("2025-01-01 00:30:00"));
+    assert_eq!(utc_timestamp("yesterday"), None);
+}
+
+fn llm_logs() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE conversations (id TEXT PRIMARY KEY, name TEXT, model TEXT);
+        CREATE TABLE responses (
+            id TEXT PRIMARY KEY, model TEXT, prompt TEXT, system TEXT, prompt_json TEXT, options_json TEXT,
+            response TEXT, response_json TEXT, conversation_id TEXT REFERENCES conversations(id),
+            duration_ms INTEGER, datetime_utc TEXT
+        );
+        INSERT INTO conversations VALUES ('c1', 'Naming things', 'gpt-4o-mini');
+        INSERT INTO responses (id, model, prompt, system, response, conversation_id, datetime_utc) VALUES
+            ('r2', 'gpt-4o', 'Shorter please', NULL, 'yo', 'c1', '2024-05-01T10:01:00.5'),
+            ('r1', 'gpt-4o-mini', 'Name a CLI', 'Be brief', 'ask', 'c1', '2024-05-01T10:00:00.1'),
+            ('r3', 'llama3', 'One-off question', NULL, 'One-off answer', NULL, '2024-05-02T08:00:00');
+        "#,
+    )
+    .unwrap();
+    conn
+}
+
+#[test]
+fn test_read_llm_groups_conversations() {
+    let chats = read_llm(&llm_logs()).unwrap();
+    assert_eq!(chats.len(), 2);
+    let naming = &chats[0];
+    assert_eq!(naming.title, "Naming things");
+    assert_eq!(naming.system_prompt.as_deref(), Some("Be brief"));
+    assert_eq!(naming.created_at.as_deref(), Some("2024-05-01 10:00:00"));
+    let texts: Vec<&str> = naming.messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(texts, vec!["Name a CLI", "ask", "Shorter please", "yo"]);
+    assert_eq!(naming.messages[1].model.as_deref(), Some("gpt-4o-mini"));
+    assert_eq!(naming.messages[3].model.as_deref(), Some("gpt-4o"));
+    assert_eq!(naming.messages[0].model, None);
+    // a response outside a conversation is titled by its prompt
+    assert_eq!(chats[1].title, "One-off question");
+    assert_eq!(chats[1].external_id, "r3");
+}
+
+#[test]
+fn test_dry_run_saves_nothing() {
+    let conn = empty_db();
+    let chats = read_llm(&llm_logs()).unwrap();
+    let planned = import(&conn, "llm", &chats, true).unwrap();
+    assert_eq!(counts(&planned), (2, 0, 0, 6));
+    assert_eq!(planned.changes[0].title, "Naming things");
+    assert!(planned.changes[0].new_chat);
+    assert_eq!(count(&conn, "chats"), 0);
+    assert_eq!(count(&conn, "imports"), 0);
+
+    let done = import(&conn, "llm", &chats, false).unwrap();
+    assert_eq!(counts(&done), counts(&planned));
+    assert_eq!(chats::system_prompt(&conn, 1).unwrap().as_deref(), Some("Be brief"));
+    let model: Option<String> = conn.query_row("SELECT model FROM messages WHERE id = 2", [], |row| row.get(0)).unwrap();
+    assert_eq!(model.as_deref(), Some("gpt-4o-mini"));
+}
+
+#[test]
+fn test_dry_run_only_reads() {
+    let path = std::env::temp_dir().join(format!("yo_import_dry_run_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    // a database from before imports were recorded
+    Connection::open(&path).unwrap().execute_batch(include_str!("fixtures/schema_v3.sql")).unwrap();
+    let read_only = || Connection::open_with_flags(&path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+    let chats = read_llm(&llm_logs()).unwrap();
+    assert_eq!(counts(&import(&read_only(), "llm", &chats, true).unwrap()), (2, 0, 0, 6));
+    assert_eq!(yo::db::schema_version(&read_only()).unwrap(), 3);
+
+    // after a real import of the first chat, only the second one is new
+    let conn = yo::db::open(&path).unwrap();
+    import(&conn, "llm", &chats[..1], false).unwrap();
+    drop(conn);
+    assert_eq!(counts(&import(&read_only(), "llm", &chats, true).unwrap()), (1, 0, 1, 2));
+    let _ = std::fs::remove_file(yo::db::backup_path(&path, 3));
+    let _ = std::fs::remove_file(&path);
+}
+
+const AICHAT_SESSION: &str = r#"
+model: openai:gpt-4o
+temperature: 0.3
+save_session: true
+messages:
+- role: system
+  content: You are a shell expert.
+- role: user
+  content: list files by size
+- role: assistant
+  content: |-
+    ```sh
+    ls -lS
+    ```
+- role: user
+  content:
+  - type: text
+    text: what about this?
+  - type: image_url
+    image_url:
+      url: data:image/png;base64,AAAA
+- role: assistant
+  content: That's a screenshot of ls.
+"#;
+
+#[test]
+fn test_parse_aichat_session() {
+    let chat = parse_aichat_session("shell", AICHAT_SESSION, Some("2024-06-01 12:00:00".into())).unwrap();
+    assert_eq!(chat.external_id, "session:shell");
+    assert_eq!(chat.title, "shell");
+    assert_eq!(chat.system_prompt.as_deref(), Some("You are a shell expert."));
+    let settings = chat.settings.unwrap();
+    assert_eq!((settings.source.as_str(), settings.model.as_str(), settings.temperature), ("openai", "gpt-4o", Some(0.3)));
+    assert_eq!(chat.messages.len(), 4);
+    assert_eq!(chat.messages[1].content, "```sh\nls -lS\n```");
+    assert_eq!(chat.messages[1].model.as_deref(), Some("openai:gpt-4o"));
+    assert_eq!(chat.messages[2].content, "what about this?");
+    assert_eq!(chat.messages[3].created_at.as_deref(), Some("2024-06-01 12:00:00"));
+
+    // models of clients yo doesn't have are kept on the messages only
+    let other = parse_aichat_session("c", "model: claude:claude-3-5-sonnet\nmessages: []\n", None).unwrap();
+    assert!(other.settings.is_none());
+    assert!(parse_aichat_session("bad", "model: [", None).is_err());
+}
+
+#[test]
+fn test_reimport_aichat_session_after_compression() {
+    let conn = empty_db();
+    let session = "model: openai:gpt-4o\nmessages:\n- role: system\n  content: be brief\n- role: user\n  content: hi\n- role: assistant\n  content: hello\n- role: user\n  content: hi\n- role: assistant\n  content: hello again\n";
+    let first = parse_aichat_session("s", session, None).unwrap();
+    import(&conn, "aichat", &[first], false).unwrap();
+
+    // aichat folded the system prompt into a summary, moved the old messages aside and the chat went on
+    let compressed = "model: openai:gpt-4o\ncompressed_messages:\n- role: user\n  content: hi\n- role: assistant\n  content: hello\n- role: user\n  content: hi\n- role: assistant\n  content: hello again\nmessages:\n- role: system\n  content: 'be brief. Summary: greetings'\n- role: user\n  content: bye\n";
+    let later = vec![parse_aichat_session("s", compressed, None).unwrap()];
+    let stats = import(&conn, "aichat", &later, false).unwrap();
+    assert_eq!(counts(&stats), (0, 1, 0, 1));
+    let contents: Vec<String> = active_path(&conn, 1).unwrap().into_iter().map(|t| t.content).collect();
+    assert_eq!(contents, vec!["hi", "hello", "hi", "hello again", "bye"]);
+    assert_eq!(counts(&import(&conn, "aichat", &later, false).unwrap()), (0, 0, 1, 0));
+}
+
+#[test]
+fn test_parse_aichat_log() {
+    let log = "# CHAT: [2024-01-05T10:20:30+08:00]\nhi there\n--------\nHello!\n--------\n\n# CHAT: Rust question [2024-01-06T09:00:00+00:00] (rust)\nwhat is a lifetime?\n\nbe short\n--------\nHow long a reference is valid.\n--------\n\n";
+    let chats = parse_aichat_log(log);
+    assert_eq!(chats.len(), 2);
+    assert_eq!(chats[0].title, "hi there");
+    assert_eq!(chats[0].created_at.as_deref(), Some("2024-01-05 02:20:30"));
+    assert_eq!(chats[0].messages[1].content, "Hello!");
+    assert_eq!(chats[1].title, "Rust question");
+    assert_eq!(chats[1].messages[0].content, "what is a lifetime?\n\nbe short");
+
+    let conn = empty_db();
+    import(&conn, "aichat", &chats, false).unwrap();
+    assert_eq!(counts(&import(&conn, "aichat", &chats, false).unwrap()), (0, 0, 2, 0));
+}
@@ -0,0 +1,41 @@
+use yo::memory::{normalize_key, parse_facts, propose, Proposal};
+
+#[test]
+fn test_normalize_key() {
+    assert_eq!(normalize_key("Preferred Language"), "preferred_language");
+    assert_eq!(normalize_key("  OS "), "os");
+    assert_eq!(normalize_key("editor/ide"), "editor_ide");
+}
+
+#[test]
+fn test_parse_facts_with_code_fence() {
+    let reply = "Here you go:\n```json\n{\"Preferred Language\": \"Rust\", \"os\": \"macOS\", \"empty\": \"\"}\n```";
+    let facts = parse_facts(reply);
+    assert_eq!(facts.len(), 2);
+    assert!(facts.contains(&("preferred_language".into(), "Rust".into())));
+    assert!(facts.contains(&("os".into(), "macOS".into())));
+}
+
+#[test]
+fn test_parse_facts_garbage() {
+    assert!(parse_facts("no facts here").is_empty());
+    assert!(parse_facts("{not json}").is_empty());
+}
+
+#[test]
+fn test_propose_dedupes_against_profile() {
+    let profile = vec![
+        ("os".to_string(), "macOS".to_string()),
+        ("editor".to_string(), "vim".to_string()),
+    ];
+    let facts = vec![
+        ("os".to_string(), "macos".to_string()),
+        ("editor".to_string(), "helix".to_string()),
+        ("project".to_string(), "yo".to_string()),
+    ];
+    let proposals = propose(facts, &profile);
+    assert_eq!(proposals, vec![
+        Proposal::Update { key: "editor".into(), old: "vim".into(), value: "helix".into() },
+        Proposal::Add { key: "project".into(), value: "yo".into() },
+    ]);
+}
@@ -0,0 +1,183 @@
+use rusqlite::Connection;
+use std::env;
+use std::fs;
+use yo::branches::active_path;
+use yo::db::{self, backup_path, init_schema, schema_version, DbError, SCHEMA_VERSION};
+use yo::tags::tags_for_chat;
+
+fn fixture(sql: &str) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(sql).unwrap();
+    conn
+}
+
+fn columns(conn: &Connection, table: &str) -> Vec<String> {
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table)).unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+}
+
+fn contents(conn: &Connection, chat_id: i64) -> Vec<String> {
+    active_path(conn, chat_id).unwrap().into_iter().map(|t| t.content).collect()
+}
+
+fn assert_current_schema(conn: &Connection) {
+    assert_eq!(schema_version(conn).unwrap(), SCHEMA_VERSION);
+    let chats = columns(conn, "chats");
+    for c in ["head_message_id", "source", "model", "temperature", "max_tokens", "persona"] {
+        assert!(chats.contains(&c.to_string()), "chats.{} missing", c);
+    }
+    let messages = columns(conn, "messages");
+    for c in ["parent_id", "hidden", "pinned"] {
+        assert!(messages.contains(&c.to_string()), "messages.{} missing", c);
+    }
+    assert!(!columns(conn, "project_chats").is_empty());
+    // existing messages are searchable after the upgrade
+    let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM messages_fts", [], |r| r.get(0)).unwrap();
+    let messages: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |r| r.get(0)).unwrap();
+    assert_eq!(indexed, messages);
+    let enforced: bool = conn.query_row("PRAGMA foreign_keys", [], |r| r.get(0)).unwrap();
+    assert!(enforced, "foreign keys are off");
+}
+
+#[test]
+fn test_fresh_database_gets_current_schema() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_schema(&conn).unwrap();
+    assert_current_schema(&conn);
+    // running again is a no-op
+    init_schema(&conn).unwrap();
+    assert_current_schema(&conn);
+}
+
+#[test]
+fn test_upgrade_from_original_schema() {
+    let conn = fixture(include_str!("fixtures/schema_v0_original.sql"));
+    init_schema(&conn).unwrap();
+    assert_current_schema(&conn);
+
+    // linear history becomes a single branch, per chat
+    assert_eq!(contents(&conn, 1), vec!["what is a lifetime?", "a scope for references", "thanks"]);
+    assert_eq!(contents(&conn, 2), vec!["pancakes?"]);
+    // free-text tags moved to the tag tables
+    assert_eq!(tags_for_chat(&conn, 1).unwrap(), vec!["rust", "work"]);
+    let profile: String = conn.query_row("SELECT value FROM user_profile WHERE key = 'name'", [], |r| r.get(0)).unwrap();
+    assert_eq!(profile, "Sam");
+}
+
+#[test]
+fn test_upgrade_keeps_multi_word_tags_whole() {
+    let conn = fixture(include_str!("fixtures/schema_v0_original.sql"));
+    conn.execute("UPDATE chats SET tags = 'Work Stuff, rust' WHERE id = 2", []).unwrap();
+    init_schema(&conn).unwrap();
+    assert_eq!(tags_for_chat(&conn, 2).unwrap(), vec!["rust", "work-stuff"]);
+}
+
+#[test]
+fn test_upgrade_from_unversioned_partial_schema() {
+    let conn = fixture(include_str!("fixtures/schema_unversioned_partial.sql"));
+    init_schema(&conn).unwrap();
+    assert_current_schema(&conn);
+
+    // existing branches are kept, not re-linked as a linear chat
+    assert_eq!(contents(&conn, 1), vec!["q", "first"]);
+    let parent: Option<i64> = conn.query_row("SELECT parent_id FROM messages WHERE id = 3", [], |r| r.get(0)).unwrap();
+    assert_eq!(parent, Some(1));
+    assert_eq!(tags_for_chat(&conn, 1).unwrap(), vec!["work"]);
+}
+
+#[test]
+fn test_upgrade_from_v3() {
+    let conn = fixture(include_str!("fixtures/schema_v3.sql"));
+    assert_eq!(schema_version(&conn).unwrap(), 3);
+    init_schema(&conn).unwrap();
+    assert_current_schema(&conn);
+
+    let (hidden, pinned): (bool, bool) =
+        conn.query_row("SELECT hidden, pinned FROM messages WHERE id = 2", [], |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+    assert!(!hidden && !pinned);
+    assert_eq!(contents(&conn, 1), vec!["hi", "hello"]);
+}
+
+#[test]
+fn test_upgrade_drops_orphans_and_cascades() {
+    let conn = fixture(include_str!("fixtures/schema_v3.sql"));
+    // left behind by a chat deleted before its messages, and a tag link to a missing tag
+    conn.execute_batch(
+        r#"
+        INSERT INTO messages (id, chat_id, role, content) VALUES (7, 42, 'user', 'orphan');
+        INSERT INTO chat_tags (chat_id, tag_id) VALUES (1, 9);
+        DELETE FROM messages WHERE id = 1;
+        "#,
+    )
+    .unwrap();
+    init_schema(&conn).unwrap();
+    assert_current_schema(&conn);
+
+    let orphans: i64 = conn.query_row("SELECT COUNT(*) FROM messages WHERE chat_id = 42", [], |r| r.get(0)).unwrap();
+    assert_eq!(orphans, 0);
+    // the reply to the deleted message became a root
+    assert_eq!(contents(&conn, 1), vec!["hello"]);
+    let violations = conn.prepare("PRAGMA foreign_key_check").unwrap().query_map([], |_| Ok(())).unwrap().count();
+    assert_eq!(violations, 0);
+    // ids of deleted messages aren't handed out again
+    conn.execute("INSERT INTO messages (chat_id, role, content) VALUES (1, 'user', 'next')", []).unwrap();
+    assert_eq!(conn.last_insert_rowid(), 8);
+
+    conn.execute("DELETE FROM chats WHERE id = 1", []).unwrap();
+    let left: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |r| r.get(0)).unwrap();
+    assert_eq!(left, 0);
+}
+
+#[test]
+fn test_newer_database_is_refused_untouched() {
+    let conn = fixture(include_str!("fixtures/schema_v3.sql"));
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+    match init_schema(&conn) {
+        Err(DbError::TooNew { found, supported }) => {
+            assert_eq!(found, SCHEMA_VERSION + 1);
+            assert_eq!(supported, SCHEMA_VERSION);
+        }
+        other => panic!("expected TooNew, got {:?}", other),
+    }
+    assert!(!columns(&conn, "messages").contains(&"hidden".to_string()));
+}
+
+#[test]
+fn test_open_backs_up_before_upgrading() {
+    let dir = env::temp_dir().join(format!("yo_migration_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("chats.db");
+    fixture_file(&path, include_str!("fixtures/schema_v0_original.sql"));
+
+    let conn = db::open(&path).unwrap();
+    assert_current_schema(&conn);
+    drop(conn);
+
+    let backup = backup_path(&path, 0);
+    assert_eq!(backup, dir.join("chats.db.v0.bak"));
+    let old = Connection::open(&backup).unwrap();
+    assert_eq!(schema_version(&old).unwrap(), 0);
+    assert!(!columns(&old, "messages").contains(&"parent_id".to_string()));
+    drop(old);
+
+    // an up-to-date database is opened without another backup
+    fs::remove_file(&backup).unwrap();
+    db::open(&path).unwrap();
+    assert!(!backup.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_new_database_file_is_not_backed_up() {
+    let dir = env::temp_dir().join(format!("yo_migration_new_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("chats.db");
+    db::open(&path).unwrap();
+    assert!(!backup_path(&path, 0).exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn fixture_file(path: &std::path::Path, sql: &str) {
+    let conn = Connection::open(path).unwrap();
+    conn.execute_batch(sql).unwrap();
+}
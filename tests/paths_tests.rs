@@ -0,0 +1,116 @@
+use serial_test::serial;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use yo::paths::{config_dir, data_dir, db_path, migrate_data, HOME_VAR};
+
+/// Run `f` with the given variables set (None = unset), restoring them afterwards
+fn with_vars(vars: &[(&str, Option<&str>)], f: impl FnOnce()) {
+    let saved: Vec<(String, Option<String>)> = vars.iter().map(|(k, _)| (k.to_string(), env::var(k).ok())).collect();
+    for (k, v) in vars {
+        match v {
+            Some(v) => unsafe { env::set_var(k, v) },
+            None => unsafe { env::remove_var(k) },
+        }
+    }
+    f();
+    for (k, v) in saved {
+        match v {
+            Some(v) => unsafe { env::set_var(&k, v) },
+            None => unsafe { env::remove_var(&k) },
+        }
+    }
+}
+
+#[test]
+#[serial]
+fn test_xdg_directories() {
+    with_vars(&[(HOME_VAR, None), ("XDG_CONFIG_HOME", Some("/tmp/xdg-config")), ("XDG_DATA_HOME", Some("/tmp/xdg-data"))], || {
+        assert_eq!(config_dir(), PathBuf::from("/tmp/xdg-config/yo"));
+        assert_eq!(data_dir(), PathBuf::from("/tmp/xdg-data/yo"));
+    });
+    with_vars(&[(HOME_VAR, None), ("XDG_CONFIG_HOME", None), ("XDG_DATA_HOME", None)], || {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(config_dir(), home.join(".config/yo"));
+        assert_eq!(data_dir(), home.join(".local/share/yo"));
+    });
+}
+
+#[test]
+#[serial]
+fn test_yo_home_overrides_everything() {
+    let home = env::temp_dir().join(format!("yo_paths_home_{}", std::process::id()));
+    with_vars(&[(HOME_VAR, Some(home.to_str().unwrap())), ("XDG_CONFIG_HOME", Some("/tmp/xdg-config")), ("XDG_DATA_HOME", Some("/tmp/xdg-data"))], || {
+        assert_eq!(config_dir(), home);
+        assert_eq!(data_dir(), home);
+        assert_eq!(db_path(), home.join("chats.db"));
+        assert!(home.is_dir());
+    });
+    fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+#[serial]
+fn test_data_dir_flag() {
+    let home = env::temp_dir().join(format!("yo_paths_flag_{}", std::process::id()));
+    with_vars(&[(HOME_VAR, Some("/tmp/yo-home-from-env"))], || {
+        let out = std::process::Command::new(env!("CARGO_BIN_EXE_yo")).arg("--data-dir").arg(&home).arg("config").output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), home.join("config.toml").to_str().unwrap());
+    });
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_legacy_data_moves_only_when_chats_are_used() {
+    let home = env::temp_dir().join(format!("yo_paths_legacy_{}", std::process::id()));
+    let legacy = home.join(".config").join("yo");
+    fs::create_dir_all(&legacy).unwrap();
+    fs::write(legacy.join("config.toml"), "source = \"ollama\"\nmodel = \"none\"\n").unwrap();
+    fs::write(legacy.join("chats.db"), "").unwrap();
+    let yo = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_yo"))
+            .args(args)
+            .env("HOME", &home)
+            .env_remove(HOME_VAR)
+            .env_remove("XDG_CONFIG_HOME")
+            .env("XDG_DATA_HOME", home.join("data"))
+            .output()
+            .unwrap()
+    };
+
+    yo(&["ask", "--no-history", "hi"]);
+    yo(&["config"]);
+    assert!(legacy.join("chats.db").exists());
+    yo(&["list-chats"]);
+    assert!(!legacy.join("chats.db").exists());
+    assert!(home.join("data").join("yo").join("chats.db").exists());
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_migrate_data_moves_chat_files_only() {
+    let base = env::temp_dir().join(format!("yo_paths_migrate_{}", std::process::id()));
+    let (from, to) = (base.join("old"), base.join("new"));
+    fs::create_dir_all(&from).unwrap();
+    for name in ["chats.db", "chats.db-wal", "chats.db.v3.bak", "current_chat", "repl_history.txt", "config.toml"] {
+        fs::write(from.join(name), name).unwrap();
+    }
+    fs::create_dir_all(from.join("templates")).unwrap();
+
+    let moved = migrate_data(&from, &to).unwrap();
+    assert_eq!(moved, vec!["chats.db", "chats.db-wal", "chats.db.v3.bak", "current_chat", "repl_history.txt"]);
+    assert_eq!(fs::read_to_string(to.join("chats.db")).unwrap(), "chats.db");
+    assert!(!from.join("chats.db").exists());
+    // configuration stays where it is
+    assert!(from.join("config.toml").exists());
+    assert!(from.join("templates").is_dir());
+    assert!(!to.join("config.toml").exists());
+
+    // nothing in the data directory is ever overwritten
+    fs::write(from.join("chats.db"), "stale").unwrap();
+    fs::write(from.join("current_chat"), "9").unwrap();
+    assert!(migrate_data(&from, &to).unwrap().is_empty());
+    assert_eq!(fs::read_to_string(to.join("chats.db")).unwrap(), "chats.db");
+    assert!(migrate_data(&to, &to).unwrap().is_empty());
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,37 @@
+use yo::config::{Config, ModelSettings};
+use yo::persona::{parse, validate_name, Persona};
+
+#[test]
+fn test_parse_persona_file() {
+    let p = parse("system_prompt = \"You review code.\"\nmodel = \"gpt-4o\"\ntemperature = 0.1\n").unwrap();
+    assert_eq!(p.system_prompt, "You review code.");
+    assert_eq!(p.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(p.source, None);
+    assert_eq!(p.temperature, Some(0.1));
+    assert!(parse("model = \"gpt-4o\"").is_err());
+}
+
+#[test]
+fn test_persona_overlays_chat_settings() {
+    let base = ModelSettings { source: "openai".into(), model: "gpt-4".into(), temperature: Some(0.7), max_tokens: Some(500) };
+    let p = Persona { system_prompt: "x".into(), model: Some("gpt-4o".into()), temperature: Some(0.0), ..Default::default() };
+    assert_eq!(p.settings(&base), ModelSettings { source: "openai".into(), model: "gpt-4o".into(), temperature: Some(0.0), max_tokens: Some(500) });
+}
+
+#[test]
+fn test_personas_in_config() {
+    let cfg: Config = toml::from_str(
+        "source = \"ollama\"\nmodel = \"llama3\"\n\n[personas.translator]\nsystem_prompt = \"Translate to French.\"\n",
+    ).unwrap();
+    assert_eq!(cfg.personas["translator"].system_prompt, "Translate to French.");
+    // an empty persona table is not written back
+    let plain = Config { source: "ollama".into(), model: "llama3".into(), ..Default::default() };
+    assert!(!toml::to_string_pretty(&plain).unwrap().contains("personas"));
+}
+
+#[test]
+fn test_validate_name() {
+    assert!(validate_name("shell-expert").is_ok());
+    assert!(validate_name("../etc").is_err());
+    assert!(validate_name("").is_err());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::empty_db;
+use std::env;
+use std::fs;
+use std::path::Path;
+use yo::chats;
+use yo::project;
+
+#[test]
+fn test_project_root_is_git_root_or_dir() {
+    let base = env::temp_dir().join(format!("yo_project_test_{}", std::process::id()));
+    let nested = base.join("repo").join("src").join("deep");
+    fs::create_dir_all(&nested).unwrap();
+    fs::create_dir_all(base.join("repo").join(".git")).unwrap();
+    fs::create_dir_all(base.join("plain")).unwrap();
+
+    assert_eq!(project::project_root(&nested), base.join("repo"));
+    assert_eq!(project::project_root(&base.join("plain")), base.join("plain"));
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn test_nearest_binding_wins() {
+    let conn = empty_db();
+    let outer = chats::create(&conn, "outer", None).unwrap();
+    let inner = chats::create(&conn, "inner", None).unwrap();
+    project::bind(&conn, Path::new("/work/repo"), outer).unwrap();
+    project::bind(&conn, Path::new("/work/repo/docs"), inner).unwrap();
+
+    let found = project::binding_for(&conn, Path::new("/work/repo/src/main")).unwrap();
+    assert_eq!(found, Some((Path::new("/work/repo").to_path_buf(), outer)));
+    let found = project::binding_for(&conn, Path::new("/work/repo/docs/api")).unwrap();
+    assert_eq!(found, Some((Path::new("/work/repo/docs").to_path_buf(), inner)));
+    assert_eq!(project::binding_for(&conn, Path::new("/work/other")).unwrap(), None);
+}
+
+#[test]
+fn test_rebind_and_unbind() {
+    let conn = empty_db();
+    let a = chats::create(&conn, "a", None).unwrap();
+    let b = chats::create(&conn, "b", None).unwrap();
+    let dir = Path::new("/work/repo");
+    project::bind(&conn, dir, a).unwrap();
+    project::bind(&conn, dir, b).unwrap();
+    assert_eq!(project::bindings(&conn).unwrap(), vec![("/work/repo".to_string(), b, Some("b".to_string()))]);
+
+    assert!(project::unbind(&conn, dir).unwrap());
+    assert!(!project::unbind(&conn, dir).unwrap());
+    assert_eq!(project::binding_for(&conn, dir).unwrap(), None);
+}
+
+#[test]
+fn test_binding_to_deleted_chat_is_ignored() {
+    let conn = empty_db();
+    let id = chats::create(&conn, "gone", None).unwrap();
+    project::bind(&conn, Path::new("/work/repo"), id).unwrap();
+    conn.execute("DELETE FROM chats WHERE id = ?1", [id]).unwrap();
+    assert_eq!(project::binding_for(&conn, Path::new("/work/repo")).unwrap(), None);
+}
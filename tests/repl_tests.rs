@@ -0,0 +1,41 @@
+use yo::repl::{parse_slash, InputBuffer, SlashCommand};
+
+#[test]
+fn test_parse_slash_commands() {
+    assert_eq!(parse_slash("hello there"), None);
+    assert_eq!(parse_slash("/model"), Some(Ok(SlashCommand::Model(None))));
+    assert_eq!(parse_slash(" /model gpt-4o "), Some(Ok(SlashCommand::Model(Some("gpt-4o".into())))));
+    assert_eq!(parse_slash("/new Trip planning"), Some(Ok(SlashCommand::New(Some("Trip planning".into())))));
+    assert_eq!(parse_slash("/switch 12"), Some(Ok(SlashCommand::Switch(12))));
+    assert_eq!(parse_slash("/system Be brief.  "), Some(Ok(SlashCommand::System(Some("Be brief.".into())))));
+    assert_eq!(parse_slash("/history"), Some(Ok(SlashCommand::History)));
+    assert_eq!(parse_slash("/clear"), Some(Ok(SlashCommand::Clear)));
+    assert_eq!(parse_slash("/quit"), Some(Ok(SlashCommand::Exit)));
+}
+
+#[test]
+fn test_parse_slash_errors() {
+    assert!(matches!(parse_slash("/switch"), Some(Err(_))));
+    assert!(matches!(parse_slash("/switch abc"), Some(Err(_))));
+    assert!(matches!(parse_slash("/frobnicate"), Some(Err(_))));
+}
+
+#[test]
+fn test_input_buffer_multiline() {
+    let mut buf = InputBuffer::default();
+    assert_eq!(buf.push("single"), Some("single".to_string()));
+    assert!(!buf.is_pending());
+
+    assert_eq!(buf.push("first\\"), None);
+    assert!(buf.is_pending());
+    assert_eq!(buf.push("second"), Some("first\nsecond".to_string()));
+
+    assert_eq!(buf.push("\"\"\""), None);
+    assert_eq!(buf.push("fn main() {\\"), None);
+    assert_eq!(buf.push(""), None);
+    assert_eq!(buf.push("\"\"\""), Some("fn main() {\\\n".to_string()));
+
+    buf.push("dropped\\");
+    buf.clear();
+    assert!(!buf.is_pending());
+}
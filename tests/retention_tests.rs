@@ -0,0 +1,153 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use std::env;
+use std::fs;
+use yo::branches::{active_path, append, set_flags};
+use yo::chats;
+use yo::config::Config;
+use yo::db;
+use yo::embeddings;
+use yo::retention::{collect, db_size, vacuum, GcStats, Retention};
+use yo::tags::add_tags;
+
+/// A chat with one question and answer, last active `days` ago
+fn chat(conn: &Connection, title: &str, days: u32) -> i64 {
+    let id = chats::create(conn, title, None).unwrap();
+    let q = append(conn, id, None, "user", "question").unwrap();
+    append(conn, id, Some(q), "assistant", "answer").unwrap();
+    age(conn, id, days);
+    id
+}
+
+fn age(conn: &Connection, chat_id: i64, days: u32) {
+    let when = format!("-{} days", days);
+    conn.execute("UPDATE messages SET created_at = datetime('now', ?1) WHERE chat_id = ?2", rusqlite::params![when, chat_id]).unwrap();
+    conn.execute("UPDATE chats SET created_at = datetime('now', ?1) WHERE id = ?2", rusqlite::params![when, chat_id]).unwrap();
+}
+
+fn chat_ids(conn: &Connection) -> Vec<i64> {
+    let mut stmt = conn.prepare("SELECT id FROM chats ORDER BY id").unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+}
+
+#[test]
+fn test_max_chats_keeps_recent_tagged_and_pinned() {
+    let conn = empty_db();
+    let oldest = chat(&conn, "oldest", 30);
+    let tagged = chat(&conn, "tagged", 20);
+    let pinned = chat(&conn, "pinned", 10);
+    let old = chat(&conn, "old", 5);
+    let recent = chat(&conn, "recent", 1);
+    add_tags(&conn, tagged, &["keep".into()]).unwrap();
+    let answer = active_path(&conn, pinned).unwrap()[1].id;
+    set_flags(&conn, answer, false, true).unwrap();
+
+    let policy = Retention { max_chats: Some(2), ..Default::default() };
+    let stats = collect(&conn, &policy, false).unwrap();
+    assert_eq!(stats, GcStats { chats: 1, messages: 0, orphans: 0 });
+    assert_eq!(chat_ids(&conn), vec![tagged, pinned, old, recent]);
+    assert!(!chat_ids(&conn).contains(&oldest));
+
+    let strict = Retention { max_chats: Some(1), keep_tagged: Some(false), keep_pinned: Some(false), ..Default::default() };
+    collect(&conn, &strict, false).unwrap();
+    assert_eq!(chat_ids(&conn), vec![recent]);
+    let messages: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0)).unwrap();
+    assert_eq!(messages, 2);
+}
+
+#[test]
+fn test_old_messages_expire() {
+    let conn = empty_db();
+    let stale = chat(&conn, "stale", 200);
+    let tagged = chat(&conn, "tagged", 200);
+    add_tags(&conn, tagged, &["work".into()]).unwrap();
+
+    // a long-running chat: old start, a pinned old answer, and a fresh follow-up
+    let ongoing = chats::create(&conn, "ongoing", None).unwrap();
+    let q1 = append(&conn, ongoing, None, "user", "old question").unwrap();
+    let a1 = append(&conn, ongoing, Some(q1), "assistant", "old answer").unwrap();
+    let q2 = append(&conn, ongoing, Some(a1), "user", "another old question").unwrap();
+    let a2 = append(&conn, ongoing, Some(q2), "assistant", "another old answer").unwrap();
+    age(&conn, ongoing, 200);
+    set_flags(&conn, a1, false, true).unwrap();
+    let q3 = append(&conn, ongoing, Some(a2), "user", "new question").unwrap();
+
+    let policy = Retention { max_age_days: Some(90), ..Default::default() };
+    let dry = collect(&conn, &policy, true).unwrap();
+    assert_eq!(dry, GcStats { chats: 1, messages: 2, orphans: 0 });
+    assert_eq!(chat_ids(&conn).len(), 3, "a dry run changes nothing");
+
+    assert_eq!(collect(&conn, &policy, false).unwrap(), dry);
+    assert_eq!(chat_ids(&conn), vec![tagged, ongoing]);
+    assert!(!chat_ids(&conn).contains(&stale));
+    // a chat holding a pin is kept whole, like a tagged one
+    assert_eq!(active_path(&conn, ongoing).unwrap().len(), 5);
+    assert_eq!(active_path(&conn, tagged).unwrap().len(), 2);
+
+    // without keep_pinned its old messages go, and the new question becomes the start
+    let unpinned = Retention { max_age_days: Some(90), keep_pinned: Some(false), ..Default::default() };
+    assert_eq!(collect(&conn, &unpinned, false).unwrap(), GcStats { chats: 0, messages: 4, orphans: 0 });
+    let path = active_path(&conn, ongoing).unwrap();
+    assert_eq!((path.len(), path[0].id, path[0].parent_id), (1, q3, None));
+}
+
+#[test]
+fn test_orphans_are_removed() {
+    let conn = empty_db();
+    let kept = chat(&conn, "kept", 0);
+    // what deleting a chat used to leave behind, before foreign keys were enforced
+    let gone = chat(&conn, "gone", 0);
+    add_tags(&conn, gone, &["work".into()]).unwrap();
+    let message: i64 = conn.query_row("SELECT MIN(id) FROM messages WHERE chat_id = ?1", [gone], |row| row.get(0)).unwrap();
+    embeddings::store(&conn, message, "test-model", &[1.0]).unwrap();
+    conn.pragma_update(None, "foreign_keys", false).unwrap();
+    conn.execute("DELETE FROM chats WHERE id = ?1", [gone]).unwrap();
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+    let stats = collect(&conn, &Retention::default(), false).unwrap();
+    // two messages and a tag link; the embedding goes with its message
+    assert_eq!(stats, GcStats { chats: 0, messages: 0, orphans: 3 });
+    let embedded: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0)).unwrap();
+    assert_eq!(embedded, 0);
+    let left: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0)).unwrap();
+    assert_eq!(left, 2);
+    let tags: i64 = conn.query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0)).unwrap();
+    assert_eq!(tags, 0);
+    assert_eq!(chat_ids(&conn), vec![kept]);
+    assert_eq!(collect(&conn, &Retention::default(), false).unwrap(), GcStats::default());
+}
+
+#[test]
+fn test_vacuum_reclaims_space() {
+    let dir = env::temp_dir().join(format!("yo_retention_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let conn = db::open(&dir.join("chats.db")).unwrap();
+    let id = chats::create(&conn, "big", None).unwrap();
+    for _ in 0..200 {
+        append(&conn, id, None, "user", &"x".repeat(2000)).unwrap();
+    }
+    age(&conn, id, 400);
+    collect(&conn, &Retention { max_age_days: Some(30), ..Default::default() }, false).unwrap();
+    let before = db_size(&conn).unwrap();
+    let reclaimed = vacuum(&conn).unwrap();
+    assert!(reclaimed > 0);
+    assert_eq!(db_size(&conn).unwrap(), before - reclaimed);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_retention_config() {
+    let cfg: Config = toml::from_str(
+        "source = \"openai\"\nmodel = \"gpt-4o\"\n\n[retention]\nmax_age_days = 90\nkeep_tagged = false\nauto_gc_every = 50\n",
+    )
+    .unwrap();
+    let retention = cfg.retention.clone().unwrap();
+    assert_eq!(retention.max_age_days, Some(90));
+    assert_eq!(retention.keep_tagged, Some(false));
+    assert_eq!(retention.auto_gc_every, Some(50));
+    assert_eq!(retention.max_chats, None);
+    let saved = toml::to_string_pretty(&cfg).unwrap();
+    assert!(saved.contains("[retention]"));
+}
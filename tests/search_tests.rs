@@ -0,0 +1,94 @@
+mod common;
+
+use common::empty_db;
+use rusqlite::Connection;
+use yo::branches::{append, delete_message};
+use yo::chats;
+use yo::search::{quote_terms, search, valid_date, SearchFilter};
+use yo::tags::add_tags;
+
+fn test_db() -> Connection {
+    let conn = empty_db();
+    let rust = chats::create(&conn, "rust", None).unwrap();
+    let cooking = chats::create(&conn, "cooking", None).unwrap();
+    add_tags(&conn, rust, &["work".into()]).unwrap();
+    let q = append(&conn, rust, None, "user", "How do lifetimes work in Rust?").unwrap();
+    append(&conn, rust, Some(q), "assistant", "Lifetimes describe how long references stay valid. Rust checks lifetimes at compile time.").unwrap();
+    let q = append(&conn, cooking, None, "user", "How long should I boil an egg?").unwrap();
+    append(&conn, cooking, Some(q), "assistant", "Boil it for nine minutes for a firm yolk.").unwrap();
+    conn
+}
+
+fn ids(conn: &Connection, query: &str, filter: &SearchFilter) -> Vec<i64> {
+    search(conn, query, filter).unwrap().into_iter().map(|h| h.message_id).collect()
+}
+
+#[test]
+fn test_search_ranks_and_highlights() {
+    let conn = test_db();
+    let hits = search(&conn, "lifetimes", &SearchFilter::default()).unwrap();
+    assert_eq!(hits.len(), 2);
+    // the answer mentions lifetimes twice, so it ranks first
+    assert_eq!(hits[0].message_id, 2);
+    assert_eq!(hits[0].chat_title.as_deref(), Some("rust"));
+    assert!(hits[0].snippet.contains("[Lifetimes]"));
+    assert!(hits[0].rank <= hits[1].rank);
+}
+
+#[test]
+fn test_phrase_boolean_and_stemming() {
+    let conn = test_db();
+    let all = SearchFilter::default();
+    assert_eq!(ids(&conn, "\"boil an egg\"", &all), vec![3]);
+    assert!(ids(&conn, "\"egg boil\"", &all).is_empty());
+    assert_eq!(ids(&conn, "rust NOT references", &all), vec![1]);
+    assert_eq!(ids(&conn, "egg OR yolk", &all).len(), 2);
+    // porter stemming: "boiling" finds "boil"
+    assert_eq!(ids(&conn, "boiling", &all).len(), 2);
+    // invalid syntax falls back to literal words
+    assert_eq!(ids(&conn, "egg?", &all), vec![3]);
+    assert_eq!(quote_terms("what's \"up\""), "\"what's\" \"\"\"up\"\"\"");
+}
+
+#[test]
+fn test_search_filters() {
+    let conn = test_db();
+    let role = SearchFilter { role: Some("user".into()), ..Default::default() };
+    let mut found = ids(&conn, "how", &role);
+    found.sort();
+    assert_eq!(found, vec![1, 3]);
+    assert!(search(&conn, "how", &role).unwrap().iter().all(|h| h.role == "user"));
+
+    let chat = SearchFilter { chat_id: Some(2), ..Default::default() };
+    assert_eq!(ids(&conn, "how", &chat), vec![3]);
+
+    let tagged = SearchFilter { tags: vec!["work".into()], ..Default::default() };
+    let mut found = ids(&conn, "how", &tagged);
+    found.sort();
+    assert_eq!(found, vec![1, 2]);
+
+    let future = SearchFilter { since: Some("2999-01-01".into()), ..Default::default() };
+    assert!(ids(&conn, "how", &future).is_empty());
+    let past = SearchFilter { until: Some("2000-01-01".into()), ..Default::default() };
+    assert!(ids(&conn, "how", &past).is_empty());
+
+    let limited = SearchFilter { limit: 1, ..Default::default() };
+    assert_eq!(ids(&conn, "how", &limited).len(), 1);
+    assert!(valid_date("2024-02-29"));
+    assert!(!valid_date("2024-2-29"));
+}
+
+#[test]
+fn test_index_follows_inserts_updates_and_deletes() {
+    let conn = test_db();
+    let all = SearchFilter::default();
+    delete_message(&conn, 4).unwrap();
+    assert!(ids(&conn, "yolk", &all).is_empty());
+
+    conn.execute("UPDATE messages SET content = 'Poach it instead' WHERE id = 3", []).unwrap();
+    assert!(ids(&conn, "boil", &all).is_empty());
+    assert_eq!(ids(&conn, "poach", &all), vec![3]);
+
+    conn.execute("DELETE FROM messages WHERE chat_id = 1", []).unwrap();
+    assert!(ids(&conn, "lifetimes", &all).is_empty());
+}
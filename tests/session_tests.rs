@@ -0,0 +1,72 @@
+use serial_test::serial;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use yo::session::{self, Scope, CHAT_VAR, SESSION_VAR};
+
+#[test]
+fn test_scope_names() {
+    assert_eq!(Scope::Env.to_string(), "$YO_CHAT");
+    assert_eq!(Scope::Project(PathBuf::from("/src/app")).to_string(), "project /src/app");
+    assert_eq!(Scope::Session("123-456".into()).to_string(), "terminal session 123-456");
+    assert_eq!(Scope::Global.to_string(), "global");
+}
+
+#[test]
+fn test_session_ids_must_be_file_names() {
+    assert!(session::valid_session_id("4242-1760000000"));
+    assert!(session::valid_session_id("tmux_pane.3"));
+    for bad in ["", "..", ".hidden", "a/b", "../current_chat", "a b"] {
+        assert!(!session::valid_session_id(bad), "{:?} should be rejected", bad);
+    }
+}
+
+#[test]
+#[serial]
+fn test_environment_variables() {
+    let saved: Vec<_> = [CHAT_VAR, SESSION_VAR].iter().map(|v| (*v, env::var(v).ok())).collect();
+    unsafe {
+        env::set_var(CHAT_VAR, " 12 ");
+        env::set_var(SESSION_VAR, "../escape");
+    }
+    assert_eq!(session::pinned_chat(), Some(12));
+    assert_eq!(session::session_id(), None);
+    unsafe {
+        env::set_var(CHAT_VAR, "latest");
+        env::set_var(SESSION_VAR, "777-1");
+    }
+    assert_eq!(session::pinned_chat(), None);
+    assert_eq!(session::session_id().as_deref(), Some("777-1"));
+    for (var, value) in saved {
+        match value {
+            Some(v) => unsafe { env::set_var(var, v) },
+            None => unsafe { env::remove_var(var) },
+        }
+    }
+}
+
+#[test]
+fn test_pointers_and_pruning() {
+    let dir = env::temp_dir().join(format!("yo_session_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let pointer = dir.join("sessions").join("tab");
+    assert_eq!(session::read_chat(&pointer), None);
+    session::write_chat(&pointer, 7).unwrap();
+    assert_eq!(session::read_chat(&pointer), Some(7));
+
+    assert_eq!(session::prune(&dir.join("sessions"), session::SESSION_TTL).unwrap(), 0);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(session::prune(&dir.join("sessions"), Duration::from_millis(10)).unwrap(), 1);
+    assert!(!pointer.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_shell_hooks() {
+    for shell in ["bash", "zsh", "fish"] {
+        assert!(session::hook(shell).unwrap().contains(SESSION_VAR));
+    }
+    assert!(session::hook("bash").unwrap().contains("export YO_SESSION="));
+    assert!(session::hook("powershell").is_none());
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::empty_db;
+use yo::branches::{active_path, append};
+use yo::chats;
+use yo::config::ModelSettings;
+use yo::embeddings;
+use yo::project;
+use yo::store::{ChatStore, ProfileEntry};
+use yo::tags::{add_tags, all_tags};
+
+fn test_store() -> ChatStore {
+    ChatStore::new(empty_db())
+}
+
+/// A chat with one question and answer
+fn chat(store: &ChatStore, title: &str) -> i64 {
+    let id = chats::create(store, title, None).unwrap();
+    let q = append(store, id, None, "user", "question").unwrap();
+    append(store, id, Some(q), "assistant", "answer").unwrap();
+    id
+}
+
+fn count(store: &ChatStore, table: &str) -> i64 {
+    store.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn test_chats_are_typed() {
+    let store = test_store();
+    let settings = ModelSettings { source: "ollama".into(), model: "llama3".into(), temperature: Some(0.2), max_tokens: None };
+    let first = chats::create(&store, "first", Some(&settings)).unwrap();
+    let second = chats::create(&store, "second", None).unwrap();
+
+    let all = store.chats().unwrap();
+    assert_eq!(all.iter().map(|c| c.id).collect::<Vec<_>>(), vec![second, first], "newest first");
+    let chat = store.chat(first).unwrap().unwrap();
+    assert_eq!(chat.title, "first");
+    assert_eq!(chat.settings, Some(settings));
+    assert_eq!(store.chat(second).unwrap().unwrap().settings, None);
+    assert!(store.chat(99).unwrap().is_none());
+}
+
+#[test]
+fn test_deleting_a_chat_cascades() {
+    let store = test_store();
+    let kept = chat(&store, "kept");
+    let gone = chat(&store, "gone");
+    add_tags(&store, gone, &["work".into()]).unwrap();
+    add_tags(&store, kept, &["home".into()]).unwrap();
+    project::bind(&store, std::path::Path::new("/tmp/project"), gone).unwrap();
+    let message = active_path(&store, gone).unwrap()[0].id;
+    embeddings::store(&store, message, "test-model", &[1.0]).unwrap();
+
+    assert_eq!(store.delete_chats(&[gone, 99]).unwrap(), 1);
+    assert_eq!(store.chats().unwrap().len(), 1);
+    assert_eq!(count(&store, "messages"), 2);
+    assert_eq!(count(&store, "embeddings"), 0);
+    assert_eq!(count(&store, "project_chats"), 0);
+    // the tag only the deleted chat had is gone too
+    assert_eq!(all_tags(&store).unwrap(), vec![("home".to_string(), 1)]);
+    assert_eq!(active_path(&store, kept).unwrap().len(), 2);
+}
+
+#[test]
+fn test_clear_all_keeps_the_profile() {
+    let store = test_store();
+    let id = chat(&store, "one");
+    chat(&store, "two");
+    add_tags(&store, id, &["work".into()]).unwrap();
+    store.set_profile("name", "Sam").unwrap();
+
+    store.clear_all().unwrap();
+    for table in ["chats", "messages", "tags", "chat_tags"] {
+        assert_eq!(count(&store, table), 0, "{} not cleared", table);
+    }
+    assert_eq!(store.profile().unwrap().len(), 1);
+}
+
+#[test]
+fn test_failed_clear_all_changes_nothing() {
+    let store = test_store();
+    let id = chat(&store, "one");
+    add_tags(&store, id, &["work".into()]).unwrap();
+    // fail the second statement, after the chats are already deleted
+    store.execute_batch("CREATE TRIGGER refuse BEFORE DELETE ON tags BEGIN SELECT RAISE(ABORT, 'no'); END;").unwrap();
+
+    assert!(store.clear_all().is_err());
+    assert_eq!(count(&store, "chats"), 1);
+    assert_eq!(count(&store, "messages"), 2);
+}
+
+#[test]
+fn test_clear_messages_keeps_the_chat() {
+    let store = test_store();
+    let id = chat(&store, "one");
+    assert_eq!(store.clear_messages(id).unwrap(), 2);
+    assert!(store.chat(id).unwrap().is_some());
+    assert!(active_path(&store, id).unwrap().is_empty());
+    // new messages start a fresh branch
+    append(&store, id, None, "user", "again").unwrap();
+    assert_eq!(active_path(&store, id).unwrap().len(), 1);
+}
+
+#[test]
+fn test_profile_upsert() {
+    let store = test_store();
+    store.set_profile("name", "Sam").unwrap();
+    store.set_profile("editor", "vim").unwrap();
+    store.set_profile("name", "Alex").unwrap();
+    assert_eq!(
+        store.profile().unwrap(),
+        vec![
+            ProfileEntry { key: "editor".into(), value: "vim".into() },
+            ProfileEntry { key: "name".into(), value: "Alex".into() },
+        ]
+    );
+}
@@ -0,0 +1,53 @@
+use rusqlite::Connection;
+use yo::tags::{add_tags, chats_with_tags, migrate_legacy_tags, normalize_tag, normalize_tags, remove_tags, tags_for_chat};
+
+fn test_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE chats (id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT, tags TEXT);
+        CREATE TABLE tags (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE chat_tags (chat_id INTEGER NOT NULL, tag_id INTEGER NOT NULL, PRIMARY KEY(chat_id, tag_id));
+        INSERT INTO chats (title) VALUES ('one'), ('two');
+        "#,
+    ).unwrap();
+    conn
+}
+
+#[test]
+fn test_normalize_tag() {
+    assert_eq!(normalize_tag(" Work Stuff "), Some("work-stuff".into()));
+    assert_eq!(normalize_tag("#Rust"), Some("rust".into()));
+    assert_eq!(normalize_tag(" - "), None);
+    assert_eq!(normalize_tags(&["a,b".into(), "A".into()]), vec!["a", "b"]);
+}
+
+#[test]
+fn test_tag_filters_require_all_tags() {
+    let conn = test_db();
+    add_tags(&conn, 1, &["work".into(), "rust".into()]).unwrap();
+    add_tags(&conn, 2, &["work".into()]).unwrap();
+    assert_eq!(chats_with_tags(&conn, &["work".into()]).unwrap(), vec![1, 2]);
+    assert_eq!(chats_with_tags(&conn, &["work".into(), "rust".into()]).unwrap(), vec![1]);
+    assert!(chats_with_tags(&conn, &["nope".into()]).unwrap().is_empty());
+}
+
+#[test]
+fn test_remove_tags_prunes_unused() {
+    let conn = test_db();
+    add_tags(&conn, 1, &["work".into()]).unwrap();
+    assert_eq!(remove_tags(&conn, 1, &["work".into()]).unwrap(), 1);
+    assert!(tags_for_chat(&conn, 1).unwrap().is_empty());
+    let left: i64 = conn.query_row("SELECT COUNT(*) FROM tags", [], |r| r.get(0)).unwrap();
+    assert_eq!(left, 0);
+}
+
+#[test]
+fn test_migrate_legacy_tags() {
+    let conn = test_db();
+    conn.execute("UPDATE chats SET tags = 'Work, rust ops;db' WHERE id = 1", []).unwrap();
+    migrate_legacy_tags(&conn).unwrap();
+    assert_eq!(tags_for_chat(&conn, 1).unwrap(), vec!["db", "rust-ops", "work"]);
+    let legacy: Option<String> = conn.query_row("SELECT tags FROM chats WHERE id = 1", [], |r| r.get(0)).unwrap();
+    assert!(legacy.is_none());
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::io;
+use yo::template::{parse_vars, placeholders, render, required_vars, Placeholder};
+
+fn no_files(path: &str) -> io::Result<String> {
+    Err(io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+}
+
+fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn test_render_fills_variables_and_defaults() {
+    let text = "Review this {{ lang | Rust }} code for {{focus}}:\n{{input}}";
+    let out = render(text, &vars(&[("focus", "bugs"), ("input", "fn main() {}")]), no_files).unwrap();
+    assert_eq!(out, "Review this Rust code for bugs:\nfn main() {}");
+
+    let out = render("{{lang|Rust}}", &vars(&[("lang", "Go")]), no_files).unwrap();
+    assert_eq!(out, "Go");
+}
+
+#[test]
+fn test_render_refuses_missing_required_variables() {
+    let err = render("{{a}} {{b|x}} {{c}} {{a}}", &vars(&[]), no_files).unwrap_err();
+    assert_eq!(err, "missing required variable(s): a, c");
+    assert_eq!(required_vars("{{a}} {{b|x}} {{c}} {{a}}"), vec!["a", "c"]);
+}
+
+#[test]
+fn test_render_includes_files_and_keeps_other_braces() {
+    let read = |p: &str| if p == "notes.txt" { Ok("NOTES".to_string()) } else { no_files(p) };
+    let out = render("see {{file:notes.txt}} and {{ not a var }} or {{", &vars(&[]), read).unwrap();
+    assert_eq!(out, "see NOTES and {{ not a var }} or {{");
+
+    let err = render("{{file:missing.txt}}", &vars(&[]), read).unwrap_err();
+    assert!(err.contains("missing.txt"));
+    assert_eq!(
+        placeholders("{{file:a.rs}} {{x|1}}"),
+        vec![Placeholder::File("a.rs".to_string()), Placeholder::Var { name: "x".to_string(), default: Some("1".to_string()) }]
+    );
+}
+
+#[test]
+fn test_parse_vars() {
+    let v = parse_vars(&["lang=Rust".to_string(), "q=a=b".to_string()]).unwrap();
+    assert_eq!(v["lang"], "Rust");
+    assert_eq!(v["q"], "a=b");
+    assert!(parse_vars(&["novalue".to_string()]).is_err());
+    assert!(parse_vars(&["=x".to_string()]).is_err());
+}